@@ -0,0 +1,88 @@
+use straal::Vec3n;
+
+use crate::error::{ModelLoadingError, SourceLocation};
+
+//Reads little endian values at absolute offsets, reads past the end are reported as errors
+pub(crate) struct BinaryReader<'b, 'p> {
+    pub(crate) bytes: &'b [u8],
+    pub(crate) file_path: &'p str,
+}
+
+impl<'b, 'p> BinaryReader<'b, 'p> {
+    pub(crate) fn invalid(&self, description: String) -> ModelLoadingError {
+        ModelLoadingError::InvalidData { location: SourceLocation::file(self.file_path), description }
+    }
+
+    pub(crate) fn bytes(&self, offset: usize, length: usize) -> Result<&'b [u8], ModelLoadingError> {
+        offset.checked_add(length).and_then(|end| self.bytes.get(offset..end))
+            .ok_or_else(|| self.invalid(format!("{} bytes at byte {} are past the end of the file", length, offset)))
+    }
+
+    pub(crate) fn u8(&self, offset: usize) -> Result<u8, ModelLoadingError> {
+        Ok(self.bytes(offset, 1)?[0])
+    }
+
+    pub(crate) fn u16(&self, offset: usize) -> Result<u16, ModelLoadingError> {
+        let b = self.bytes(offset, 2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn i16(&self, offset: usize) -> Result<i16, ModelLoadingError> {
+        let b = self.bytes(offset, 2)?;
+        Ok(i16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u32(&self, offset: usize) -> Result<u32, ModelLoadingError> {
+        let b = self.bytes(offset, 4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn u64(&self, offset: usize) -> Result<u64, ModelLoadingError> {
+        let b = self.bytes(offset, 8)?;
+        Ok(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    pub(crate) fn i32(&self, offset: usize) -> Result<i32, ModelLoadingError> {
+        let b = self.bytes(offset, 4)?;
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn f32(&self, offset: usize) -> Result<f32, ModelLoadingError> {
+        let b = self.bytes(offset, 4)?;
+        Ok(f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn vec3(&self, offset: usize) -> Result<Vec3n, ModelLoadingError> {
+        Ok(Vec3n::new(self.f32(offset)?, self.f32(offset + 4)?, self.f32(offset + 8)?))
+    }
+
+    //Counts and offsets are signed in the file, negative ones are corrupt
+    pub(crate) fn count(&self, offset: usize, what: &str) -> Result<usize, ModelLoadingError> {
+        let value = self.i32(offset)?;
+        if value < 0 {
+            return Err(self.invalid(format!("the {} is negative ({})", what, value)));
+        }
+        Ok(value as usize)
+    }
+
+    //A count of records that all have to fit in the file, so corrupt counts fail before anything is allocated for them
+    pub(crate) fn record_count(&self, offset: usize, record_size: usize, what: &str) -> Result<usize, ModelLoadingError> {
+        let count = self.count(offset, what)?;
+        if count.checked_mul(record_size).map_or(true, |size| size > self.bytes.len()) {
+            return Err(self.invalid(format!("the {} ({}) is more than the {} bytes of the file can hold", what, count, self.bytes.len())));
+        }
+        Ok(count)
+    }
+
+    //Fixed size and zero terminated
+    pub(crate) fn name(&self, offset: usize, size: usize) -> Result<String, ModelLoadingError> {
+        let bytes = self.bytes(offset, size)?;
+        let length = bytes.iter().position(|&b| b == 0).unwrap_or(size);
+        Ok(String::from_utf8_lossy(&bytes[..length]).into_owned())
+    }
+}
+
+//Quake models and IQM are Z up, the rotation (x, y, z) -> (x, z, -y) makes them Y up without mirroring them
+pub(crate) fn y_up(v: Vec3n) -> Vec3n {
+    Vec3n::new(v.x, v.z, -v.y)
+}
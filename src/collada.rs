@@ -0,0 +1,535 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs;
+use std::ops::Range;
+
+use roxmltree::{Document, Node};
+use straal::{Vec3n, Vec4n};
+
+use crate::error::{BYTES_SOURCE_NAME, ModelLoadingError, SourceLocation};
+use crate::models::*;
+use crate::mtl::{Material, TextureMap, TextureOptions};
+use crate::triangulation;
+
+//Row major, points are column vectors
+type Matrix = [[f32; 4]; 4];
+
+const IDENTITY: Matrix = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+
+//Primitives that make up a mesh, lines and splines are not loaded
+const POLYGON_PRIMITIVES: [&str; 5] = ["triangles", "polylist", "polygons", "trifans", "tristrips"];
+
+//A <source> with the layout of its accessor
+struct Source {
+    values: Vec<f32>,
+    stride: usize,
+    offset: usize,
+}
+
+//An <input> of a primitive, VERTEX inputs are replaced by the inputs of the <vertices> element they refer to
+struct Input<'a, 'input> {
+    semantic: &'a str,
+    source: Node<'a, 'input>,
+    offset: usize,
+    set: usize,
+}
+
+impl ObjModel {
+    //Loads every geometry instance of the visual scene of a COLLADA file as its own model, with the node transforms
+    //applied and the bound materials. Z up and X up files are turned to Y up.
+    //Files without a scene give one model per geometry
+    pub fn load_collada_from_file(file_path: &str) -> Result<Vec<ObjModel>, ModelLoadingError> {
+        let bytes = fs::read(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        ObjModel::parse_collada(&bytes, file_path)
+    }
+
+    pub fn from_collada_bytes(bytes: &[u8]) -> Result<Vec<ObjModel>, ModelLoadingError> {
+        ObjModel::parse_collada(bytes, BYTES_SOURCE_NAME)
+    }
+
+    fn parse_collada(bytes: &[u8], file_path: &str) -> Result<Vec<ObjModel>, ModelLoadingError> {
+        let source = String::from_utf8_lossy(bytes);
+        let document = Document::parse(&source).map_err(|e| {
+            let position = e.pos();
+            ModelLoadingError::Xml { location: SourceLocation::new(file_path, position.row as usize, position.col as usize), source: e }
+        })?;
+        Collada::new(&document, file_path).models()
+    }
+}
+
+struct Collada<'a, 'input> {
+    document: &'a Document<'input>,
+    file_path: &'a str,
+    //Every element with an id, the targets of url attributes
+    ids: HashMap<&'a str, Node<'a, 'input>>,
+}
+
+impl<'a, 'input: 'a> Collada<'a, 'input> {
+    fn new(document: &'a Document<'input>, file_path: &'a str) -> Collada<'a, 'input> {
+        let ids = document.root().descendants()
+            .filter_map(|node| node.attribute("id").map(|id| (id, node)))
+            .collect();
+        Collada { document, file_path, ids }
+    }
+
+    fn location(&self, node: Node) -> SourceLocation {
+        let position = self.document.text_pos_at(node.range().start);
+        SourceLocation::new(self.file_path, position.row as usize, position.col as usize)
+    }
+
+    fn invalid(&self, node: Node, description: String) -> ModelLoadingError {
+        ModelLoadingError::InvalidData { location: self.location(node), description }
+    }
+
+    //Follows a url attribute such as "#mesh-geometry" of the given node
+    fn reference(&self, node: Node<'a, 'input>, attribute: &str) -> Result<Node<'a, 'input>, ModelLoadingError> {
+        let url = node.attribute(attribute)
+            .ok_or_else(|| self.invalid(node, format!("<{}> has no {} attribute", node.tag_name().name(), attribute)))?;
+        self.ids.get(url.trim_start_matches('#')).copied()
+            .ok_or_else(|| self.invalid(node, format!("{} refers to {}, which does not exist", attribute, url)))
+    }
+
+    fn floats(&self, node: Node) -> Result<Vec<f32>, ModelLoadingError> {
+        node.text().unwrap_or("").split_whitespace().map(|text| text.parse::<f32>().map_err(|e| ModelLoadingError::ParseFloat {
+            location: self.location(node),
+            text: text.to_string(),
+            source: e,
+        })).collect()
+    }
+
+    fn integers(&self, node: Node) -> Result<Vec<usize>, ModelLoadingError> {
+        node.text().unwrap_or("").split_whitespace().map(|text| text.parse::<usize>().map_err(|e| ModelLoadingError::ParseIndex {
+            location: self.location(node),
+            text: text.to_string(),
+            source: e,
+        })).collect()
+    }
+
+    fn models(&self) -> Result<Vec<ObjModel>, ModelLoadingError> {
+        let root = self.document.root_element();
+        let up_axis = match descendant(root, "up_axis").and_then(|n| n.text()).map(str::trim) {
+            //(x, y, z) to (x, z, -y) and (x, y, z) to (-y, x, z)
+            Some("Z_UP") => [[1.0, 0.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, -1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]],
+            Some("X_UP") => [[0.0, -1.0, 0.0, 0.0], [1.0, 0.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]],
+            _ => IDENTITY,
+        };
+
+        let visual_scene = match child(root, "scene").and_then(|scene| child(scene, "instance_visual_scene")) {
+            Some(instance) => Some(self.reference(instance, "url")?),
+            None => child(root, "library_visual_scenes").and_then(|library| child(library, "visual_scene")),
+        };
+
+        let mut models = Vec::new();
+        match visual_scene {
+            Some(scene) => {
+                for node in children(scene, "node") {
+                    self.visit(node, &up_axis, &mut Vec::new(), &mut models)?;
+                }
+            }
+            None => {
+                for geometry in child(root, "library_geometries").into_iter().flat_map(|library| children(library, "geometry")) {
+                    let name = geometry.attribute("name").or_else(|| geometry.attribute("id")).unwrap_or("mesh");
+                    models.extend(self.mesh(geometry, &up_axis, &HashMap::new(), name)?);
+                }
+            }
+        }
+        Ok(models)
+    }
+
+    //Walks the node hierarchy, instances of geometries and controllers become models.
+    //The nodes being visited are kept by their range in the file, an instance of one of them would never end
+    fn visit(&self, node: Node<'a, 'input>, parent: &Matrix, visiting: &mut Vec<Range<usize>>, models: &mut Vec<ObjModel>) -> Result<(), ModelLoadingError> {
+        visiting.push(node.range());
+        let transform = multiply(parent, &self.local_transform(node)?);
+        let name = node.attribute("name").or_else(|| node.attribute("id")).unwrap_or("node");
+        for element in node.children().filter(|c| c.is_element()) {
+            match element.tag_name().name() {
+                "instance_geometry" => {
+                    let geometry = self.reference(element, "url")?;
+                    models.extend(self.mesh(geometry, &transform, &self.material_bindings(element)?, name)?);
+                }
+                //Skinned meshes are loaded in their bind pose
+                "instance_controller" => {
+                    let controller = self.reference(element, "url")?;
+                    if let Some(skin) = child(controller, "skin").or_else(|| child(controller, "morph")) {
+                        let geometry = self.reference(skin, "source")?;
+                        models.extend(self.mesh(geometry, &transform, &self.material_bindings(element)?, name)?);
+                    }
+                }
+                "node" => self.visit(element, &transform, visiting, models)?,
+                "instance_node" => {
+                    let instanced = self.reference(element, "url")?;
+                    if visiting.contains(&instanced.range()) {
+                        return Err(self.invalid(element, "<instance_node> refers to a node that contains it".to_string()));
+                    }
+                    self.visit(instanced, &transform, visiting, models)?;
+                }
+                _ => {}
+            }
+        }
+        visiting.pop();
+        Ok(())
+    }
+
+    //The transform elements of a node are applied in the order they are written
+    fn local_transform(&self, node: Node) -> Result<Matrix, ModelLoadingError> {
+        let mut transform = IDENTITY;
+        for element in node.children().filter(|c| c.is_element()) {
+            let name = element.tag_name().name();
+            let required = match name {
+                "matrix" => 16,
+                "rotate" => 4,
+                "translate" | "scale" => 3,
+                _ => continue,
+            };
+            let v = self.floats(element)?;
+            if v.len() < required {
+                return Err(self.invalid(element, format!("<{}> needs {} numbers but has {}", name, required, v.len())));
+            }
+            let local = match name {
+                "matrix" => [[v[0], v[1], v[2], v[3]], [v[4], v[5], v[6], v[7]], [v[8], v[9], v[10], v[11]], [v[12], v[13], v[14], v[15]]],
+                "translate" => [[1.0, 0.0, 0.0, v[0]], [0.0, 1.0, 0.0, v[1]], [0.0, 0.0, 1.0, v[2]], [0.0, 0.0, 0.0, 1.0]],
+                "scale" => [[v[0], 0.0, 0.0, 0.0], [0.0, v[1], 0.0, 0.0], [0.0, 0.0, v[2], 0.0], [0.0, 0.0, 0.0, 1.0]],
+                _ => rotation(Vec3n::new(v[0], v[1], v[2]), v[3].to_radians()),
+            };
+            transform = multiply(&transform, &local);
+        }
+        Ok(transform)
+    }
+
+    //Material symbols used by the primitives and the materials they are bound to
+    fn material_bindings(&self, instance: Node<'a, 'input>) -> Result<HashMap<&'a str, Node<'a, 'input>>, ModelLoadingError> {
+        let mut bindings = HashMap::new();
+        for binding in instance.descendants().filter(|n| n.has_tag_name("instance_material")) {
+            if let Some(symbol) = binding.attribute("symbol") {
+                bindings.insert(symbol, self.reference(binding, "target")?);
+            }
+        }
+        Ok(bindings)
+    }
+
+    fn source(&self, node: Node) -> Result<Source, ModelLoadingError> {
+        let values = match child(node, "float_array") {
+            Some(array) => self.floats(array)?,
+            None => return Err(self.invalid(node, "the source has no float_array".to_string())),
+        };
+        let accessor = child(node, "technique_common").and_then(|technique| child(technique, "accessor"));
+        let attribute = |name: &str, default: usize| accessor.and_then(|a| a.attribute(name)).and_then(|v| v.parse().ok()).unwrap_or(default);
+        Ok(Source { values, stride: attribute("stride", 1).max(1), offset: attribute("offset", 0) })
+    }
+
+    //The inputs of a primitive, with VERTEX expanded into the inputs of <vertices>
+    fn inputs(&self, primitive: Node<'a, 'input>) -> Result<Vec<Input<'a, 'input>>, ModelLoadingError> {
+        let mut inputs = Vec::new();
+        for input in children(primitive, "input") {
+            let semantic = input.attribute("semantic").unwrap_or("");
+            let offset = input.attribute("offset").and_then(|v| v.parse().ok()).unwrap_or(0);
+            let set = input.attribute("set").and_then(|v| v.parse().ok()).unwrap_or(0);
+            let source = self.reference(input, "source")?;
+            if semantic == "VERTEX" {
+                for vertex_input in children(source, "input") {
+                    let semantic = vertex_input.attribute("semantic").unwrap_or("");
+                    inputs.push(Input { semantic, source: self.reference(vertex_input, "source")?, offset, set: 0 });
+                }
+            } else {
+                inputs.push(Input { semantic, source, offset, set });
+            }
+        }
+        Ok(inputs)
+    }
+
+    //Corners of every polygon of a primitive, as the start of their indices in the index list
+    fn polygons(&self, primitive: Node, indices: &mut Vec<usize>, stride: usize) -> Result<Vec<Vec<usize>>, ModelLoadingError> {
+        let name = primitive.tag_name().name();
+        let mut polygons = Vec::new();
+        //Each <p> of polygons, trifans and tristrips holds one polygon, triangles and polylist have a single <p>
+        for p in primitive.children().filter(|c| c.has_tag_name("p") || c.has_tag_name("ph")) {
+            let p = if p.has_tag_name("ph") { child(p, "p").unwrap_or(p) } else { p };
+            let start = indices.len() / stride;
+            indices.extend(self.integers(p)?);
+            let corners: Vec<usize> = (start..indices.len() / stride).collect();
+            match name {
+                "polygons" => polygons.push(corners),
+                "trifans" => polygons.extend((1..corners.len().saturating_sub(1)).map(|i| vec![corners[0], corners[i], corners[i + 1]])),
+                //Every other triangle of a strip is flipped to keep the winding
+                "tristrips" => polygons.extend((0..corners.len().saturating_sub(2)).map(|i| if i % 2 == 0 {
+                    vec![corners[i], corners[i + 1], corners[i + 2]]
+                } else {
+                    vec![corners[i + 1], corners[i], corners[i + 2]]
+                })),
+                _ => {}
+            }
+        }
+
+        let corner_count = indices.len() / stride;
+        match name {
+            "triangles" => polygons.extend((0..corner_count / 3).map(|t| vec![t * 3, t * 3 + 1, t * 3 + 2])),
+            "polylist" => {
+                let counts = match child(primitive, "vcount") {
+                    Some(vcount) => self.integers(vcount)?,
+                    None => Vec::new(),
+                };
+                let mut start = 0;
+                for count in counts {
+                    if start + count > corner_count {
+                        return Err(self.invalid(primitive, format!("vcount lists more corners than the {} in <p>", corner_count)));
+                    }
+                    polygons.push((start..start + count).collect());
+                    start += count;
+                }
+            }
+            _ => {}
+        }
+        Ok(polygons)
+    }
+
+    fn mesh(&self, geometry: Node<'a, 'input>, transform: &Matrix, bindings: &HashMap<&'a str, Node<'a, 'input>>, name: &str)
+            -> Result<Option<ObjModel>, ModelLoadingError> {
+        let mesh = match child(geometry, "mesh") {
+            Some(mesh) => mesh,
+            None => return Ok(None),
+        };
+        let normal_transform = cofactor(transform);
+        let mirrored = determinant(transform) < 0.0;
+
+        let primitives: Vec<Node> = mesh.children().filter(|c| POLYGON_PRIMITIVES.contains(&c.tag_name().name())).collect();
+        let mut primitive_inputs = Vec::with_capacity(primitives.len());
+        for &primitive in &primitives {
+            primitive_inputs.push(self.inputs(primitive)?);
+        }
+        //Attributes only some primitives have get default values for the others
+        let has = |semantic: &str| primitive_inputs.iter().flatten().any(|input: &Input| input.semantic == semantic);
+        let (has_normals, has_tex_coords, has_colors) = (has("NORMAL"), has("TEXCOORD"), has("COLOR"));
+
+        //Elements are keyed by where they start in the file
+        let mut sources: HashMap<usize, Source> = HashMap::new();
+        let mut model = ObjModel::empty();
+        let mut material_indices: HashMap<usize, usize> = HashMap::new();
+        let mut mapped_vertices: HashMap<Vec<usize>, u32> = HashMap::new();
+        for (primitive, inputs) in primitives.into_iter().zip(primitive_inputs) {
+            let pick = |semantic: &str| inputs.iter().filter(|i| i.semantic == semantic).min_by_key(|i| i.set);
+            let (position, normal, tex_coord, color) = (pick("POSITION"), pick("NORMAL"), pick("TEXCOORD"), pick("COLOR"));
+            let position = position.ok_or_else(|| self.invalid(primitive, "the primitive has no positions".to_string()))?;
+            for input in [Some(position), normal, tex_coord, color].iter().flatten() {
+                if let Entry::Vacant(entry) = sources.entry(input.source.range().start) {
+                    entry.insert(self.source(input.source)?);
+                }
+            }
+
+            //Positions and normals need all three components, missing texture coordinates and color channels get defaults
+            for input in [Some(position), normal].iter().flatten() {
+                let components = sources[&input.source.range().start].stride;
+                if components < 3 {
+                    return Err(self.invalid(primitive, format!("the {} source has {} components per element, 3 are needed", input.semantic.to_lowercase(), components)));
+                }
+            }
+
+            let stride = inputs.iter().map(|i| i.offset + 1).max().unwrap_or(1);
+            let mut indices = Vec::new();
+            let polygons = self.polygons(primitive, &mut indices, stride)?;
+
+            let material = primitive.attribute("material").and_then(|symbol| bindings.get(symbol).or_else(|| self.ids.get(symbol)));
+            let material = material.map(|&node| *material_indices.entry(node.range().start).or_insert_with(|| {
+                model.materials.push(self.material(node));
+                model.materials.len() - 1
+            }));
+            let start = model.indices.len();
+
+            //Values of an input for a corner, checked against the size of the source
+            let value = |input: &Input, corner: usize, components: usize| -> Result<Vec<f32>, ModelLoadingError> {
+                let source = &sources[&input.source.range().start];
+                let element = indices[corner * stride + input.offset];
+                let values = element.checked_mul(source.stride)
+                    .and_then(|at| at.checked_add(source.offset))
+                    .and_then(|at| source.values.get(at..at.checked_add(components.min(source.stride))?));
+                match values {
+                    Some(values) => Ok(values.to_vec()),
+                    None => Err(self.invalid(primitive, format!("index {} is out of range for the {} source", element, input.semantic.to_lowercase()))),
+                }
+            };
+
+            for polygon in polygons {
+                let mut corner_vertices = Vec::with_capacity(polygon.len());
+                let mut corner_positions = Vec::with_capacity(polygon.len());
+                for &corner in &polygon {
+                    //Primitives can lay out their indices differently and read other sources, so corners are
+                    //the same vertex only when they take the same elements of the same sources
+                    let key = [Some(position), normal, tex_coord, color].iter().flat_map(|input| match input {
+                        Some(input) => [input.source.range().start, indices[corner * stride + input.offset]],
+                        None => [usize::MAX, 0],
+                    }).collect::<Vec<usize>>();
+                    let p = value(position, corner, 3)?;
+                    let point = transform_point(transform, Vec3n::new(p[0], p[1], p[2]));
+                    corner_positions.push(point);
+                    if let Some(&index) = mapped_vertices.get(&key) {
+                        corner_vertices.push(index);
+                        continue;
+                    }
+
+                    model.vertices.push(Vertex { position: point });
+                    if has_normals {
+                        let n = match normal { Some(input) => value(input, corner, 3)?, None => vec![0.0; 3] };
+                        let n = transform_direction(&normal_transform, Vec3n::new(n[0], n[1], n[2]));
+                        model.normals.push(Normal { normal: ObjModel::safe_normalized(n) });
+                    }
+                    if has_tex_coords {
+                        let uv = match tex_coord { Some(input) => value(input, corner, 2)?, None => vec![0.0; 2] };
+                        model.tex_coords.push(UV { tex_coords: straal::Vec2 { x: uv[0], y: uv.get(1).copied().unwrap_or(0.0) } });
+                    }
+                    if has_colors {
+                        let c = match color { Some(input) => value(input, corner, 4)?, None => vec![1.0; 4] };
+                        let channel = |i: usize| c.get(i).copied().unwrap_or(1.0);
+                        model.colors.push(Color { color: Vec4n::new(channel(0), channel(1), channel(2), channel(3)) });
+                    }
+                    let index = (model.vertices.len() - 1) as u32;
+                    mapped_vertices.insert(key, index);
+                    corner_vertices.push(index);
+                }
+
+                for triangle in triangulation::triangulate(&corner_positions) {
+                    let triangle = if mirrored { [triangle[0], triangle[2], triangle[1]] } else { triangle };
+                    model.indices.extend(triangle.iter().map(|&corner| corner_vertices[corner]));
+                }
+            }
+
+            model.sub_meshes.push(SubMesh {
+                index_range: start..model.indices.len(),
+                material_name: material.map(|m| model.materials[m].name.clone()),
+                material,
+            });
+        }
+
+        let mut object = NamedPart { name: name.to_string(), index_ranges: Vec::new() };
+        object.extend(0..model.indices.len());
+        model.objects.push(object);
+        Ok(Some(model))
+    }
+
+    //Converts the common profile of the effect of a material, the shading model itself is not kept
+    fn material(&self, node: Node<'a, 'input>) -> Material {
+        let name = node.attribute("name").or_else(|| node.attribute("id")).unwrap_or("material");
+        let mut material = Material { name: name.to_string(), ..Default::default() };
+        let effect = match child(node, "instance_effect").and_then(|instance| self.reference(instance, "url").ok()) {
+            Some(effect) => effect,
+            None => return material,
+        };
+        let technique = descendant(effect, "profile_COMMON")
+            .and_then(|profile| child(profile, "technique"))
+            .and_then(|technique| technique.children().find(|c| c.is_element() && c.tag_name().name() != "extra"));
+        let technique = match technique {
+            Some(technique) => technique,
+            None => return material,
+        };
+
+        let mut transparent = false;
+        for parameter in technique.children().filter(|c| c.is_element()) {
+            let color = child(parameter, "color").and_then(|c| self.floats(c).ok()).filter(|c| c.len() >= 3).map(|c| Vec3n::new(c[0], c[1], c[2]));
+            let number = child(parameter, "float").and_then(|f| f.text()).and_then(|text| text.trim().parse::<f32>().ok());
+            let map = child(parameter, "texture").and_then(|texture| self.texture_path(effect, texture.attribute("texture")?))
+                .map(|path| TextureMap { path, options: TextureOptions::default() });
+            match parameter.tag_name().name() {
+                "emission" => { material.emissive = color; material.emissive_map = map; }
+                "ambient" => { material.ambient = color; material.ambient_map = map; }
+                "diffuse" => { material.diffuse = color; material.diffuse_map = map; }
+                "specular" => { material.specular = color; material.specular_map = map; }
+                "shininess" => material.shininess = number,
+                "transparent" => transparent = true,
+                "transparency" => material.dissolve = number,
+                "index_of_refraction" => material.optical_density = number,
+                _ => {}
+            }
+        }
+        //Plenty of exporters write a transparency of 0 for opaque materials without a transparent color
+        if !transparent {
+            material.dissolve = None;
+        }
+        material
+    }
+
+    //Textures name a sampler, which names a surface, which names an image. Many exporters name the image directly
+    fn texture_path(&self, effect: Node<'a, 'input>, sampler: &str) -> Option<String> {
+        let parameter = |sid: &str| effect.descendants().find(|n| n.has_tag_name("newparam") && n.attribute("sid") == Some(sid));
+        let image_id = match parameter(sampler).and_then(|p| child(p, "sampler2D")) {
+            Some(sampler) => match child(sampler, "source").and_then(|s| s.text()) {
+                Some(surface) => parameter(surface.trim())
+                    .and_then(|p| descendant(p, "init_from"))
+                    .and_then(|init| init.text())?
+                    .trim(),
+                //1.5 refers to the image from the sampler
+                None => child(sampler, "instance_image")?.attribute("url")?.trim_start_matches('#'),
+            },
+            None => sampler,
+        };
+        let image = self.ids.get(image_id)?;
+        let init_from = child(*image, "init_from")?;
+        let path = child(init_from, "ref").unwrap_or(init_from).text()?.trim();
+        Some(path.trim_start_matches("file://").to_string())
+    }
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.tag_name().name() == name)
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item=Node<'a, 'input>> + 'a {
+    node.children().filter(move |c| c.tag_name().name() == name)
+}
+
+fn descendant<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.descendants().find(|c| c.tag_name().name() == name)
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (row, result_row) in result.iter_mut().enumerate() {
+        for (column, value) in result_row.iter_mut().enumerate() {
+            *value = (0..4).map(|i| a[row][i] * b[i][column]).sum();
+        }
+    }
+    result
+}
+
+//Rotation around a unit axis by an angle in radians
+fn rotation(axis: Vec3n, angle: f32) -> Matrix {
+    let axis = ObjModel::safe_normalized(axis);
+    let (x, y, z) = (axis.x, axis.y, axis.z);
+    let (sin, cos) = angle.sin_cos();
+    let t = 1.0 - cos;
+    [[t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y, 0.0],
+        [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x, 0.0],
+        [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0]]
+}
+
+fn transform_point(m: &Matrix, p: Vec3n) -> Vec3n {
+    Vec3n::new(m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+               m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+               m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3])
+}
+
+//Directions ignore the translation
+fn transform_direction(m: &Matrix, d: Vec3n) -> Vec3n {
+    Vec3n::new(m[0][0] * d.x + m[0][1] * d.y + m[0][2] * d.z,
+               m[1][0] * d.x + m[1][1] * d.y + m[1][2] * d.z,
+               m[2][0] * d.x + m[2][1] * d.y + m[2][2] * d.z)
+}
+
+//The cofactor matrix of the rotation and scale part is the inverse transpose scaled by the determinant,
+//which is all normals need since they are normalized afterwards
+fn cofactor(m: &Matrix) -> Matrix {
+    let c = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let sign = if determinant(m) < 0.0 { -1.0 } else { 1.0 };
+    [[sign * c(1, 2, 1, 2), -sign * c(1, 2, 0, 2), sign * c(1, 2, 0, 1), 0.0],
+        [-sign * c(0, 2, 1, 2), sign * c(0, 2, 0, 2), -sign * c(0, 2, 0, 1), 0.0],
+        [sign * c(0, 1, 1, 2), -sign * c(0, 1, 0, 2), sign * c(0, 1, 0, 1), 0.0],
+        [0.0, 0.0, 0.0, 1.0]]
+}
+
+fn determinant(m: &Matrix) -> f32 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
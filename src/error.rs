@@ -0,0 +1,133 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::num::{ParseFloatError, ParseIntError};
+
+//Names used in errors for models that do not come from a file
+pub(crate) const BYTES_SOURCE_NAME: &str = "<bytes>";
+pub(crate) const READER_SOURCE_NAME: &str = "<reader>";
+
+//Where in the source something went wrong, lines and columns start at 1, 0 means unknown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file_path: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceLocation {
+    pub fn new(file_path: &str, line: usize, column: usize) -> SourceLocation {
+        SourceLocation { file_path: file_path.to_string(), line, column }
+    }
+
+    pub fn file(file_path: &str) -> SourceLocation {
+        SourceLocation::new(file_path, 0, 0)
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.line, self.column) {
+            (0, _) => write!(f, "{}", self.file_path),
+            (line, 0) => write!(f, "{}:{}", self.file_path, line),
+            (line, column) => write!(f, "{}:{}:{}", self.file_path, line, column),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ModelLoadingError {
+    //The file could not be opened or read
+    Io { location: SourceLocation, source: io::Error },
+    //A coordinate or other real number could not be parsed
+    ParseFloat { location: SourceLocation, text: String, source: ParseFloatError },
+    //A face index could not be parsed
+    ParseIndex { location: SourceLocation, text: String, source: ParseIntError },
+    //A statement has fewer components than it requires, text holds the whole line
+    MissingComponents { location: SourceLocation, text: String, expected: usize, found: usize },
+    //A face refers to an element that does not exist
+    IndexOutOfRange { location: SourceLocation, text: String, index: i64, count: usize },
+    //A value is not one of the accepted options, expected describes what would have been valid
+    InvalidValue { location: SourceLocation, text: String, expected: &'static str },
+    //A face has too few corners or mixes different index layouts
+    UnsupportedFaceLayout { location: SourceLocation, text: String },
+    //The statement keyword is not known to the loader
+    UnsupportedStatement { location: SourceLocation, text: String },
+    //A JSON based file (glTF) is not valid JSON
+    Json { location: SourceLocation, source: serde_json::Error },
+    //An XML based file (COLLADA) is not well formed XML
+    Xml { location: SourceLocation, source: roxmltree::Error },
+    //The file is well formed but its contents do not make sense, for formats without lines to point at
+    InvalidData { location: SourceLocation, description: String },
+}
+
+impl ModelLoadingError {
+    pub fn location(&self) -> &SourceLocation {
+        match self {
+            ModelLoadingError::Io { location, .. } |
+            ModelLoadingError::ParseFloat { location, .. } |
+            ModelLoadingError::ParseIndex { location, .. } |
+            ModelLoadingError::MissingComponents { location, .. } |
+            ModelLoadingError::IndexOutOfRange { location, .. } |
+            ModelLoadingError::InvalidValue { location, .. } |
+            ModelLoadingError::UnsupportedFaceLayout { location, .. } |
+            ModelLoadingError::UnsupportedStatement { location, .. } |
+            ModelLoadingError::Json { location, .. } |
+            ModelLoadingError::Xml { location, .. } |
+            ModelLoadingError::InvalidData { location, .. } => location
+        }
+    }
+}
+
+impl fmt::Display for ModelLoadingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelLoadingError::Io { location, source } => {
+                write!(f, "{}: could not read the file: {}", location, source)
+            }
+            ModelLoadingError::ParseFloat { location, text, source } => {
+                write!(f, "{}: could not parse \"{}\" as a number: {}", location, text, source)
+            }
+            ModelLoadingError::ParseIndex { location, text, source } => {
+                write!(f, "{}: could not parse \"{}\" as an index: {}", location, text, source)
+            }
+            ModelLoadingError::MissingComponents { location, text, expected, found } => {
+                write!(f, "{}: expected {} components but found {} in \"{}\"", location, expected, found, text)
+            }
+            ModelLoadingError::IndexOutOfRange { location, text, index, count } => {
+                write!(f, "{}: index {} in \"{}\" is out of range, only {} elements are defined", location, index, text, count)
+            }
+            ModelLoadingError::InvalidValue { location, text, expected } => {
+                write!(f, "{}: invalid value \"{}\", expected {}", location, text, expected)
+            }
+            ModelLoadingError::UnsupportedFaceLayout { location, text } => {
+                write!(f, "{}: unsupported face layout \"{}\"", location, text)
+            }
+            ModelLoadingError::UnsupportedStatement { location, text } => {
+                write!(f, "{}: unsupported statement \"{}\"", location, text)
+            }
+            ModelLoadingError::Json { location, source } => {
+                write!(f, "{}: invalid JSON: {}", location, source)
+            }
+            ModelLoadingError::Xml { location, source } => {
+                write!(f, "{}: invalid XML: {}", location, source)
+            }
+            ModelLoadingError::InvalidData { location, description } => {
+                write!(f, "{}: {}", location, description)
+            }
+        }
+    }
+}
+
+impl error::Error for ModelLoadingError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ModelLoadingError::Io { source, .. } => Some(source),
+            ModelLoadingError::ParseFloat { source, .. } => Some(source),
+            ModelLoadingError::ParseIndex { source, .. } => Some(source),
+            ModelLoadingError::Json { source, .. } => Some(source),
+            ModelLoadingError::Xml { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,757 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+use straal::{Vec3n, Vec4n};
+
+use crate::binary::BinaryReader;
+use crate::error::{BYTES_SOURCE_NAME, ModelLoadingError, SourceLocation};
+use crate::models::*;
+use crate::mtl::{Material, TextureMap, TextureOptions};
+use crate::triangulation;
+
+const BINARY_MAGIC: &[u8] = b"Kaydara FBX Binary  \0";
+const BINARY_HEADER_SIZE: usize = 27;
+//From 7.5 on node records use 64 bit offsets
+const LARGE_RECORD_VERSION: u32 = 7500;
+//Real files nest a handful of levels, the limit keeps malformed ones from exhausting the stack
+const MAX_NODE_DEPTH: usize = 64;
+
+#[derive(Debug, Clone)]
+enum Property {
+    Integer(i64),
+    Float(f64),
+    String(String),
+    //Raw binary data, embedded textures and the like are not needed for meshes
+    Raw,
+    IntegerArray(Vec<i64>),
+    FloatArray(Vec<f64>),
+}
+
+//The binary and the ASCII format both describe a tree of named nodes with a list of properties each
+#[derive(Debug, Default)]
+struct Node {
+    name: String,
+    properties: Vec<Property>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children_named<'n>(&'n self, name: &'n str) -> impl Iterator<Item=&'n Node> + 'n {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    fn integer(&self, index: usize) -> Option<i64> {
+        match self.properties.get(index) {
+            Some(Property::Integer(value)) => Some(*value),
+            Some(Property::Float(value)) => Some(*value as i64),
+            _ => None,
+        }
+    }
+
+    fn float(&self, index: usize) -> Option<f64> {
+        match self.properties.get(index) {
+            Some(Property::Integer(value)) => Some(*value as f64),
+            Some(Property::Float(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn string(&self, index: usize) -> Option<&str> {
+        match self.properties.get(index) {
+            Some(Property::String(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    //Object names are written as "Class::Name", only the name part is of interest
+    fn object_name(&self) -> Option<&str> {
+        self.string(1).map(|name| name.splitn(2, "::").last().unwrap_or(name))
+    }
+
+    //The values of an array, old ASCII files list them as plain properties instead
+    fn floats(&self) -> Vec<f64> {
+        match self.properties.first() {
+            Some(Property::FloatArray(values)) => values.clone(),
+            Some(Property::IntegerArray(values)) => values.iter().map(|&v| v as f64).collect(),
+            _ => (0..self.properties.len()).filter_map(|i| self.float(i)).collect(),
+        }
+    }
+
+    fn integers(&self) -> Vec<i64> {
+        match self.properties.first() {
+            Some(Property::IntegerArray(values)) => values.clone(),
+            Some(Property::FloatArray(values)) => values.iter().map(|&v| v as i64).collect(),
+            _ => (0..self.properties.len()).filter_map(|i| self.integer(i)).collect(),
+        }
+    }
+}
+
+impl ObjModel {
+    //Loads every mesh of a binary or ASCII FBX 7.x file, polygons are split into sub-meshes by material.
+    //Meshes keep their own coordinates, model transforms are not applied
+    pub fn load_fbx_from_file(file_path: &str) -> Result<Vec<ObjModel>, ModelLoadingError> {
+        let bytes = fs::read(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        ObjModel::parse_fbx(&bytes, file_path)
+    }
+
+    pub fn from_fbx_bytes(bytes: &[u8]) -> Result<Vec<ObjModel>, ModelLoadingError> {
+        ObjModel::parse_fbx(bytes, BYTES_SOURCE_NAME)
+    }
+
+    fn parse_fbx(bytes: &[u8], file_path: &str) -> Result<Vec<ObjModel>, ModelLoadingError> {
+        let root = if bytes.starts_with(BINARY_MAGIC) {
+            BinaryFbxReader::parse(bytes, file_path)?
+        } else {
+            AsciiReader::parse(&String::from_utf8_lossy(bytes), file_path)?
+        };
+        Scene::new(&root, file_path).meshes()
+    }
+}
+
+struct BinaryFbxReader<'b> {
+    reader: BinaryReader<'b, 'b>,
+    large_records: bool,
+}
+
+impl<'b> BinaryFbxReader<'b> {
+    fn parse(bytes: &'b [u8], file_path: &'b str) -> Result<Node, ModelLoadingError> {
+        let mut reader = BinaryFbxReader { reader: BinaryReader { bytes, file_path }, large_records: false };
+        let version = reader.reader.u32(23)?;
+        if !(7000..8000).contains(&version) {
+            return Err(reader.reader.invalid(format!("FBX version {} is not supported, only 7.x is", version)));
+        }
+        reader.large_records = version >= LARGE_RECORD_VERSION;
+
+        let mut root = Node::default();
+        let mut at = BINARY_HEADER_SIZE;
+        //The top level list ends with a null record, followed by a footer
+        while let (Some(node), next) = reader.node(at, 0)? {
+            root.children.push(node);
+            at = next;
+        }
+        Ok(root)
+    }
+
+    //Offsets and counts at the start of a node record, 32 bit before 7.5 and 64 bit after
+    fn record_value(&self, at: usize, index: usize) -> Result<usize, ModelLoadingError> {
+        if self.large_records {
+            self.reader.u64(at + index * 8).map(|v| v as usize)
+        } else {
+            self.reader.u32(at + index * 4).map(|v| v as usize)
+        }
+    }
+
+    //Reads the node record at the given offset, None for the null record that ends a list.
+    //Also returns the offset right after the record
+    fn node(&self, at: usize, depth: usize) -> Result<(Option<Node>, usize), ModelLoadingError> {
+        if depth > MAX_NODE_DEPTH {
+            return Err(self.reader.invalid(format!("the node record at byte {} is nested deeper than {} levels", at, MAX_NODE_DEPTH)));
+        }
+        let header_size = if self.large_records { 24 } else { 12 };
+        let end_offset = self.record_value(at, 0)?;
+        let property_count = self.record_value(at, 1)?;
+        let property_list_length = self.record_value(at, 2)?;
+        let name_length = self.reader.bytes(at + header_size, 1)?[0] as usize;
+        if end_offset == 0 {
+            return Ok((None, at + header_size + 1));
+        }
+        if end_offset <= at || end_offset > self.reader.bytes.len() {
+            return Err(self.reader.invalid(format!("the node record at byte {} ends at byte {}, outside of the file", at, end_offset)));
+        }
+
+        let name_start = at + header_size + 1;
+        let mut node = Node {
+            name: String::from_utf8_lossy(self.reader.bytes(name_start, name_length)?).into_owned(),
+            properties: Vec::new(),
+            children: Vec::new(),
+        };
+        let mut property_at = name_start + name_length;
+        for _ in 0..property_count {
+            let (property, next) = self.property(property_at)?;
+            node.properties.push(property);
+            property_at = next;
+        }
+
+        let mut child_at = name_start + name_length + property_list_length;
+        while child_at < end_offset {
+            match self.node(child_at, depth + 1)? {
+                (Some(child), next) => {
+                    node.children.push(child);
+                    child_at = next;
+                }
+                (None, _) => break,
+            }
+        }
+        Ok((Some(node), end_offset))
+    }
+
+    fn property(&self, at: usize) -> Result<(Property, usize), ModelLoadingError> {
+        let type_code = self.reader.bytes(at, 1)?[0];
+        let data = at + 1;
+        let number = |size: usize| -> Result<(f64, i64), ModelLoadingError> {
+            let b = self.reader.bytes(data, size)?;
+            Ok(match (type_code, size) {
+                (b'F', _) => (f64::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]])), 0),
+                (b'D', _) => (f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]), 0),
+                (_, 1) => (0.0, i64::from(b[0])),
+                (_, 2) => (0.0, i64::from(i16::from_le_bytes([b[0], b[1]]))),
+                (_, 4) => (0.0, i64::from(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))),
+                _ => (0.0, i64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])),
+            })
+        };
+
+        match type_code {
+            b'C' => Ok((Property::Integer(number(1)?.1), data + 1)),
+            b'Y' => Ok((Property::Integer(number(2)?.1), data + 2)),
+            b'I' => Ok((Property::Integer(number(4)?.1), data + 4)),
+            b'L' => Ok((Property::Integer(number(8)?.1), data + 8)),
+            b'F' => Ok((Property::Float(number(4)?.0), data + 4)),
+            b'D' => Ok((Property::Float(number(8)?.0), data + 8)),
+            b'S' | b'R' => {
+                let length = self.reader.u32(data)? as usize;
+                let bytes = self.reader.bytes(data + 4, length)?;
+                let property = if type_code == b'S' {
+                    Property::String(binary_string(bytes))
+                } else {
+                    Property::Raw
+                };
+                Ok((property, data + 4 + length))
+            }
+            b'f' | b'd' | b'l' | b'i' | b'b' => self.array_property(type_code, data),
+            _ => Err(self.reader.invalid(format!("unknown property type '{}' at byte {}", type_code as char, at))),
+        }
+    }
+
+    //Arrays have a length, an encoding (0 plain, 1 zlib) and the size of the stored data
+    fn array_property(&self, type_code: u8, at: usize) -> Result<(Property, usize), ModelLoadingError> {
+        let length = self.reader.u32(at)? as usize;
+        let encoding = self.reader.u32(at + 4)?;
+        let stored_length = self.reader.u32(at + 8)? as usize;
+        let stored = self.reader.bytes(at + 12, stored_length)?;
+        let element_size = match type_code {
+            b'b' => 1,
+            b'i' | b'f' => 4,
+            _ => 8,
+        };
+
+        let decompressed;
+        let data = match encoding {
+            0 => stored,
+            1 => {
+                //The length is not trusted to size the buffer, inflating stops after the bytes it asks for
+                let mut inflated = Vec::new();
+                ZlibDecoder::new(stored).take((length * element_size) as u64).read_to_end(&mut inflated)
+                    .map_err(|e| self.reader.invalid(format!("the compressed array at byte {} could not be inflated: {}", at, e)))?;
+                decompressed = inflated;
+                &decompressed[..]
+            }
+            _ => return Err(self.reader.invalid(format!("unknown array encoding {} at byte {}", encoding, at))),
+        };
+        if data.len() < length * element_size {
+            return Err(self.reader.invalid(format!("the array at byte {} holds {} bytes instead of {}", at, data.len(), length * element_size)));
+        }
+
+        let elements = data.chunks(element_size).take(length);
+        let property = match type_code {
+            b'b' => Property::IntegerArray(elements.map(|b| i64::from(b[0])).collect()),
+            b'i' => Property::IntegerArray(elements.map(|b| i64::from(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))).collect()),
+            b'l' => Property::IntegerArray(elements.map(|b| i64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])).collect()),
+            b'f' => Property::FloatArray(elements.map(|b| f64::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]]))).collect()),
+            _ => Property::FloatArray(elements.map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])).collect()),
+        };
+        Ok((property, at + 12 + stored_length))
+    }
+}
+
+//Binary files write object names as "Name\0\x01Class", the ASCII format as "Class::Name"
+fn binary_string(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    match text.find("\u{0}\u{1}") {
+        Some(separator) => format!("{}::{}", &text[separator + 2..], &text[..separator]),
+        None => text.into_owned(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(String),
+    Text(String),
+    Colon,
+    Comma,
+    Star,
+    Open,
+    Close,
+}
+
+//Name: property, property, ... {
+//    Child: ...
+//}
+//Arrays are written as Name: *count { a: value, value, ... }
+struct AsciiReader<'s> {
+    file_path: &'s str,
+    //Tokens with their line and column
+    tokens: Vec<(Token, usize, usize)>,
+    position: usize,
+}
+
+impl<'s> AsciiReader<'s> {
+    fn parse(source: &str, file_path: &'s str) -> Result<Node, ModelLoadingError> {
+        let mut reader = AsciiReader { file_path, tokens: tokenize_ascii(source), position: 0 };
+        Ok(Node { children: reader.nodes(0)?, ..Default::default() })
+    }
+
+    fn invalid(&self, description: &str) -> ModelLoadingError {
+        let (line, column) = self.tokens.get(self.position)
+            .or_else(|| self.tokens.last())
+            .map(|&(_, line, column)| (line, column))
+            .unwrap_or((0, 0));
+        ModelLoadingError::InvalidData { location: SourceLocation::new(self.file_path, line, column), description: description.to_string() }
+    }
+
+    fn peek(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.position + offset).map(|(token, _, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek(0).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, description: &str) -> Result<(), ModelLoadingError> {
+        if self.peek(0) == Some(&expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.invalid(description))
+        }
+    }
+
+    //The nodes of a list, depth 0 is the top level which ends with the file instead of a }
+    fn nodes(&mut self, depth: usize) -> Result<Vec<Node>, ModelLoadingError> {
+        if depth > MAX_NODE_DEPTH {
+            return Err(self.invalid(&format!("nodes are nested deeper than {} levels", MAX_NODE_DEPTH)));
+        }
+        let nested = depth > 0;
+        let mut nodes = Vec::new();
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (None, _) if nested => return Err(self.invalid("the file ends inside a node, a } is missing")),
+                (None, _) => return Ok(nodes),
+                (Some(Token::Close), _) if nested => {
+                    self.position += 1;
+                    return Ok(nodes);
+                }
+                (Some(Token::Word(_)), Some(Token::Colon)) => {
+                    let node = self.node(depth)?;
+                    nodes.push(node);
+                }
+                _ => return Err(self.invalid("expected a node name followed by a colon")),
+            }
+        }
+    }
+
+    fn node(&mut self, depth: usize) -> Result<Node, ModelLoadingError> {
+        let name = match self.next() {
+            Some(Token::Word(name)) => name,
+            _ => return Err(self.invalid("expected a node name")),
+        };
+        self.position += 1;
+        let mut node = Node { name, ..Default::default() };
+
+        loop {
+            match (self.peek(0).cloned(), self.peek(1)) {
+                (Some(Token::Number(number)), _) => {
+                    self.position += 1;
+                    node.properties.push(self.number(&number)?);
+                }
+                (Some(Token::Text(text)), _) => {
+                    self.position += 1;
+                    node.properties.push(Property::String(text));
+                }
+                //A bare word is a property unless it starts the next node
+                (Some(Token::Word(word)), next) if next != Some(&Token::Colon) => {
+                    self.position += 1;
+                    node.properties.push(Property::String(word));
+                }
+                (Some(Token::Comma), _) => self.position += 1,
+                (Some(Token::Star), _) => {
+                    let array = self.array()?;
+                    node.properties.push(array);
+                }
+                (Some(Token::Open), _) => {
+                    self.position += 1;
+                    node.children = self.nodes(depth + 1)?;
+                    return Ok(node);
+                }
+                _ => return Ok(node),
+            }
+        }
+    }
+
+    fn number(&self, text: &str) -> Result<Property, ModelLoadingError> {
+        let property = if text.contains(['.', 'e', 'E']) {
+            text.parse::<f64>().ok().map(Property::Float)
+        } else {
+            text.parse::<i64>().ok().map(Property::Integer)
+        };
+        property.ok_or_else(|| self.invalid(&format!("\"{}\" is not a valid number", text)))
+    }
+
+    //*count { a: value, value, ... }
+    fn array(&mut self) -> Result<Property, ModelLoadingError> {
+        self.expect(Token::Star, "expected an array")?;
+        match self.next() {
+            Some(Token::Number(_)) => {}
+            _ => return Err(self.invalid("expected the length of the array")),
+        }
+        self.expect(Token::Open, "expected { after the array length")?;
+        if self.peek(0) == Some(&Token::Word("a".to_string())) {
+            self.position += 1;
+            self.expect(Token::Colon, "expected : after a")?;
+        }
+
+        let mut values = Vec::new();
+        loop {
+            match self.next() {
+                Some(Token::Number(number)) => values.push(self.number(&number)?),
+                Some(Token::Comma) => {}
+                Some(Token::Close) => break,
+                _ => {
+                    self.position -= 1;
+                    return Err(self.invalid("expected a number in the array"));
+                }
+            }
+        }
+
+        if values.iter().all(|v| matches!(v, Property::Integer(_))) {
+            Ok(Property::IntegerArray(values.iter().map(|v| if let Property::Integer(i) = v { *i } else { 0 }).collect()))
+        } else {
+            Ok(Property::FloatArray(values.iter().map(|v| match v {
+                Property::Integer(i) => *i as f64,
+                Property::Float(f) => *f,
+                _ => 0.0,
+            }).collect()))
+        }
+    }
+}
+
+//Splits the ASCII format into tokens, skipping ; comments
+fn tokenize_ascii(source: &str) -> Vec<(Token, usize, usize)> {
+    let mut tokens = Vec::new();
+    for (line_index, line) in source.lines().enumerate() {
+        let chars: Vec<(usize, char)> = line.char_indices().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let (start, c) = chars[i];
+            let column = start + 1;
+            let position = |token: Token| (token, line_index + 1, column);
+            match c {
+                ';' => break,
+                ':' => tokens.push(position(Token::Colon)),
+                ',' => tokens.push(position(Token::Comma)),
+                '*' => tokens.push(position(Token::Star)),
+                '{' => tokens.push(position(Token::Open)),
+                '}' => tokens.push(position(Token::Close)),
+                '"' => {
+                    let end = chars[i + 1..].iter().position(|&(_, c)| c == '"').map(|p| i + 1 + p).unwrap_or(chars.len());
+                    let text: String = chars[i + 1..end].iter().map(|&(_, c)| c).collect();
+                    tokens.push(position(Token::Text(text)));
+                    i = end;
+                }
+                c if c.is_whitespace() => {}
+                c => {
+                    let end = chars[i..].iter()
+                        .position(|&(_, c)| c.is_whitespace() || ":,*{}\";".contains(c))
+                        .map(|p| i + p)
+                        .unwrap_or(chars.len());
+                    let word: String = chars[i..end].iter().map(|&(_, c)| c).collect();
+                    let is_number = c.is_ascii_digit() || ((c == '-' || c == '+' || c == '.') && word.len() > 1);
+                    tokens.push(position(if is_number { Token::Number(word) } else { Token::Word(word) }));
+                    i = end;
+                    continue;
+                }
+            }
+            i += 1;
+        }
+    }
+    tokens
+}
+
+//Which element of a layer belongs to a polygon corner
+#[derive(Copy, Clone, PartialEq)]
+enum Mapping {
+    ByPolygonVertex,
+    ByControlPoint,
+    ByPolygon,
+    AllSame,
+}
+
+//A LayerElementNormal, LayerElementUV, LayerElementColor or LayerElementMaterial of a geometry
+struct LayerElement {
+    mapping: Mapping,
+    indices: Option<Vec<i64>>,
+    values: Vec<f64>,
+    components: usize,
+}
+
+impl LayerElement {
+    fn read(geometry: &Node, layer_name: &str, values_name: &str, index_name: &str, components: usize, file_path: &str)
+            -> Result<Option<LayerElement>, ModelLoadingError> {
+        let layer = match geometry.child(layer_name) {
+            Some(layer) => layer,
+            None => return Ok(None),
+        };
+        let invalid = |description: String| ModelLoadingError::InvalidData { location: SourceLocation::file(file_path), description };
+
+        let mapping_name = layer.child("MappingInformationType").and_then(|n| n.string(0)).unwrap_or("AllSame");
+        let mapping = match mapping_name {
+            "ByPolygonVertex" => Mapping::ByPolygonVertex,
+            "ByVertice" | "ByVertex" | "ByControlPoint" => Mapping::ByControlPoint,
+            "ByPolygon" => Mapping::ByPolygon,
+            "AllSame" => Mapping::AllSame,
+            //Edge mappings only make sense for smoothing and creases
+            "ByEdge" => return Ok(None),
+            _ => return Err(invalid(format!("unknown mapping \"{}\" in {}", mapping_name, layer_name))),
+        };
+        let reference = layer.child("ReferenceInformationType").and_then(|n| n.string(0)).unwrap_or("Direct");
+        let indices = match reference {
+            "Direct" => None,
+            "IndexToDirect" | "Index" => layer.child(index_name).map(|n| n.integers()),
+            _ => return Err(invalid(format!("unknown reference \"{}\" in {}", reference, layer_name))),
+        };
+        let values = layer.child(values_name).map(|n| n.floats()).unwrap_or_default();
+        Ok(Some(LayerElement { mapping, indices, values, components }))
+    }
+
+    fn value(&self, polygon_vertex: usize, control_point: usize, polygon: usize) -> Option<&[f64]> {
+        let element = match self.mapping {
+            Mapping::ByPolygonVertex => polygon_vertex,
+            Mapping::ByControlPoint => control_point,
+            Mapping::ByPolygon => polygon,
+            Mapping::AllSame => 0,
+        };
+        let element = match &self.indices {
+            Some(indices) => *indices.get(element).filter(|&&i| i >= 0)? as usize,
+            None => element,
+        };
+        self.values.get(element * self.components..(element + 1) * self.components)
+    }
+}
+
+//The objects of a file and the connections between them
+struct Scene<'n> {
+    file_path: &'n str,
+    objects: HashMap<i64, &'n Node>,
+    //Child and parent ids, with the property name for connections to a property
+    connections: Vec<(i64, i64, Option<&'n str>)>,
+    geometries: Vec<&'n Node>,
+}
+
+impl<'n> Scene<'n> {
+    fn new(root: &'n Node, file_path: &'n str) -> Scene<'n> {
+        let mut scene = Scene { file_path, objects: HashMap::new(), connections: Vec::new(), geometries: Vec::new() };
+        if let Some(objects) = root.child("Objects") {
+            for object in &objects.children {
+                if let Some(id) = object.integer(0) {
+                    scene.objects.insert(id, object);
+                }
+                //Files before 7.x keep the mesh data in the model itself
+                if object.name == "Geometry" || (object.name == "Model" && object.child("Vertices").is_some()) {
+                    scene.geometries.push(object);
+                }
+            }
+        }
+        if let Some(connections) = root.child("Connections") {
+            for connection in connections.children_named("C") {
+                if let (Some(child), Some(parent)) = (connection.integer(1), connection.integer(2)) {
+                    scene.connections.push((child, parent, connection.string(3)));
+                }
+            }
+        }
+        scene
+    }
+
+    fn invalid(&self, description: String) -> ModelLoadingError {
+        ModelLoadingError::InvalidData { location: SourceLocation::file(self.file_path), description }
+    }
+
+    //Objects of the given kind connected to the parent, in the order of the connections
+    fn connected(&self, parent: i64, kind: &str) -> Vec<(i64, &'n Node, Option<&'n str>)> {
+        self.connections.iter()
+            .filter(|&&(_, p, _)| p == parent)
+            .filter_map(|&(child, _, property)| self.objects.get(&child).map(|&node| (child, node, property)))
+            .filter(|(_, node, _)| node.name == kind)
+            .collect()
+    }
+
+    fn meshes(&self) -> Result<Vec<ObjModel>, ModelLoadingError> {
+        self.geometries.iter().map(|&geometry| {
+            let id = geometry.integer(0);
+            //The model a geometry is attached to gives it a name and materials
+            let model = match geometry.name.as_str() {
+                "Model" => Some(geometry),
+                _ => self.connections.iter()
+                    .filter(|&&(child, _, _)| Some(child) == id)
+                    .filter_map(|(_, parent, _)| self.objects.get(parent).copied())
+                    .find(|node| node.name == "Model"),
+            };
+            let materials = match model.and_then(|m| m.integer(0)) {
+                Some(model_id) => self.connected(model_id, "Material").into_iter().map(|(id, node, _)| self.material(id, node)).collect(),
+                None => Vec::new(),
+            };
+            let name = model.or(Some(geometry)).and_then(|n| n.object_name()).unwrap_or("mesh");
+            self.mesh(geometry, name, materials)
+        }).collect()
+    }
+
+    fn material(&self, id: i64, node: &Node) -> Material {
+        let mut material = Material { name: node.object_name().unwrap_or("material").to_string(), ..Default::default() };
+        //P: records are name, type, label, flags and values, the older Property: records have no label
+        let (properties, first_value) = match node.child("Properties70") {
+            Some(properties) => (Some(properties), 4),
+            None => (node.child("Properties60"), 3),
+        };
+        for property in properties.into_iter().flat_map(|p| p.children.iter()) {
+            let color = || match (property.float(first_value), property.float(first_value + 1), property.float(first_value + 2)) {
+                (Some(r), Some(g), Some(b)) => Some(Vec3n::new(r as f32, g as f32, b as f32)),
+                _ => None,
+            };
+            match property.string(0) {
+                Some("DiffuseColor") | Some("Diffuse") => material.diffuse = color(),
+                Some("AmbientColor") | Some("Ambient") => material.ambient = color(),
+                Some("SpecularColor") | Some("Specular") => material.specular = color(),
+                Some("EmissiveColor") | Some("Emissive") => material.emissive = color(),
+                Some("Shininess") | Some("ShininessExponent") => material.shininess = property.float(first_value).map(|v| v as f32),
+                Some("Opacity") => material.dissolve = property.float(first_value).map(|v| v as f32),
+                _ => {}
+            }
+        }
+
+        for (_, texture, slot) in self.connected(id, "Texture") {
+            let path = texture.child("RelativeFilename").or_else(|| texture.child("FileName")).and_then(|n| n.string(0));
+            let map = match path {
+                Some(path) if !path.is_empty() => Some(TextureMap { path: path.to_string(), options: TextureOptions::default() }),
+                _ => continue,
+            };
+            match slot {
+                Some("DiffuseColor") => material.diffuse_map = map,
+                Some("SpecularColor") => material.specular_map = map,
+                Some("EmissiveColor") => material.emissive_map = map,
+                Some("NormalMap") => material.normal_map = map,
+                Some("Bump") => material.bump_map = map,
+                Some("TransparentColor") => material.dissolve_map = map,
+                _ => {}
+            }
+        }
+        material
+    }
+
+    fn mesh(&self, geometry: &Node, name: &str, materials: Vec<Material>) -> Result<ObjModel, ModelLoadingError> {
+        let control_points: Vec<Vec3n> = geometry.child("Vertices").map(|n| n.floats()).unwrap_or_default()
+            .chunks(3)
+            .filter(|c| c.len() == 3)
+            .map(|c| Vec3n::new(c[0] as f32, c[1] as f32, c[2] as f32))
+            .collect();
+
+        //The last corner of every polygon is stored as -(index + 1)
+        let mut polygons: Vec<Vec<(usize, usize)>> = Vec::new();
+        let mut polygon = Vec::new();
+        for (polygon_vertex, &raw) in geometry.child("PolygonVertexIndex").map(|n| n.integers()).unwrap_or_default().iter().enumerate() {
+            let control_point = if raw < 0 { !raw } else { raw } as usize;
+            if control_point >= control_points.len() {
+                return Err(self.invalid(format!("polygon vertex {} of {} refers to control point {}, only {} are defined",
+                                                polygon_vertex, name, control_point, control_points.len())));
+            }
+            polygon.push((polygon_vertex, control_point));
+            if raw < 0 {
+                polygons.push(std::mem::take(&mut polygon));
+            }
+        }
+        if !polygon.is_empty() {
+            polygons.push(polygon);
+        }
+
+        let normals = LayerElement::read(geometry, "LayerElementNormal", "Normals", "NormalsIndex", 3, self.file_path)?;
+        let tex_coords = LayerElement::read(geometry, "LayerElementUV", "UV", "UVIndex", 2, self.file_path)?;
+        let colors = LayerElement::read(geometry, "LayerElementColor", "Colors", "ColorIndex", 4, self.file_path)?;
+        //The material layer holds indices into the materials of the model directly
+        let material_layer = LayerElement::read(geometry, "LayerElementMaterial", "Materials", "", 1, self.file_path)?
+            .map(|layer| LayerElement { indices: None, ..layer });
+
+        let mut model = ObjModel { materials, ..ObjModel::empty() };
+        let mut mapped_vertices: HashMap<Vec<u32>, u32> = HashMap::new();
+        let mut sub_mesh_material = None;
+        for (polygon_index, polygon) in polygons.iter().enumerate() {
+            let material = material_layer.as_ref()
+                .and_then(|layer| layer.value(0, 0, polygon_index))
+                .map(|value| value[0] as usize)
+                .filter(|&m| m < model.materials.len());
+            if model.sub_meshes.is_empty() || material != sub_mesh_material {
+                if let Some(sub_mesh) = model.sub_meshes.last_mut() {
+                    sub_mesh.index_range.end = model.indices.len();
+                }
+                model.sub_meshes.push(SubMesh {
+                    index_range: model.indices.len()..model.indices.len(),
+                    material_name: material.map(|m| model.materials[m].name.clone()),
+                    material,
+                });
+                sub_mesh_material = material;
+            }
+
+            let mut corner_vertices = Vec::with_capacity(polygon.len());
+            for &(polygon_vertex, control_point) in polygon {
+                let attribute = |layer: &Option<LayerElement>| -> Vec<f32> {
+                    match layer {
+                        Some(layer) => match layer.value(polygon_vertex, control_point, polygon_index) {
+                            Some(value) => value.iter().map(|&v| v as f32).collect(),
+                            None => vec![0.0; layer.components],
+                        },
+                        None => Vec::new(),
+                    }
+                };
+                let (normal, uv, color) = (attribute(&normals), attribute(&tex_coords), attribute(&colors));
+
+                let mut key = vec![control_point as u32];
+                key.extend(normal.iter().chain(uv.iter()).chain(color.iter()).map(|v| v.to_bits()));
+                let index = *mapped_vertices.entry(key).or_insert_with(|| {
+                    model.vertices.push(Vertex { position: control_points[control_point] });
+                    if !normal.is_empty() {
+                        model.normals.push(Normal { normal: Vec3n::new(normal[0], normal[1], normal[2]) });
+                    }
+                    if !uv.is_empty() {
+                        model.tex_coords.push(UV { tex_coords: straal::Vec2 { x: uv[0], y: uv[1] } });
+                    }
+                    if !color.is_empty() {
+                        model.colors.push(Color { color: Vec4n::new(color[0], color[1], color[2], color[3]) });
+                    }
+                    (model.vertices.len() - 1) as u32
+                });
+                corner_vertices.push(index);
+            }
+
+            let corners: Vec<Vec3n> = polygon.iter().map(|&(_, control_point)| control_points[control_point]).collect();
+            for triangle in triangulation::triangulate(&corners) {
+                model.indices.extend(triangle.iter().map(|&corner| corner_vertices[corner]));
+            }
+        }
+        if let Some(sub_mesh) = model.sub_meshes.last_mut() {
+            sub_mesh.index_range.end = model.indices.len();
+        }
+
+        let mut object = NamedPart { name: name.to_string(), index_ranges: Vec::new() };
+        object.extend(0..model.indices.len());
+        model.objects.push(object);
+        Ok(model)
+    }
+}
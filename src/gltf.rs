@@ -0,0 +1,554 @@
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+use straal::{Vec2n, Vec3n, Vec4n};
+
+use crate::error::{BYTES_SOURCE_NAME, ModelLoadingError, SourceLocation};
+use crate::models::*;
+use crate::mtl::{Material, TextureMap, TextureOptions};
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+//Primitive modes, the ones below triangles (points and lines) have no faces
+const MODE_POINTS: u64 = 0;
+const MODE_TRIANGLES: u64 = 4;
+const MODE_TRIANGLE_STRIP: u64 = 5;
+const MODE_TRIANGLE_FAN: u64 = 6;
+
+//Accessor component types
+const BYTE: u64 = 5120;
+const UNSIGNED_BYTE: u64 = 5121;
+const SHORT: u64 = 5122;
+const UNSIGNED_SHORT: u64 = 5123;
+const UNSIGNED_INT: u64 = 5125;
+const FLOAT: u64 = 5126;
+
+impl ObjModel {
+    //Loads every mesh of a .gltf or .glb file, the primitives of a mesh become its sub-meshes.
+    //Meshes keep their own coordinates, node transforms are not applied
+    pub fn load_gltf_from_file(file_path: &str) -> Result<Vec<ObjModel>, ModelLoadingError> {
+        let bytes = fs::read(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        Document::parse(&bytes, file_path, Path::new(file_path).parent())?.meshes()
+    }
+
+    //Loads a .glb or a .gltf file that embeds its buffers as data URIs
+    pub fn from_gltf_bytes(bytes: &[u8]) -> Result<Vec<ObjModel>, ModelLoadingError> {
+        Document::parse(bytes, BYTES_SOURCE_NAME, None)?.meshes()
+    }
+}
+
+struct Document<'a> {
+    file_path: &'a str,
+    json: Value,
+    buffers: Vec<Vec<u8>>,
+    materials: Vec<Material>,
+}
+
+impl<'a> Document<'a> {
+    fn parse(bytes: &[u8], file_path: &'a str, base_dir: Option<&Path>) -> Result<Document<'a>, ModelLoadingError> {
+        let (json, binary_chunk) = if bytes.starts_with(GLB_MAGIC) {
+            split_glb(bytes, file_path)?
+        } else {
+            (bytes, None)
+        };
+        let json: Value = serde_json::from_slice(json).map_err(|e| ModelLoadingError::Json {
+            location: SourceLocation::new(file_path, e.line(), e.column()),
+            source: e,
+        })?;
+
+        let mut document = Document { file_path, json, buffers: Vec::new(), materials: Vec::new() };
+        let version = document.json.get("asset").and_then(|a| a.get("version")).and_then(Value::as_str).unwrap_or("");
+        if !version.starts_with("2.") {
+            return Err(document.invalid(format!("unsupported glTF version \"{}\", only 2.x is supported", version)));
+        }
+        document.buffers = document.load_buffers(base_dir, binary_chunk)?;
+        document.materials = document.load_materials()?;
+        Ok(document)
+    }
+
+    fn invalid(&self, description: String) -> ModelLoadingError {
+        ModelLoadingError::InvalidData { location: SourceLocation::file(self.file_path), description }
+    }
+
+    //The elements of a top level array like "meshes", missing arrays are empty
+    fn elements(&self, name: &str) -> &[Value] {
+        self.json.get(name).and_then(Value::as_array).map(|a| a.as_slice()).unwrap_or(&[])
+    }
+
+    fn element(&self, name: &str, index: usize) -> Result<&Value, ModelLoadingError> {
+        self.elements(name).get(index)
+            .ok_or_else(|| self.invalid(format!("{} {} does not exist, only {} are defined", name, index, self.elements(name).len())))
+    }
+
+    fn optional_integer(&self, object: &Value, key: &str) -> Result<Option<u64>, ModelLoadingError> {
+        match object.get(key) {
+            None => Ok(None),
+            Some(value) => value.as_u64().map(Some)
+                .ok_or_else(|| self.invalid(format!("\"{}\" must be a non negative integer, found {}", key, value))),
+        }
+    }
+
+    fn integer(&self, object: &Value, key: &str) -> Result<u64, ModelLoadingError> {
+        self.optional_integer(object, key)?.ok_or_else(|| self.invalid(format!("\"{}\" is missing in {}", key, object)))
+    }
+
+    fn index(&self, object: &Value, key: &str) -> Result<usize, ModelLoadingError> {
+        self.integer(object, key).map(|i| i as usize)
+    }
+
+    fn optional_index(&self, object: &Value, key: &str) -> Result<Option<usize>, ModelLoadingError> {
+        self.optional_integer(object, key).map(|i| i.map(|i| i as usize))
+    }
+
+    fn numbers(&self, object: &Value, key: &str, default: &[f32]) -> Result<Vec<f32>, ModelLoadingError> {
+        match object.get(key) {
+            None => Ok(default.to_vec()),
+            Some(value) => value.as_array()
+                .filter(|a| a.len() == default.len())
+                .and_then(|a| a.iter().map(|n| n.as_f64().map(|n| n as f32)).collect::<Option<Vec<f32>>>())
+                .ok_or_else(|| self.invalid(format!("\"{}\" must hold {} numbers, found {}", key, default.len(), value))),
+        }
+    }
+
+    fn number(&self, object: &Value, key: &str, default: f32) -> Result<f32, ModelLoadingError> {
+        match object.get(key) {
+            None => Ok(default),
+            Some(value) => value.as_f64().map(|n| n as f32)
+                .ok_or_else(|| self.invalid(format!("\"{}\" must be a number, found {}", key, value))),
+        }
+    }
+
+    //Buffers come from the binary chunk of a .glb, from base64 data URIs or from files next to the .gltf
+    fn load_buffers(&self, base_dir: Option<&Path>, binary_chunk: Option<&[u8]>) -> Result<Vec<Vec<u8>>, ModelLoadingError> {
+        let mut buffers = Vec::new();
+        for (i, buffer) in self.elements("buffers").iter().enumerate() {
+            let data = match buffer.get("uri").and_then(Value::as_str) {
+                None => match binary_chunk {
+                    Some(chunk) if i == 0 => chunk.to_vec(),
+                    _ => return Err(self.invalid(format!("buffer {} has no uri and is not the binary chunk of a .glb", i))),
+                },
+                Some(uri) if uri.starts_with("data:") => self.decode_data_uri(uri)?,
+                Some(uri) => {
+                    let path = match base_dir {
+                        Some(dir) => dir.join(percent_decode(uri)),
+                        None => return Err(self.invalid(format!("buffer {} refers to the file \"{}\", which needs a file path to resolve", i, uri))),
+                    };
+                    fs::read(&path).map_err(|e| ModelLoadingError::Io {
+                        location: SourceLocation::file(&path.to_string_lossy()),
+                        source: e,
+                    })?
+                }
+            };
+            let byte_length = self.index(buffer, "byteLength")?;
+            if data.len() < byte_length {
+                return Err(self.invalid(format!("buffer {} holds {} bytes but should be {} bytes long", i, data.len(), byte_length)));
+            }
+            buffers.push(data);
+        }
+        Ok(buffers)
+    }
+
+    fn decode_data_uri(&self, uri: &str) -> Result<Vec<u8>, ModelLoadingError> {
+        match uri.find(',') {
+            Some(comma) if uri[..comma].ends_with(";base64") => base64::decode(&uri[comma + 1..])
+                .map_err(|e| self.invalid(format!("data URI is not valid base64: {}", e))),
+            _ => Err(self.invalid("only base64 encoded data URIs are supported".to_string())),
+        }
+    }
+
+    //Translates the metallic roughness materials into their closest MTL counterparts
+    fn load_materials(&self) -> Result<Vec<Material>, ModelLoadingError> {
+        let mut materials = Vec::new();
+        for (i, material) in self.elements("materials").iter().enumerate() {
+            let name = material.get("name").and_then(Value::as_str).map(|n| n.to_string()).unwrap_or_else(|| format!("material_{}", i));
+            let pbr = material.get("pbrMetallicRoughness").cloned().unwrap_or(Value::Null);
+            let base_color = self.numbers(&pbr, "baseColorFactor", &[1.0, 1.0, 1.0, 1.0])?;
+            let emissive = self.numbers(material, "emissiveFactor", &[0.0, 0.0, 0.0])?;
+
+            let mut converted = Material {
+                name,
+                diffuse: Some(Vec3n::new(base_color[0], base_color[1], base_color[2])),
+                dissolve: Some(base_color[3]),
+                emissive: Some(Vec3n::new(emissive[0], emissive[1], emissive[2])),
+                metallic: Some(self.number(&pbr, "metallicFactor", 1.0)?),
+                roughness: Some(self.number(&pbr, "roughnessFactor", 1.0)?),
+                diffuse_map: self.texture_map(&pbr, "baseColorTexture", None)?,
+                emissive_map: self.texture_map(material, "emissiveTexture", None)?,
+                ambient_map: self.texture_map(material, "occlusionTexture", Some("r"))?,
+                //Roughness and metalness share one image
+                roughness_map: self.texture_map(&pbr, "metallicRoughnessTexture", Some("g"))?,
+                metallic_map: self.texture_map(&pbr, "metallicRoughnessTexture", Some("b"))?,
+                normal_map: self.texture_map(material, "normalTexture", None)?,
+                ..Default::default()
+            };
+            if let Some(normal_map) = converted.normal_map.as_mut() {
+                let normal_texture = &material["normalTexture"];
+                normal_map.options.bump_multiplier = self.number(normal_texture, "scale", 1.0)?;
+            }
+            materials.push(converted);
+        }
+        Ok(materials)
+    }
+
+    //Only textures whose image is a file can be expressed as a path, embedded images are skipped
+    fn texture_map(&self, object: &Value, key: &str, channel: Option<&str>) -> Result<Option<TextureMap>, ModelLoadingError> {
+        let texture_info = match object.get(key) {
+            Some(texture_info) => texture_info,
+            None => return Ok(None),
+        };
+        let texture = self.element("textures", self.index(texture_info, "index")?)?;
+        let image = match self.optional_index(texture, "source")? {
+            Some(source) => self.element("images", source)?,
+            None => return Ok(None),
+        };
+        Ok(image.get("uri").and_then(Value::as_str)
+            .filter(|uri| !uri.starts_with("data:"))
+            .map(|uri| TextureMap {
+                path: percent_decode(uri),
+                options: TextureOptions { channel: channel.map(|c| c.to_string()), ..Default::default() },
+            }))
+    }
+
+    fn meshes(&self) -> Result<Vec<ObjModel>, ModelLoadingError> {
+        (0..self.elements("meshes").len()).map(|i| self.mesh(i)).collect()
+    }
+
+    fn mesh(&self, mesh_index: usize) -> Result<ObjModel, ModelLoadingError> {
+        let mesh = self.element("meshes", mesh_index)?;
+        let mut model = ObjModel { materials: self.materials.clone(), ..ObjModel::empty() };
+        let mut loaded_attributes: Vec<(Value, usize, usize)> = Vec::new();
+
+        for primitive in mesh.get("primitives").and_then(Value::as_array).map(|a| a.as_slice()).unwrap_or(&[]) {
+            let mode = self.optional_integer(primitive, "mode")?.unwrap_or(MODE_TRIANGLES);
+            //Lines are skipped, points are kept as vertices without indices
+            if mode != MODE_POINTS && mode < MODE_TRIANGLES {
+                continue;
+            }
+            //Primitives that use the same attribute accessors share their vertices
+            let attributes = primitive.get("attributes").cloned().unwrap_or(Value::Null);
+            let (base, vertex_count) = match loaded_attributes.iter().find(|(loaded, _, _)| *loaded == attributes) {
+                Some(&(_, base, vertex_count)) => (base, vertex_count),
+                None => {
+                    let base = model.vertices.len();
+                    let vertex_count = self.read_vertices(&mut model, &attributes, mesh_index)?;
+                    loaded_attributes.push((attributes, base, vertex_count));
+                    (base, vertex_count)
+                }
+            };
+            if mode == MODE_POINTS {
+                continue;
+            }
+
+            let indices: Vec<usize> = match self.optional_index(primitive, "indices")? {
+                Some(accessor) => {
+                    let (values, _) = self.accessor(accessor, &[1])?;
+                    let indices: Vec<usize> = values.into_iter().map(|i| i as usize).collect();
+                    if let Some(&index) = indices.iter().find(|&&i| i >= vertex_count) {
+                        return Err(self.invalid(format!("index {} of accessor {} is out of range, only {} vertices are defined", index, accessor, vertex_count)));
+                    }
+                    indices
+                }
+                None => (0..vertex_count).collect(),
+            };
+
+            let start = model.indices.len();
+            let corner_count = indices.len();
+            let triangles: Vec<[usize; 3]> = match mode {
+                MODE_TRIANGLE_STRIP => (0..corner_count.saturating_sub(2))
+                    .map(|i| if i % 2 == 0 { [i, i + 1, i + 2] } else { [i + 1, i, i + 2] })
+                    .collect(),
+                MODE_TRIANGLE_FAN => (1..corner_count.saturating_sub(1)).map(|i| [0, i, i + 1]).collect(),
+                _ => (0..corner_count / 3).map(|i| [i * 3, i * 3 + 1, i * 3 + 2]).collect(),
+            };
+            for triangle in triangles {
+                model.indices.extend(triangle.iter().map(|&corner| (base + indices[corner]) as u32));
+            }
+
+            let material = self.optional_index(primitive, "material")?;
+            if let Some(material) = material {
+                self.element("materials", material)?;
+            }
+            model.sub_meshes.push(SubMesh {
+                index_range: start..model.indices.len(),
+                material_name: material.map(|m| self.materials[m].name.clone()),
+                material,
+            });
+        }
+
+        //Primitives without an attribute others do have get a neutral value for it
+        let vertex_count = model.vertices.len();
+        if !model.normals.is_empty() {
+            pad(&mut model.normals, vertex_count, Normal { normal: Vec3n::zero() });
+        }
+        if !model.tex_coords.is_empty() {
+            pad(&mut model.tex_coords, vertex_count, UV { tex_coords: Vec2n::zero() });
+        }
+        if !model.tangents.is_empty() {
+            pad(&mut model.tangents, vertex_count, Tangent { tangent: Vec4n::new(1.0, 0.0, 0.0, 1.0) });
+        }
+        if !model.colors.is_empty() {
+            pad(&mut model.colors, vertex_count, Color { color: Vec4n::new(1.0, 1.0, 1.0, 1.0) });
+        }
+
+        let name = mesh.get("name").and_then(Value::as_str).map(|n| n.to_string()).unwrap_or_else(|| format!("mesh_{}", mesh_index));
+        let mut object = NamedPart { name, index_ranges: Vec::new() };
+        object.extend(0..model.indices.len());
+        model.objects.push(object);
+        Ok(model)
+    }
+
+    //Appends the vertices of a primitive to the model and returns how many there were
+    fn read_vertices(&self, model: &mut ObjModel, attributes: &Value, mesh_index: usize) -> Result<usize, ModelLoadingError> {
+        let base = model.vertices.len();
+
+        let positions = self.attribute(attributes, "POSITION", &[3])?
+            .ok_or_else(|| self.invalid(format!("a primitive of mesh {} has no POSITION attribute", mesh_index)))?;
+        let vertex_count = positions.len() / 3;
+        model.vertices.extend(positions.chunks(3).map(|p| Vertex { position: Vec3n::new(p[0], p[1], p[2]) }));
+        //Every attribute needs a value for every vertex, or all streams after a short one would be misaligned
+        let check_count = |name: &str, values: &[f32], components: usize| if values.len() / components == vertex_count {
+            Ok(())
+        } else {
+            Err(self.invalid(format!("the {} attribute of a primitive of mesh {} has {} elements, POSITION has {}",
+                                     name, mesh_index, values.len() / components, vertex_count)))
+        };
+
+        if let Some(normals) = self.attribute(attributes, "NORMAL", &[3])? {
+            check_count("NORMAL", &normals, 3)?;
+            pad(&mut model.normals, base, Normal { normal: Vec3n::zero() });
+            model.normals.extend(normals.chunks(3).map(|n| Normal { normal: Vec3n::new(n[0], n[1], n[2]) }));
+        }
+        if let Some(tex_coords) = self.attribute(attributes, "TEXCOORD_0", &[2])? {
+            check_count("TEXCOORD_0", &tex_coords, 2)?;
+            //glTF puts the origin of texture space in the top left corner, OBJ in the bottom left one
+            pad(&mut model.tex_coords, base, UV { tex_coords: Vec2n::zero() });
+            model.tex_coords.extend(tex_coords.chunks(2).map(|uv| UV { tex_coords: straal::Vec2 { x: uv[0], y: 1.0 - uv[1] } }));
+        }
+        if let Some(tangents) = self.attribute(attributes, "TANGENT", &[4])? {
+            check_count("TANGENT", &tangents, 4)?;
+            //Flipping v above mirrors the bitangent, so the handedness flips along with it
+            pad(&mut model.tangents, base, Tangent { tangent: Vec4n::new(1.0, 0.0, 0.0, 1.0) });
+            model.tangents.extend(tangents.chunks(4).map(|t| Tangent { tangent: Vec4n::new(t[0], t[1], t[2], -t[3]) }));
+        }
+        if let Some((colors, components)) = self.attribute_with_components(attributes, "COLOR_0", &[3, 4])? {
+            check_count("COLOR_0", &colors, components)?;
+            pad(&mut model.colors, base, Color { color: Vec4n::new(1.0, 1.0, 1.0, 1.0) });
+            model.colors.extend(colors.chunks(components).map(|c| Color {
+                color: Vec4n::new(c[0], c[1], c[2], if components == 4 { c[3] } else { 1.0 }),
+            }));
+        }
+        Ok(vertex_count)
+    }
+
+    fn attribute(&self, attributes: &Value, name: &str, allowed_components: &[usize]) -> Result<Option<Vec<f32>>, ModelLoadingError> {
+        Ok(self.attribute_with_components(attributes, name, allowed_components)?.map(|(values, _)| values))
+    }
+
+    fn attribute_with_components(&self, attributes: &Value, name: &str, allowed_components: &[usize])
+                                 -> Result<Option<(Vec<f32>, usize)>, ModelLoadingError> {
+        match self.optional_index(attributes, name)? {
+            Some(accessor) => {
+                let (values, components) = self.accessor(accessor, allowed_components)?;
+                Ok(Some((values.into_iter().map(|v| v as f32).collect(), components)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    //Decodes an accessor into a flat list of values, applying normalization and sparse substitution.
+    //Values are returned as f64 so 32 bit indices survive
+    fn accessor(&self, index: usize, allowed_components: &[usize]) -> Result<(Vec<f64>, usize), ModelLoadingError> {
+        let accessor = self.element("accessors", index)?;
+        let count = self.index(accessor, "count")?;
+        let component_type = self.integer(accessor, "componentType")?;
+        let normalized = accessor.get("normalized").and_then(Value::as_bool).unwrap_or(false);
+        let type_name = accessor.get("type").and_then(Value::as_str).unwrap_or("");
+        let (columns, rows) = match type_name {
+            "SCALAR" => (1, 1),
+            "VEC2" => (1, 2),
+            "VEC3" => (1, 3),
+            "VEC4" => (1, 4),
+            "MAT2" => (2, 2),
+            "MAT3" => (3, 3),
+            "MAT4" => (4, 4),
+            _ => return Err(self.invalid(format!("accessor {} has an unknown type \"{}\"", index, type_name))),
+        };
+        let components = columns * rows;
+        if !allowed_components.contains(&components) {
+            return Err(self.invalid(format!("accessor {} has {} components per element, expected one of {:?}", index, components, allowed_components)));
+        }
+
+        let mut values = match self.optional_index(accessor, "bufferView")? {
+            Some(view) => {
+                let byte_offset = self.optional_index(accessor, "byteOffset")?.unwrap_or(0);
+                self.read_elements(view, byte_offset, count, component_type, normalized, columns, rows)?
+            }
+            //Without a buffer view everything is zero, usually to be filled in by sparse values.
+            //Nothing bounds the count then, so it may not describe more elements than all buffers together hold
+            None => {
+                let buffer_bytes: usize = self.buffers.iter().map(Vec::len).sum();
+                match count.checked_mul(components) {
+                    Some(length) if length <= buffer_bytes => vec![0.0; length],
+                    _ => return Err(self.invalid(format!("accessor {} has {} elements but no buffer view to hold them", index, count))),
+                }
+            }
+        };
+
+        if let Some(sparse) = accessor.get("sparse") {
+            let sparse_count = self.index(sparse, "count")?;
+            let sparse_indices = sparse.get("indices").cloned().unwrap_or(Value::Null);
+            let sparse_values = sparse.get("values").cloned().unwrap_or(Value::Null);
+            let targets = self.read_elements(self.index(&sparse_indices, "bufferView")?,
+                                             self.optional_index(&sparse_indices, "byteOffset")?.unwrap_or(0),
+                                             sparse_count, self.integer(&sparse_indices, "componentType")?, false, 1, 1)?;
+            let substitutes = self.read_elements(self.index(&sparse_values, "bufferView")?,
+                                                 self.optional_index(&sparse_values, "byteOffset")?.unwrap_or(0),
+                                                 sparse_count, component_type, normalized, columns, rows)?;
+            for (i, &target) in targets.iter().enumerate() {
+                let target = target as usize;
+                if target >= count {
+                    return Err(self.invalid(format!("sparse index {} of accessor {} is out of range, it has {} elements", target, index, count)));
+                }
+                values[target * components..(target + 1) * components].copy_from_slice(&substitutes[i * components..(i + 1) * components]);
+            }
+        }
+
+        Ok((values, components))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn read_elements(&self, view_index: usize, byte_offset: usize, count: usize, component_type: u64, normalized: bool, columns: usize, rows: usize)
+                     -> Result<Vec<f64>, ModelLoadingError> {
+        let view = self.element("bufferViews", view_index)?;
+        let buffer_index = self.index(view, "buffer")?;
+        let buffer = self.buffers.get(buffer_index)
+            .ok_or_else(|| self.invalid(format!("buffer view {} refers to buffer {}, which does not exist", view_index, buffer_index)))?;
+        let view_offset = self.optional_index(view, "byteOffset")?.unwrap_or(0);
+        let view_length = self.index(view, "byteLength")?;
+        if view_offset.checked_add(view_length).map_or(true, |end| end > buffer.len()) {
+            return Err(self.invalid(format!("buffer view {} reaches past the end of buffer {}", view_index, buffer_index)));
+        }
+        let data = &buffer[view_offset..view_offset + view_length];
+
+        let component_size = match component_type {
+            BYTE | UNSIGNED_BYTE => 1,
+            SHORT | UNSIGNED_SHORT => 2,
+            UNSIGNED_INT | FLOAT => 4,
+            _ => return Err(self.invalid(format!("unknown component type {}", component_type))),
+        };
+        //Every column of a matrix starts on a 4 byte boundary
+        let column_size = if columns > 1 { (rows * component_size).div_ceil(4) * 4 } else { rows * component_size };
+        let element_size = column_size * columns;
+        let stride = match self.optional_index(view, "byteStride")? {
+            Some(stride) if stride > 0 => stride,
+            _ => element_size,
+        };
+        //The count comes from the file, so it is checked against the data before anything is allocated for it
+        let end = match count {
+            0 => Some(0),
+            _ => stride.checked_mul(count - 1).and_then(|last| last.checked_add(byte_offset)).and_then(|last| last.checked_add(element_size)),
+        };
+        if end.map_or(true, |end| end > data.len()) {
+            return Err(self.invalid(format!("{} elements do not fit in buffer view {}", count, view_index)));
+        }
+
+        let mut values = Vec::with_capacity(count * columns * rows);
+        for element in 0..count {
+            for column in 0..columns {
+                for row in 0..rows {
+                    let at = byte_offset + element * stride + column * column_size + row * component_size;
+                    values.push(read_component(&data[at..at + component_size], component_type, normalized));
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+//Normalized integers map to [0, 1] when unsigned and [-1, 1] when signed
+fn read_component(bytes: &[u8], component_type: u64, normalized: bool) -> f64 {
+    let (value, max) = match component_type {
+        BYTE => (f64::from(bytes[0] as i8), 127.0),
+        UNSIGNED_BYTE => (f64::from(bytes[0]), 255.0),
+        SHORT => (f64::from(i16::from_le_bytes([bytes[0], bytes[1]])), 32767.0),
+        UNSIGNED_SHORT => (f64::from(u16::from_le_bytes([bytes[0], bytes[1]])), 65535.0),
+        UNSIGNED_INT => (f64::from(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])), 4_294_967_295.0),
+        _ => return f64::from(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+    };
+    if normalized { (value / max).max(-1.0) } else { value }
+}
+
+//A .glb is a header followed by a JSON chunk and an optional binary chunk
+fn split_glb<'b>(bytes: &'b [u8], file_path: &str) -> Result<(&'b [u8], Option<&'b [u8]>), ModelLoadingError> {
+    let invalid = |description: &str| ModelLoadingError::InvalidData {
+        location: SourceLocation::file(file_path),
+        description: description.to_string(),
+    };
+    let read_u32 = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+
+    if bytes.len() < 12 {
+        return Err(invalid("the .glb header is incomplete"));
+    }
+    if read_u32(4) != 2 {
+        return Err(invalid("only version 2 .glb containers are supported"));
+    }
+    let length = (read_u32(8) as usize).min(bytes.len());
+
+    let mut json = None;
+    let mut binary = None;
+    let mut at = 12;
+    while at + 8 <= length {
+        let chunk_length = read_u32(at) as usize;
+        let chunk_type = read_u32(at + 4);
+        let start = at + 8;
+        if start + chunk_length > length {
+            return Err(invalid("a .glb chunk reaches past the end of the file"));
+        }
+        match chunk_type {
+            GLB_CHUNK_JSON if json.is_none() => json = Some(&bytes[start..start + chunk_length]),
+            GLB_CHUNK_BIN if binary.is_none() => binary = Some(&bytes[start..start + chunk_length]),
+            //Unknown chunks are meant to be skipped
+            _ => {}
+        }
+        at = start + chunk_length;
+    }
+
+    match json {
+        Some(json) => Ok((json, binary)),
+        None => Err(invalid("the .glb has no JSON chunk")),
+    }
+}
+
+//URIs of external files may escape characters like spaces
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+//Fills an attribute list with a neutral value up to the given length
+fn pad<T: Copy>(attributes: &mut Vec<T>, length: usize, value: T) {
+    if attributes.len() < length {
+        attributes.resize(length, value);
+    }
+}
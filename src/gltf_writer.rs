@@ -0,0 +1,272 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::models::*;
+use crate::mtl::{Material, TextureMap};
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const UNSIGNED_INT: u32 = 5125;
+const FLOAT: u32 = 5126;
+const MODE_POINTS: u32 = 0;
+const MODE_TRIANGLES: u32 = 4;
+
+impl ObjModel {
+    //Writes the model as a .gltf file with its data in a .bin file of the same name next to it.
+    //Models without vertices have no data and get no .bin file
+    pub fn save_gltf_to_file(&self, file_path: &str) -> io::Result<()> {
+        let path = Path::new(file_path);
+        let buffer_path = path.with_extension("bin");
+        let buffer_uri = buffer_path.file_name().map(|name| percent_encode(&name.to_string_lossy())).unwrap_or_default();
+        let (document, buffer) = self.gltf_document(Some(&buffer_uri));
+
+        if !buffer.is_empty() {
+            let mut buffer_writer = BufWriter::new(File::create(&buffer_path)?);
+            buffer_writer.write_all(&buffer)?;
+            buffer_writer.flush()?;
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &document)?;
+        writer.flush()
+    }
+
+    pub fn save_glb_to_file(&self, file_path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(file_path)?);
+        self.write_glb_to(&mut writer)?;
+        writer.flush()
+    }
+
+    //Writes the model as a binary .glb container
+    pub fn write_glb_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let (document, mut buffer) = self.gltf_document(None);
+        let mut json = serde_json::to_vec(&document)?;
+
+        //Both chunks have to be 4 byte aligned, JSON is padded with spaces and binary data with zeros
+        json.resize(json.len().div_ceil(4) * 4, b' ');
+        buffer.resize(buffer.len().div_ceil(4) * 4, 0);
+        let binary_chunk_size = if buffer.is_empty() { 0 } else { 8 + buffer.len() };
+
+        writer.write_all(b"glTF")?;
+        writer.write_all(&2u32.to_le_bytes())?;
+        writer.write_all(&((12 + 8 + json.len() + binary_chunk_size) as u32).to_le_bytes())?;
+        writer.write_all(&(json.len() as u32).to_le_bytes())?;
+        writer.write_all(b"JSON")?;
+        writer.write_all(&json)?;
+        if !buffer.is_empty() {
+            writer.write_all(&(buffer.len() as u32).to_le_bytes())?;
+            writer.write_all(b"BIN\0")?;
+            writer.write_all(&buffer)?;
+        }
+        Ok(())
+    }
+
+    //Builds the JSON part of the file and the single buffer it refers to, a missing uri means the buffer is the .glb binary chunk.
+    //Every sub-mesh becomes a primitive, all of them share the vertex attributes. Point clouds are one primitive of points
+    fn gltf_document(&self, buffer_uri: Option<&str>) -> (Value, Vec<u8>) {
+        let mut builder = BufferBuilder::default();
+        let mut document = json!({
+            "asset": { "version": "2.0", "generator": "vertexify" },
+        });
+        if self.vertices.is_empty() {
+            return (document, Vec::new());
+        }
+
+        let positions: Vec<[f32; 3]> = self.vertices.iter().map(|v| [v.position.x, v.position.y, v.position.z]).collect();
+        let mut attributes = json!({ "POSITION": builder.add_floats(&positions, "VEC3") });
+        if !self.normals.is_empty() {
+            let normals: Vec<[f32; 3]> = self.normals.iter().map(|n| unit_normal(n.normal)).collect();
+            attributes["NORMAL"] = builder.add_floats(&normals, "VEC3");
+        }
+        if !self.tex_coords.is_empty() {
+            //glTF puts the origin of texture space in the top left corner, OBJ in the bottom left one
+            let tex_coords: Vec<[f32; 2]> = self.tex_coords.iter().map(|uv| [uv.tex_coords.x, 1.0 - uv.tex_coords.y]).collect();
+            attributes["TEXCOORD_0"] = builder.add_floats(&tex_coords, "VEC2");
+        }
+        if !self.tangents.is_empty() {
+            let tangents: Vec<[f32; 4]> = self.tangents.iter().map(|t| [t.tangent.x, t.tangent.y, t.tangent.z, -t.tangent.w]).collect();
+            attributes["TANGENT"] = builder.add_floats(&tangents, "VEC4");
+        }
+        if !self.colors.is_empty() {
+            let colors: Vec<[f32; 4]> = self.colors.iter()
+                .map(|c| [unit(c.color.x), unit(c.color.y), unit(c.color.z), unit(c.color.w)])
+                .collect();
+            attributes["COLOR_0"] = builder.add_floats(&colors, "VEC4");
+        }
+
+        let mut primitives = Vec::new();
+        if self.indices.is_empty() {
+            primitives.push(json!({ "attributes": attributes, "mode": MODE_POINTS }));
+        } else {
+            let index_view = builder.add_view(self.indices.iter().flat_map(|i| i.to_le_bytes().to_vec()).collect(), Some(ELEMENT_ARRAY_BUFFER));
+            let whole_model = [SubMesh { index_range: 0..self.indices.len(), material_name: None, material: None }];
+            let sub_meshes = if self.sub_meshes.is_empty() { &whole_model[..] } else { &self.sub_meshes[..] };
+            for sub_mesh in sub_meshes.iter().filter(|s| !s.index_range.is_empty()) {
+                let indices = &self.indices[sub_mesh.index_range.clone()];
+                let accessor = builder.add_accessor(json!({
+                    "bufferView": index_view,
+                    "byteOffset": sub_mesh.index_range.start * 4,
+                    "componentType": UNSIGNED_INT,
+                    "count": indices.len(),
+                    "type": "SCALAR",
+                    "min": [indices.iter().min()],
+                    "max": [indices.iter().max()],
+                }));
+                let mut primitive = json!({ "attributes": attributes, "indices": accessor, "mode": MODE_TRIANGLES });
+                if let Some(material) = sub_mesh.material {
+                    primitive["material"] = json!(material);
+                }
+                primitives.push(primitive);
+            }
+        }
+
+        let name = self.objects.first().map(|o| o.name.as_str()).unwrap_or("mesh");
+        let mut images: Vec<&str> = Vec::new();
+        let materials: Vec<Value> = self.materials.iter().map(|m| gltf_material(m, &mut images)).collect();
+
+        let mut buffer = json!({ "byteLength": builder.data.len() });
+        if let Some(uri) = buffer_uri {
+            buffer["uri"] = json!(uri);
+        }
+        document["buffers"] = json!([buffer]);
+        document["bufferViews"] = Value::Array(builder.views);
+        document["accessors"] = Value::Array(builder.accessors);
+        document["meshes"] = json!([{ "name": name, "primitives": primitives }]);
+        document["nodes"] = json!([{ "name": name, "mesh": 0 }]);
+        document["scenes"] = json!([{ "nodes": [0] }]);
+        document["scene"] = json!(0);
+        if !materials.is_empty() {
+            document["materials"] = Value::Array(materials);
+        }
+        if !images.is_empty() {
+            document["images"] = images.iter().map(|path| json!({ "uri": percent_encode(path) })).collect();
+            document["textures"] = (0..images.len()).map(|i| json!({ "source": i })).collect();
+        }
+
+        (document, builder.data)
+    }
+}
+
+//Collects the binary data, buffer views and accessors of a document
+#[derive(Default)]
+struct BufferBuilder {
+    data: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl BufferBuilder {
+    //Views start on 4 byte boundaries so every component type is aligned
+    fn add_view(&mut self, bytes: Vec<u8>, target: Option<u32>) -> usize {
+        self.data.resize(self.data.len().div_ceil(4) * 4, 0);
+        let mut view = json!({ "buffer": 0, "byteOffset": self.data.len(), "byteLength": bytes.len() });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.data.extend_from_slice(&bytes);
+        self.views.push(view);
+        self.views.len() - 1
+    }
+
+    fn add_accessor(&mut self, accessor: Value) -> usize {
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    //Adds a vertex attribute in its own view, with the bounds of every component
+    fn add_floats<T: AsRef<[f32]>>(&mut self, elements: &[T], type_name: &str) -> Value {
+        let components = elements.first().map(|e| e.as_ref().len()).unwrap_or(0);
+        let mut min = vec![f32::INFINITY; components];
+        let mut max = vec![f32::NEG_INFINITY; components];
+        let mut bytes = Vec::with_capacity(elements.len() * components * 4);
+        for element in elements {
+            for (i, &value) in element.as_ref().iter().enumerate() {
+                min[i] = min[i].min(value);
+                max[i] = max[i].max(value);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        let view = self.add_view(bytes, Some(ARRAY_BUFFER));
+        json!(self.add_accessor(json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": elements.len(),
+            "type": type_name,
+            "min": min,
+            "max": max,
+        })))
+    }
+}
+
+//The inverse of the conversion done by the loader, OBJ materials without PBR values become rough dielectrics
+fn gltf_material<'m>(material: &'m Material, images: &mut Vec<&'m str>) -> Value {
+    let diffuse = material.diffuse.map(|d| [d.x, d.y, d.z]).unwrap_or([1.0, 1.0, 1.0]);
+    let alpha = material.dissolve.unwrap_or(1.0);
+    //Blinn-Phong exponent to roughness, the usual approximation when no roughness is given
+    let roughness = material.roughness
+        .or_else(|| material.shininess.map(|ns| (2.0 / (ns.max(0.0) + 2.0)).sqrt()))
+        .unwrap_or(1.0);
+
+    let mut pbr = json!({
+        "baseColorFactor": [unit(diffuse[0]), unit(diffuse[1]), unit(diffuse[2]), unit(alpha)],
+        "metallicFactor": unit(material.metallic.unwrap_or(0.0)),
+        "roughnessFactor": unit(roughness),
+    });
+    if let Some(map) = &material.diffuse_map {
+        pbr["baseColorTexture"] = texture_info(map, images);
+    }
+
+    let mut converted = json!({ "name": material.name, "pbrMetallicRoughness": pbr });
+    if let Some(emissive) = material.emissive {
+        converted["emissiveFactor"] = json!([unit(emissive.x), unit(emissive.y), unit(emissive.z)]);
+    }
+    if let Some(map) = &material.emissive_map {
+        converted["emissiveTexture"] = texture_info(map, images);
+    }
+    if let Some(map) = &material.normal_map {
+        converted["normalTexture"] = texture_info(map, images);
+        converted["normalTexture"]["scale"] = json!(map.options.bump_multiplier);
+    }
+    if alpha < 1.0 {
+        converted["alphaMode"] = json!("BLEND");
+    }
+    converted
+}
+
+//Every image gets one texture with the same index
+fn texture_info<'m>(map: &'m TextureMap, images: &mut Vec<&'m str>) -> Value {
+    let index = match images.iter().position(|&path| path == map.path) {
+        Some(index) => index,
+        None => {
+            images.push(&map.path);
+            images.len() - 1
+        }
+    };
+    json!({ "index": index })
+}
+
+fn unit(value: f32) -> f32 {
+    value.clamp(0.0, 1.0)
+}
+
+//Normals have to be unit length, degenerate ones get an arbitrary direction
+fn unit_normal(normal: straal::Vec3n) -> [f32; 3] {
+    let normal = ObjModel::safe_normalized(normal);
+    if normal.dot(normal) > 0.0 { [normal.x, normal.y, normal.z] } else { [0.0, 0.0, 1.0] }
+}
+
+//URIs can not contain spaces and a few other characters, paths are written with those escaped
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
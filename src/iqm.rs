@@ -0,0 +1,339 @@
+use std::fs;
+
+use straal::{Vec2n, Vec3n, Vec4n};
+
+use crate::binary::{BinaryReader, y_up};
+use crate::error::{BYTES_SOURCE_NAME, ModelLoadingError, SourceLocation};
+use crate::models::*;
+use crate::skeleton::normalized_quaternion;
+
+const IQM_MAGIC: &[u8] = b"INTERQUAKEMODEL\0";
+const IQM_VERSION: usize = 2;
+
+const IQM_MESH_SIZE: usize = 24;
+const IQM_VERTEX_ARRAY_SIZE: usize = 20;
+const IQM_TRIANGLE_SIZE: usize = 12;
+const IQM_JOINT_SIZE: usize = 48;
+const IQM_POSE_SIZE: usize = 88;
+const IQM_ANIM_SIZE: usize = 20;
+const IQM_LOOP: usize = 1;
+//Three positions of one byte each
+const IQM_MIN_VERTEX_SIZE: usize = 3;
+
+//Vertex array types
+const IQM_POSITION: usize = 0;
+const IQM_TEXCOORD: usize = 1;
+const IQM_NORMAL: usize = 2;
+const IQM_TANGENT: usize = 3;
+const IQM_BLENDINDEXES: usize = 4;
+const IQM_BLENDWEIGHTS: usize = 5;
+const IQM_COLOR: usize = 6;
+
+//Vertex array formats
+const IQM_BYTE: usize = 0;
+const IQM_UBYTE: usize = 1;
+const IQM_SHORT: usize = 2;
+const IQM_USHORT: usize = 3;
+const IQM_INT: usize = 4;
+const IQM_UINT: usize = 5;
+const IQM_HALF: usize = 6;
+const IQM_FLOAT: usize = 7;
+const IQM_DOUBLE: usize = 8;
+
+//Translation, rotation quaternion and scale
+const POSE_CHANNELS: usize = 10;
+
+//The components of one vertex array, `size` per vertex
+struct VertexArray {
+    size: usize,
+    values: Vec<f32>,
+}
+
+impl VertexArray {
+    //Missing components get the default of the corresponding slot
+    fn get(&self, vertex: usize, defaults: &[f32]) -> Vec<f32> {
+        (0..defaults.len()).map(|c| if c < self.size { self.values[vertex * self.size + c] } else { defaults[c] }).collect()
+    }
+}
+
+impl SkinnedModel {
+    //Loads an Inter-Quake Model with its skeleton and animations, every mesh of the file becomes an object
+    //and a sub-mesh named after its material. Like the Quake formats it is converted from Z up to Y up
+    pub fn load_iqm_from_file(file_path: &str) -> Result<SkinnedModel, ModelLoadingError> {
+        let bytes = fs::read(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        SkinnedModel::parse_iqm(&bytes, file_path)
+    }
+
+    pub fn from_iqm_bytes(bytes: &[u8]) -> Result<SkinnedModel, ModelLoadingError> {
+        SkinnedModel::parse_iqm(bytes, BYTES_SOURCE_NAME)
+    }
+
+    fn parse_iqm(bytes: &[u8], file_path: &str) -> Result<SkinnedModel, ModelLoadingError> {
+        let reader = BinaryReader { bytes, file_path };
+        if reader.bytes(0, IQM_MAGIC.len())? != IQM_MAGIC {
+            return Err(reader.invalid("the file does not start with the IQM identifier INTERQUAKEMODEL".to_string()));
+        }
+        let version = reader.count(16, "version")?;
+        if version != IQM_VERSION {
+            return Err(reader.invalid(format!("IQM version {} is not supported, only version {} is", version, IQM_VERSION)));
+        }
+        let text_size = reader.count(28, "text size")?;
+        let text_offset = reader.count(32, "text offset")?;
+        let mesh_count = reader.record_count(36, IQM_MESH_SIZE, "number of meshes")?;
+        let meshes_offset = reader.count(40, "mesh offset")?;
+        let vertex_array_count = reader.record_count(44, IQM_VERTEX_ARRAY_SIZE, "number of vertex arrays")?;
+        let vertex_count = reader.record_count(48, IQM_MIN_VERTEX_SIZE, "number of vertices")?;
+        let vertex_arrays_offset = reader.count(52, "vertex array offset")?;
+        let triangle_count = reader.record_count(56, IQM_TRIANGLE_SIZE, "number of triangles")?;
+        let triangles_offset = reader.count(60, "triangle offset")?;
+        let joint_count = reader.record_count(68, IQM_JOINT_SIZE, "number of joints")?;
+        let joints_offset = reader.count(72, "joint offset")?;
+        let pose_count = reader.record_count(76, IQM_POSE_SIZE, "number of poses")?;
+        let poses_offset = reader.count(80, "pose offset")?;
+        let anim_count = reader.record_count(84, IQM_ANIM_SIZE, "number of animations")?;
+        let anims_offset = reader.count(88, "animation offset")?;
+        let frame_channel_count = reader.record_count(96, 2, "number of frame channels")?;
+        let frame_count = reader.record_count(92, frame_channel_count * 2, "number of frames")?;
+        //Frames without animated channels take no space in the file but still hold a pose per joint
+        if frame_count > 0 && frame_channel_count == 0 {
+            return Err(reader.invalid(format!("the file has {} frames but no animated channels", frame_count)));
+        }
+        let frames_offset = reader.count(100, "frame offset")?;
+
+        //Names are offsets into a block of zero terminated strings
+        let text_block = reader.bytes(text_offset, text_size)?;
+        let text = |offset: usize| -> Result<String, ModelLoadingError> {
+            let rest = text_block.get(offset..).ok_or_else(|| reader.invalid(format!("text offset {} is past the {} bytes of text", offset, text_size)))?;
+            let length = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+            Ok(String::from_utf8_lossy(&rest[..length]).into_owned())
+        };
+
+        let mut arrays: [Option<VertexArray>; 7] = [None, None, None, None, None, None, None];
+        for array in 0..vertex_array_count {
+            let offset = vertex_arrays_offset + array * IQM_VERTEX_ARRAY_SIZE;
+            let array_type = reader.count(offset, "vertex array type")?;
+            let format = reader.count(offset + 8, "vertex array format")?;
+            let size = reader.count(offset + 12, "vertex array size")?;
+            let data_offset = reader.count(offset + 16, "vertex array data offset")?;
+            //Custom arrays are left alone
+            if array_type >= arrays.len() {
+                continue;
+            }
+            let component_size = format_size(format).ok_or_else(|| reader.invalid(format!("vertex array {} has the unknown format {}", array, format)))?;
+            //Weights and colors stored as integers are fractions of the largest value of their type
+            let normalize = array_type == IQM_BLENDWEIGHTS || array_type == IQM_COLOR;
+            if vertex_count.checked_mul(size).and_then(|n| n.checked_mul(component_size)).map_or(true, |n| n > bytes.len()) {
+                return Err(reader.invalid(format!("vertex array {} has {} components for each of {} vertices, more than the file can hold", array, size, vertex_count)));
+            }
+            let values = (0..vertex_count * size)
+                .map(|i| read_component(&reader, format, data_offset + i * component_size, normalize))
+                .collect::<Result<Vec<f32>, _>>()?;
+            arrays[array_type] = Some(VertexArray { size, values });
+        }
+
+        let positions = arrays[IQM_POSITION].as_ref().ok_or_else(|| reader.invalid("the file has no vertex positions".to_string()))?;
+        let mut model = ObjModel::empty();
+        for vertex in 0..vertex_count {
+            let p = positions.get(vertex, &[0.0, 0.0, 0.0]);
+            model.vertices.push(Vertex { position: y_up(Vec3n::new(p[0], p[1], p[2])) });
+            //Texture coordinates start at the top left
+            if let Some(array) = &arrays[IQM_TEXCOORD] {
+                let uv = array.get(vertex, &[0.0, 0.0]);
+                model.tex_coords.push(UV { tex_coords: Vec2n { x: uv[0], y: 1.0 - uv[1] } });
+            }
+            if let Some(array) = &arrays[IQM_NORMAL] {
+                let n = array.get(vertex, &[0.0, 0.0, 0.0]);
+                model.normals.push(Normal { normal: y_up(Vec3n::new(n[0], n[1], n[2])) });
+            }
+            if let Some(array) = &arrays[IQM_TANGENT] {
+                let t = array.get(vertex, &[0.0, 0.0, 0.0, 1.0]);
+                let direction = y_up(Vec3n::new(t[0], t[1], t[2]));
+                //Flipping v mirrors the bitangent, so the handedness changes sign
+                model.tangents.push(Tangent { tangent: Vec4n::new(direction.x, direction.y, direction.z, -t[3]) });
+            }
+            if let Some(array) = &arrays[IQM_COLOR] {
+                let c = array.get(vertex, &[1.0, 1.0, 1.0, 1.0]);
+                model.colors.push(Color { color: Vec4n::new(c[0], c[1], c[2], c[3]) });
+            }
+        }
+
+        let mut joint_weights = Vec::new();
+        if let (Some(indices), Some(weights), true) = (&arrays[IQM_BLENDINDEXES], &arrays[IQM_BLENDWEIGHTS], joint_count > 0) {
+            for vertex in 0..vertex_count {
+                let i = indices.get(vertex, &[0.0; 4]);
+                let w = weights.get(vertex, &[0.0; 4]);
+                if let Some(&joint) = i.iter().find(|&&joint| joint as usize >= joint_count) {
+                    return Err(reader.invalid(format!("vertex {} is weighted to joint {}, only {} are defined", vertex, joint, joint_count)));
+                }
+                joint_weights.push(JointWeights {
+                    joints: [i[0] as u32, i[1] as u32, i[2] as u32, i[3] as u32],
+                    weights: Vec4n::new(w[0], w[1], w[2], w[3]),
+                });
+            }
+        }
+
+        for triangle in 0..triangle_count {
+            let offset = triangles_offset + triangle * IQM_TRIANGLE_SIZE;
+            //IQM triangles are clockwise like the Quake formats
+            for &corner in &[0, 2, 1] {
+                let index = reader.count(offset + corner * 4, "vertex index")?;
+                if index >= vertex_count {
+                    return Err(reader.invalid(format!("triangle {} refers to vertex {}, only {} are defined", triangle, index, vertex_count)));
+                }
+                model.indices.push(index as u32);
+            }
+        }
+
+        for mesh in 0..mesh_count {
+            let offset = meshes_offset + mesh * IQM_MESH_SIZE;
+            let name = text(reader.count(offset, "mesh name")?)?;
+            let material = text(reader.count(offset + 4, "mesh material")?)?;
+            let first_triangle = reader.count(offset + 16, "first triangle")?;
+            let mesh_triangles = reader.count(offset + 20, "number of mesh triangles")?;
+            if first_triangle + mesh_triangles > triangle_count {
+                return Err(reader.invalid(format!("mesh {} uses triangles {} to {}, only {} are defined", name, first_triangle, first_triangle + mesh_triangles, triangle_count)));
+            }
+            let index_range = first_triangle * 3..(first_triangle + mesh_triangles) * 3;
+            let mut object = NamedPart { name, index_ranges: Vec::new() };
+            object.extend(index_range.clone());
+            model.objects.push(object);
+            let material_name = if material.is_empty() { None } else { Some(material) };
+            model.sub_meshes.push(SubMesh { index_range, material_name, material: None });
+        }
+        if model.sub_meshes.is_empty() {
+            model.sub_meshes.push(SubMesh { index_range: 0..model.indices.len(), material_name: None, material: None });
+        }
+        //Generating normals reorders the vertices, which would separate them from their joint weights,
+        //so they are smoothed on a copy of the mesh and copied back per corner
+        if model.normals.is_empty() {
+            let mut smoothed = ObjModel { indices: model.indices.clone(), vertices: model.vertices.clone(), ..ObjModel::empty() };
+            smoothed.generate_normals();
+            model.normals = vec![Normal { normal: Vec3n::zero() }; model.vertices.len()];
+            for (&index, &smoothed_index) in model.indices.iter().zip(smoothed.indices.iter()) {
+                model.normals[index as usize] = smoothed.normals[smoothed_index as usize];
+            }
+        }
+
+        let mut joints = Vec::new();
+        for joint in 0..joint_count {
+            let offset = joints_offset + joint * IQM_JOINT_SIZE;
+            let name = text(reader.count(offset, "joint name")?)?;
+            let parent = reader.i32(offset + 4)?;
+            if parent >= joint as i32 {
+                return Err(reader.invalid(format!("joint {} has parent {}, parents have to come before their children", name, parent)));
+            }
+            let mut channels = [0.0; POSE_CHANNELS];
+            for (c, channel) in channels.iter_mut().enumerate() {
+                *channel = reader.f32(offset + 8 + c * 4)?;
+            }
+            joints.push(Joint { name, parent: if parent < 0 { None } else { Some(parent as usize) }, bind_pose: joint_pose(&channels) });
+        }
+
+        //Every frame stores the animated channels of all poses, the other channels keep their offset
+        if frame_count > 0 && pose_count != joint_count {
+            return Err(reader.invalid(format!("the animations have {} poses for {} joints", pose_count, joint_count)));
+        }
+        let mut poses = Vec::new();
+        for pose in 0..pose_count {
+            let offset = poses_offset + pose * IQM_POSE_SIZE;
+            let mask = reader.i32(offset + 4)? as u32;
+            let mut channel_offsets = [0.0; POSE_CHANNELS];
+            let mut channel_scales = [0.0; POSE_CHANNELS];
+            for c in 0..POSE_CHANNELS {
+                channel_offsets[c] = reader.f32(offset + 8 + c * 4)?;
+                channel_scales[c] = reader.f32(offset + 8 + (POSE_CHANNELS + c) * 4)?;
+            }
+            poses.push((mask, channel_offsets, channel_scales));
+        }
+        let animated_channels: usize = poses.iter().map(|(mask, _, _)| (mask & ((1 << POSE_CHANNELS) - 1)).count_ones() as usize).sum();
+        if frame_count > 0 && animated_channels != frame_channel_count {
+            return Err(reader.invalid(format!("the poses animate {} channels, the frames have {}", animated_channels, frame_channel_count)));
+        }
+        let mut frames = Vec::new();
+        let mut at = frames_offset;
+        for _ in 0..frame_count {
+            let mut frame = Vec::with_capacity(poses.len());
+            for (mask, channel_offsets, channel_scales) in &poses {
+                let mut channels = *channel_offsets;
+                for (c, channel) in channels.iter_mut().enumerate() {
+                    if mask & (1 << c) != 0 {
+                        *channel += reader.u16(at)? as f32 * channel_scales[c];
+                        at += 2;
+                    }
+                }
+                frame.push(joint_pose(&channels));
+            }
+            frames.push(frame);
+        }
+
+        let mut animations = Vec::new();
+        for anim in 0..anim_count {
+            let offset = anims_offset + anim * IQM_ANIM_SIZE;
+            let name = text(reader.count(offset, "animation name")?)?;
+            let first_frame = reader.count(offset + 4, "first frame")?;
+            let anim_frames = reader.count(offset + 8, "number of animation frames")?;
+            if first_frame + anim_frames > frame_count {
+                return Err(reader.invalid(format!("animation {} uses frames {} to {}, only {} are defined", name, first_frame, first_frame + anim_frames, frame_count)));
+            }
+            animations.push(AnimationClip {
+                name,
+                frame_rate: reader.f32(offset + 12)?,
+                looping: reader.count(offset + 16, "animation flags")? & IQM_LOOP != 0,
+                frames: frames[first_frame..first_frame + anim_frames].to_vec(),
+            });
+        }
+
+        Ok(SkinnedModel { model, joint_weights, joints, animations })
+    }
+}
+
+//Translation, rotation and scale in Z up, the scale axes are swapped along with the others
+fn joint_pose(channels: &[f32; POSE_CHANNELS]) -> JointPose {
+    let rotation = y_up(Vec3n::new(channels[3], channels[4], channels[5]));
+    JointPose {
+        translation: y_up(Vec3n::new(channels[0], channels[1], channels[2])),
+        rotation: normalized_quaternion(Vec4n::new(rotation.x, rotation.y, rotation.z, channels[6])),
+        scale: Vec3n::new(channels[7], channels[9], channels[8]),
+    }
+}
+
+fn format_size(format: usize) -> Option<usize> {
+    match format {
+        IQM_BYTE | IQM_UBYTE => Some(1),
+        IQM_SHORT | IQM_USHORT | IQM_HALF => Some(2),
+        IQM_INT | IQM_UINT | IQM_FLOAT => Some(4),
+        IQM_DOUBLE => Some(8),
+        _ => None,
+    }
+}
+
+//Integers are divided by the largest value of their type when normalize is set
+fn read_component(reader: &BinaryReader, format: usize, offset: usize, normalize: bool) -> Result<f32, ModelLoadingError> {
+    let b = reader.bytes(offset, format_size(format).unwrap_or(0))?;
+    let (value, max) = match format {
+        IQM_BYTE => (b[0] as i8 as f32, i8::MAX as f32),
+        IQM_UBYTE => (b[0] as f32, u8::MAX as f32),
+        IQM_SHORT => (i16::from_le_bytes([b[0], b[1]]) as f32, i16::MAX as f32),
+        IQM_USHORT => (u16::from_le_bytes([b[0], b[1]]) as f32, u16::MAX as f32),
+        IQM_INT => (i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32, i32::MAX as f32),
+        IQM_UINT => (u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32, u32::MAX as f32),
+        IQM_HALF => return Ok(half_to_f32(u16::from_le_bytes([b[0], b[1]]))),
+        IQM_FLOAT => return Ok(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        _ => return Ok(f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32),
+    };
+    Ok(if normalize { (value / max).max(-1.0) } else { value })
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1F) as i32;
+    let mantissa = (half & 0x3FF) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 => if mantissa == 0.0 { f32::INFINITY } else { f32::NAN },
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
@@ -1,13 +1,43 @@
 #[macro_use]
 extern crate glium;
 extern crate straal;
+extern crate serde_json;
+extern crate base64;
+extern crate flate2;
+extern crate roxmltree;
 
-pub use models::ObjModel;
+pub use error::{ModelLoadingError, SourceLocation};
+pub use models::{MorphModel, ObjModel, SkinnedModel};
+pub use mtl::Material;
 
+mod binary;
+mod collada;
+pub mod error;
+mod fbx;
+mod gltf;
+mod gltf_writer;
+mod iqm;
 pub mod models;
+mod morph;
+pub mod mtl;
+mod off;
+mod off_writer;
+mod ply;
+mod ply_writer;
+mod point_cloud;
+mod quake;
+mod skeleton;
+mod stl;
+mod stl_writer;
+mod tangents;
+mod text;
+mod three_ds;
+mod triangulation;
+mod writer;
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
     use std::time::{Duration, SystemTime};
 
     use glium::{glutin, Surface};
@@ -18,7 +48,10 @@ mod tests {
     use glutin::MouseScrollDelta;
     use glutin::VirtualKeyCode;
 
+    use super::error::*;
     use super::models::*;
+    use super::mtl::*;
+    use super::triangulation::triangulate;
 
     #[test]
     fn load_obj_file_v() {
@@ -44,6 +77,1273 @@ mod tests {
         println!("{:?}", quad);
     }
 
+    #[test]
+    fn report_malformed_obj_lines() {
+        let file_path = std::env::temp_dir().join(format!("vertexify_malformed_{}.obj", std::process::id()));
+        let file_path = file_path.to_str().unwrap();
+
+        std::fs::write(file_path, "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\nv 0.0 1.x 0.0\n").unwrap();
+        match ObjModel::load_from_file(file_path) {
+            Err(ModelLoadingError::ParseFloat { location, text, .. }) => {
+                assert_eq!((location.line, location.column), (6, 7));
+                assert_eq!(text, "1.x");
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        std::fs::write(file_path, "v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 4\n").unwrap();
+        match ObjModel::load_from_file(file_path) {
+            Err(ModelLoadingError::IndexOutOfRange { location, index, count, .. }) => {
+                assert_eq!((location.line, location.column), (4, 7));
+                assert_eq!((index, count), (4, 3));
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        std::fs::write(file_path, "v 0 0 0\nv 1 0\n").unwrap();
+        assert!(match ObjModel::load_from_file(file_path) {
+            Err(ModelLoadingError::MissingComponents { expected: 3, found: 2, .. }) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn resolve_relative_obj_indices() {
+        let file_path = std::env::temp_dir().join(format!("vertexify_relative_{}.obj", std::process::id()));
+        let file_path = file_path.to_str().unwrap();
+
+        std::fs::write(file_path, "v 0 0 0\nv 1 0 0\nv 1 1 0\nf -3 -2 -1\nv 0 1 0\nf 1 -2 -1\n").unwrap();
+        let relative = ObjModel::load_from_file(file_path).unwrap();
+        std::fs::write(file_path, "v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 3\nv 0 1 0\nf 1 3 4\n").unwrap();
+        let absolute = ObjModel::load_from_file(file_path).unwrap();
+        assert_eq!(format!("{:?}", relative), format!("{:?}", absolute));
+
+        std::fs::write(file_path, "v 0 0 0\nv 1 0 0\nf -1 -2 -3\n").unwrap();
+        match ObjModel::load_from_file(file_path) {
+            Err(ModelLoadingError::IndexOutOfRange { location, index, count, .. }) => {
+                assert_eq!(location.line, 3);
+                assert_eq!((index, count), (-3, 2));
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_obj_from_memory() {
+        let from_file = ObjModel::load_from_file("res/quad.obj").unwrap();
+        let from_bytes = ObjModel::from_bytes(include_bytes!("../res/quad.obj")).unwrap();
+        let from_str = ObjModel::from_str(include_str!("../res/quad.obj")).unwrap();
+        let from_reader = ObjModel::from_reader(std::io::Cursor::new(include_bytes!("../res/quad.obj").to_vec())).unwrap();
+        let expected = format!("{:?}", from_file);
+        assert_eq!(format!("{:?}", from_bytes), expected);
+        assert_eq!(format!("{:?}", from_str), expected);
+        assert_eq!(format!("{:?}", from_reader), expected);
+
+        match ObjModel::from_str("v 0 0 0\nvp 1 0\n") {
+            Err(ModelLoadingError::UnsupportedStatement { location, text }) => {
+                assert_eq!(location.line, 2);
+                assert_eq!(text, "vp");
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_mtl_library() {
+        let dir = std::env::temp_dir().join(format!("vertexify_mtl_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("materials.mtl"), "\
+            # Two materials\n\
+            newmtl painted metal\n\
+            Ka 0.1\n\
+            Kd 0.8 0.2 0.2\n\
+            Tr 0.25\n\
+            illum 2\n\
+            Pr 0.4\n\
+            Pm 1.0\n\
+            map_Kd -s 2 2 -o 0.5 0.5 0 -blendu off textures/paint albedo.png\n\
+            map_Bump -bm 0.3 normal.png\n\
+            newmtl glass\n\
+            d -halo 0.6\n\
+            map_Pr rough.png\n").unwrap();
+        std::fs::write(dir.join("model.obj"), "mtllib materials.mtl missing.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 3\n").unwrap();
+
+        let model = ObjModel::load_from_file(dir.join("model.obj").to_str().unwrap()).unwrap();
+        assert_eq!(model.material_libraries(), &["materials.mtl".to_string(), "missing.mtl".to_string()]);
+
+        let materials = model.materials();
+        assert_eq!(materials.len(), 2);
+        let metal = &materials[0];
+        assert_eq!(metal.name, "painted metal");
+        assert_eq!(metal.ambient.unwrap().y, 0.1);
+        assert_eq!(metal.diffuse.unwrap().x, 0.8);
+        assert_eq!(metal.dissolve, Some(0.75));
+        assert_eq!(metal.illumination_model, Some(2));
+        assert_eq!((metal.roughness, metal.metallic), (Some(0.4), Some(1.0)));
+        let diffuse_map = metal.diffuse_map.as_ref().unwrap();
+        assert_eq!(diffuse_map.path, "textures/paint albedo.png");
+        assert_eq!((diffuse_map.options.scale.x, diffuse_map.options.scale.z), (2.0, 1.0));
+        assert_eq!(diffuse_map.options.offset.x, 0.5);
+        assert!(!diffuse_map.options.blend_u);
+        assert_eq!(metal.bump_map.as_ref().unwrap().options.bump_multiplier, 0.3);
+
+        let glass = &materials[1];
+        assert_eq!(glass.dissolve, Some(0.6));
+        assert_eq!(glass.roughness_map.as_ref().unwrap().path, "rough.png");
+    }
+
+    #[test]
+    fn split_obj_into_sub_meshes() {
+        let materials = Material::load_library_from_reader("newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\n".as_bytes()).unwrap();
+        let model = ObjModel::from_reader_with_materials("\
+            v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+            f 1 2 3\n\
+            usemtl red\n\
+            f 1 2 3 4\n\
+            usemtl blue\n\
+            f 1 2 3\n\
+            usemtl unknown\n\
+            f 1 3 4\n".as_bytes(), materials).unwrap();
+
+        let sub_meshes = model.sub_meshes();
+        assert_eq!(sub_meshes.len(), 4);
+        assert_eq!(sub_meshes.iter().map(|s| s.index_range.clone()).collect::<Vec<_>>(), vec![0..3, 3..9, 9..12, 12..15]);
+        assert_eq!(sub_meshes.iter().map(|s| s.material).collect::<Vec<_>>(), vec![None, Some(0), Some(1), None]);
+        assert_eq!(sub_meshes[3].material_name.as_ref().unwrap(), "unknown");
+
+        let quad = ObjModel::load_from_file("res/quad.obj").unwrap();
+        assert_eq!(quad.sub_meshes().len(), 1);
+        assert_eq!(quad.sub_meshes()[0].index_range, 0..6);
+    }
+
+    #[test]
+    fn keep_obj_objects_and_groups() {
+        let model = ObjModel::from_str("\
+            v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 5 5 5\n\
+            o car body\n\
+            g body paint\n\
+            f 1 2 3 4\n\
+            o wheel_left\n\
+            g wheels paint\n\
+            f 1 2 5\n\
+            g\n\
+            f 2 3 5\n").unwrap();
+
+        let objects = model.objects();
+        assert_eq!(objects.iter().map(|o| o.name.as_str()).collect::<Vec<_>>(), vec!["car body", "wheel_left"]);
+        assert_eq!(objects[0].index_ranges, vec![0..6]);
+        assert_eq!(objects[1].index_ranges, vec![6..12]);
+
+        let groups = model.groups();
+        assert_eq!(groups.iter().map(|g| g.name.as_str()).collect::<Vec<_>>(), vec!["body", "paint", "wheels", "default"]);
+        assert_eq!(groups[1].index_ranges, vec![0..9]);
+        assert_eq!(groups[3].index_ranges, vec![9..12]);
+
+        let wheel = model.extract_object("wheel_left").unwrap();
+        assert_eq!(wheel.objects().len(), 1);
+        assert_eq!(wheel.objects()[0].index_ranges, vec![0..6]);
+        assert_eq!(wheel.groups().iter().map(|g| g.name.as_str()).collect::<Vec<_>>(), vec!["paint", "wheels", "default"]);
+        assert_eq!(wheel.sub_meshes()[0].index_range, 0..6);
+        assert_eq!(format!("{:?}", wheel).matches("Vertex {").count(), 4);
+        assert!(model.extract_object("wheel_right").is_none());
+    }
+
+    #[test]
+    fn generate_normals_per_smoothing_group() {
+        //The cone is written with s off, so generated normals have to match the stored flat normals
+        let stored = ObjModel::load_from_file("res/cone_flat_shaded.obj").unwrap();
+        let mut generated = ObjModel::load_from_file("res/cone_flat_shaded.obj").unwrap();
+        generated.generate_normals();
+        assert_eq!(generated.indices().len(), stored.indices().len());
+        for (&s, &g) in stored.indices().iter().zip(generated.indices().iter()) {
+            let stored_normal = stored.normals()[s as usize].normal;
+            let generated_normal = generated.normals()[g as usize].normal;
+            assert!(stored_normal.dot(generated_normal) > 0.999);
+        }
+
+        //Two faces in the same group share the vertices of their edge, a third one in another group does not
+        let mut model = ObjModel::from_str("\
+            v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 1\n\
+            s 1\n\
+            f 1 2 3\nf 1 3 4\n\
+            s 2\n\
+            f 1 4 2\n").unwrap();
+        assert_eq!(model.smoothing_groups(), &[1, 1, 2]);
+        model.generate_normals();
+        assert_eq!(model.vertices().len(), 7);
+        let indices = model.indices();
+        assert_eq!((indices[0], indices[2]), (indices[3], indices[4]));
+        assert_ne!(indices[0], indices[6]);
+    }
+
+    const CUBE: &str = "\
+        v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\n\
+        v -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1\n\
+        f 1 4 3 2\nf 5 6 7 8\nf 1 2 6 5\nf 2 3 7 6\nf 3 4 8 7\nf 4 1 5 8\n";
+
+    #[test]
+    fn generate_normals_with_crease_angle() {
+        let mut smooth = ObjModel::from_str(CUBE).unwrap();
+        smooth.generate_normals();
+        assert_eq!(smooth.vertices().len(), 8);
+
+        //Cube faces meet at 90 degrees, so every corner gets the normal of its own face
+        let mut creased = ObjModel::from_str(CUBE).unwrap();
+        creased.generate_normals_with_crease_angle(std::f32::consts::PI / 4.0);
+        assert_eq!(creased.vertices().len(), 24);
+        assert_eq!(creased.normals().len(), 24);
+        for triangle in creased.indices().chunks(3) {
+            let v0 = creased.vertices()[triangle[0] as usize].position;
+            let v1 = creased.vertices()[triangle[1] as usize].position;
+            let v2 = creased.vertices()[triangle[2] as usize].position;
+            let face_normal = (v1 - v0).cross(v2 - v0).normalized();
+            for &index in triangle {
+                assert!(creased.normals()[index as usize].normal.dot(face_normal) > 0.999);
+            }
+        }
+
+        //A crease angle wider than the corners keeps everything smooth
+        let mut wide = ObjModel::from_str(CUBE).unwrap();
+        wide.generate_normals_with_crease_angle(std::f32::consts::PI * 0.75);
+        assert_eq!(wide.vertices().len(), 8);
+    }
+
+    #[test]
+    fn weight_generated_normals() {
+        //A large face facing up and a sliver facing along -x share the vertex at the origin, both at a right angle
+        let source = "v 0 0 0\nv 0 0 -10\nv -10 0 0\nv 0 0.01 0\nf 1 2 3\nf 1 4 2\n";
+        let normal_at_origin = |weighting: NormalWeighting| {
+            let mut model = ObjModel::from_str(source).unwrap();
+            model.generate_normals_weighted(weighting, None);
+            let origin = model.indices()[0] as usize;
+            model.normals()[origin].normal
+        };
+
+        let uniform = normal_at_origin(NormalWeighting::Uniform);
+        assert!((uniform.x + uniform.y).abs() < 1e-5);
+        let area = normal_at_origin(NormalWeighting::Area);
+        assert!(area.y > 0.999);
+        let angle = normal_at_origin(NormalWeighting::Angle);
+        assert!((angle.x + angle.y).abs() < 1e-5);
+        let area_angle = normal_at_origin(NormalWeighting::AreaAngle);
+        assert!(area_angle.y > 0.999);
+
+        //A small face with a right angle at the origin and a long face with a narrow one, facing along -x
+        let source = "v 0 0 0\nv 0 0 -1\nv -1 0 0\nv 0 1 -10\nv 0 0 -10\nf 1 2 3\nf 1 4 5\n";
+        let normal_at_origin = |weighting: NormalWeighting| {
+            let mut model = ObjModel::from_str(source).unwrap();
+            model.generate_normals_weighted(weighting, None);
+            model.normals()[model.indices()[0] as usize].normal
+        };
+        let uniform = normal_at_origin(NormalWeighting::Uniform);
+        let angle = normal_at_origin(NormalWeighting::Angle);
+        let area = normal_at_origin(NormalWeighting::Area);
+        assert!((uniform.x + uniform.y).abs() < 1e-5);
+        assert!(angle.y > 0.99 && angle.y - uniform.y > 0.2);
+        assert!(area.x < -0.99);
+    }
+
+    #[test]
+    fn generate_tangents_along_u() {
+        let mut quad = ObjModel::load_from_file("res/quad.obj").unwrap();
+        quad.generate_tangents();
+        assert_eq!(quad.tangents().len(), quad.vertices().len());
+        for tangent in quad.tangents() {
+            let t = tangent.tangent;
+            assert!((t.x - 1.0).abs() < 1e-5 && t.y.abs() < 1e-5 && t.z.abs() < 1e-5);
+            assert_eq!(t.w, 1.0);
+        }
+
+        //The right half mirrors u, so the shared edge is split and the mirrored side gets the opposite handedness
+        let source = "v 0 0 0\nv 1 0 0\nv 2 0 0\nv 0 1 0\nv 1 1 0\nv 2 1 0\n\
+                      vt 0 0\nvt 1 0\nvt 0 1\nvt 1 1\n\
+                      f 1/1 2/2 5/4 4/3\nf 2/2 3/1 6/3 5/4\n";
+        let mut mirrored = ObjModel::from_str(source).unwrap();
+        mirrored.generate_tangents();
+        assert_eq!(mirrored.vertices().len(), 8);
+        for (vertex, tangent) in mirrored.vertices().iter().zip(mirrored.tangents()) {
+            let t = tangent.tangent;
+            let left = vertex.position.x < 0.5 || (vertex.position.x < 1.5 && t.w > 0.0);
+            assert_eq!(t.w, if left { 1.0 } else { -1.0 });
+            assert!((t.x - t.w).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn load_obj_vertex_colors() {
+        let source = "v 0 0 0 1 0 0\nv 1 0 0 0 1 0 0.5\nv 0 1 0 1\nv 1 1 0 1 0 0 1 0.25\nf 1 2 3\nf 2 4 3\n";
+        let model = ObjModel::from_str(source).unwrap();
+        //Vertices are numbered in the order the faces first use them, which matches the file here
+        let expected = [(1.0, 0.0, 0.0, 1.0), (0.0, 1.0, 0.0, 0.5), (1.0, 1.0, 1.0, 1.0), (0.0, 0.0, 1.0, 0.25)];
+        assert_eq!(model.colors().len(), expected.len());
+        for (color, expected) in model.colors().iter().zip(expected.iter()) {
+            let color = color.color;
+            assert_eq!((color.x, color.y, color.z, color.w), *expected);
+        }
+        //The weight of the third vertex does not end up in its position
+        assert_eq!(model.vertices()[model.indices()[2] as usize].position.z, 0.0);
+
+        assert!(ObjModel::from_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap().colors().is_empty());
+        match ObjModel::from_str("v 0 0 0 1 0\n") {
+            Err(ModelLoadingError::MissingComponents { expected: 6, found: 5, .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn write_and_reload_obj() {
+        let materials = Material::load_library_from_reader("\
+            newmtl red\nKd 1 0 0\nNs 12.5\nillum 2\nmap_Kd -clamp on -s 2 2 1 red paint.png\n\
+            newmtl blue\nKd 0 0 1\n".as_bytes()).unwrap();
+        let source = "\
+            v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0.125 -2.5 3\n\
+            vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\nvn 1 0 0\n\
+            o car body\ng body paint\nusemtl red\ns 1\n\
+            f 1/1/1 2/2/1 3/3/1 4/1/1\n\
+            o wheel\ng wheels paint\nusemtl blue\ns off\n\
+            f 1/1/2 2/2/2 5/3/2\n\
+            g\nf 2/2/2 3/3/2 5/1/2\n";
+        let model = ObjModel::from_reader_with_materials(source.as_bytes(), materials.clone()).unwrap();
+
+        let mut written = Vec::new();
+        model.write_to(&mut written, 6, None).unwrap();
+        let text = String::from_utf8(written).unwrap();
+        //Positions and normals are shared again even though the loader split the vertices
+        assert_eq!(text.lines().filter(|l| l.starts_with("v ")).count(), 5);
+        assert_eq!(text.lines().filter(|l| l.starts_with("vn ")).count(), 2);
+        assert!(text.contains("v 0.125 -2.5 3\n"));
+        let reloaded = ObjModel::from_reader_with_materials(text.as_bytes(), materials).unwrap();
+        assert_eq!(format!("{:?}", reloaded), format!("{:?}", model));
+
+        let dir = std::env::temp_dir().join(format!("vertexify_writer_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("saved.obj");
+        model.save_to_file(path.to_str().unwrap(), 4).unwrap();
+        let saved = ObjModel::load_from_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(saved.material_libraries(), &["saved.mtl".to_string()]);
+        assert_eq!(format!("{:?}", saved.materials()), format!("{:?}", model.materials()));
+        assert_eq!(saved.indices(), model.indices());
+
+        //Faces without groups get no g statement, so no default group appears. OBJ can not leave an object,
+        //group or material, a face without them after faces with them joins those on reload
+        let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 3\no part\ng paint\nusemtl red\nf 1 2 3\nf 3 2 1\n";
+        let merged = ObjModel::from_str(source).unwrap();
+        let mut model = ObjModel::from_str(source).unwrap();
+        model.objects[0].index_ranges[0].end = 6;
+        model.groups[0].index_ranges[0].end = 6;
+        model.sub_meshes[1].index_range.end = 6;
+        model.sub_meshes.push(SubMesh { index_range: 6..9, material_name: None, material: None });
+        let mut written = Vec::new();
+        model.write_to(&mut written, 6, None).unwrap();
+        let text = String::from_utf8(written).unwrap();
+        assert_eq!(text.lines().filter(|l| l.starts_with("g")).collect::<Vec<_>>(), vec!["g paint"]);
+        let reloaded = ObjModel::from_str(&text).unwrap();
+        assert_eq!(format!("{:?}", reloaded), format!("{:?}", merged));
+    }
+
+    #[test]
+    fn write_and_reload_flat_shaded_obj() {
+        //Every face of the cone is in s off, which has to survive the round trip to keep the normals flat
+        let model = ObjModel::load_from_file("res/cone_flat_shaded.obj").unwrap();
+        assert!(!model.smoothing_groups().is_empty());
+        let mut written = Vec::new();
+        model.write_to(&mut written, 6, None).unwrap();
+        assert!(String::from_utf8(written.clone()).unwrap().contains("s off\n"));
+        let mut reloaded = ObjModel::from_bytes(&written).unwrap();
+        assert_eq!(reloaded.smoothing_groups(), model.smoothing_groups());
+        reloaded.generate_normals();
+        assert_eq!(reloaded.vertices().len(), reloaded.indices().len());
+    }
+
+    #[test]
+    fn load_gltf_and_glb() {
+        //A triangle whose second position is replaced by a sparse value, with normalized byte colors
+        let mut buffer: Vec<u8> = Vec::new();
+        for value in &[0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        for index in &[0u16, 1, 2, 0] {
+            buffer.extend_from_slice(&index.to_le_bytes());
+        }
+        buffer.extend_from_slice(&[255, 0, 0, 255, 0, 255, 0, 51, 0, 0, 255, 255]);
+        buffer.extend_from_slice(&[1, 0, 0, 0]);
+        for value in &[2.0f32, 0.0, 0.0] {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        let json = |uri: &str| format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "buffers": [{{ "byteLength": {} {} }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }},
+                {{ "buffer": 0, "byteOffset": 44, "byteLength": 12 }},
+                {{ "buffer": 0, "byteOffset": 56, "byteLength": 1 }},
+                {{ "buffer": 0, "byteOffset": 60, "byteLength": 12 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                   "sparse": {{ "count": 1, "indices": {{ "bufferView": 3, "componentType": 5121 }}, "values": {{ "bufferView": 4 }} }} }},
+                {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }},
+                {{ "bufferView": 2, "componentType": 5121, "normalized": true, "count": 3, "type": "VEC4" }}
+            ],
+            "materials": [{{ "name": "red", "pbrMetallicRoughness": {{ "baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0 }} }}],
+            "meshes": [{{ "name": "triangle", "primitives": [{{ "attributes": {{ "POSITION": 0, "COLOR_0": 2 }}, "indices": 1, "material": 0 }}] }}]
+        }}"#, buffer.len(), uri);
+
+        let uri = format!(r#", "uri": "data:application/octet-stream;base64,{}""#, base64::encode(&buffer));
+        let embedded = json(&uri);
+        let mut glb_json = json("").into_bytes();
+        glb_json.resize(glb_json.len().div_ceil(4) * 4, b' ');
+        let mut glb: Vec<u8> = b"glTF".to_vec();
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((12 + 8 + glb_json.len() + 8 + buffer.len()) as u32).to_le_bytes());
+        glb.extend_from_slice(&(glb_json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"JSON");
+        glb.extend_from_slice(&glb_json);
+        glb.extend_from_slice(&(buffer.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"BIN\0");
+        glb.extend_from_slice(&buffer);
+
+        for bytes in &[embedded.into_bytes(), glb] {
+            let meshes = ObjModel::from_gltf_bytes(bytes).unwrap();
+            assert_eq!(meshes.len(), 1);
+            let mesh = &meshes[0];
+            assert_eq!(mesh.objects()[0].name, "triangle");
+            assert_eq!(mesh.indices(), &[0, 1, 2]);
+            assert_eq!(mesh.vertices()[1].position.x, 2.0);
+            assert_eq!(mesh.vertices()[2].position.y, 1.0);
+            let color = mesh.colors()[1].color;
+            assert_eq!((color.y, color.w), (1.0, 0.2));
+            assert_eq!(mesh.sub_meshes()[0].material, Some(0));
+            assert_eq!(mesh.materials()[0].metallic, Some(0.0));
+        }
+
+        //Primitives with the same attributes share their vertices
+        let primitive = r#"{ "attributes": { "POSITION": 0, "COLOR_0": 2 }, "indices": 1, "material": 0 }"#;
+        let two_primitives = json(&uri).replace(primitive, &format!("{}, {}", primitive, primitive));
+        let mesh = ObjModel::from_gltf_bytes(two_primitives.as_bytes()).unwrap().remove(0);
+        assert_eq!(mesh.vertices().len(), 3);
+        assert_eq!(mesh.indices(), &[0, 1, 2, 0, 1, 2]);
+        assert_eq!(mesh.sub_meshes().len(), 2);
+        //Points load as vertices without indices
+        let points = json(&uri).replace(primitive, r#"{ "attributes": { "POSITION": 0 }, "mode": 0 }"#);
+        let mesh = ObjModel::from_gltf_bytes(points.as_bytes()).unwrap().remove(0);
+        assert_eq!(mesh.vertices().len(), 3);
+        assert!(mesh.indices().is_empty());
+
+        match ObjModel::from_gltf_bytes(b"{ \"asset\": { \"version\": \"1.0\" } }") {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        //Colors for fewer vertices than there are positions, and an index count far beyond its buffer view
+        for &(from, to) in &[(r#""count": 3, "type": "VEC4""#, r#""count": 2, "type": "VEC4""#),
+                             (r#""count": 3, "type": "SCALAR""#, r#""count": 4611686018427387904, "type": "SCALAR""#)] {
+            match ObjModel::from_gltf_bytes(json(&uri).replace(from, to).as_bytes()) {
+                Err(ModelLoadingError::InvalidData { .. }) => {}
+                other => panic!("Unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn write_and_reload_gltf() {
+        let materials = Material::load_library_from_reader("newmtl red\nKd 1 0 0\nNs 98\nmap_Kd red paint.png\n".as_bytes()).unwrap();
+        let source = "v 0 0 0 1 0 0\nv 1 0 0 0 1 0\nv 1 1 0 0 0 1\nv 0 1 -2 1 1 1\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                      o quad\nf 1/1 2/2 3/3\nusemtl red\nf 1/1 3/3 4/4\n";
+        let mut model = ObjModel::from_reader_with_materials(source.as_bytes(), materials).unwrap();
+        model.generate_tangents();
+
+        let mut glb = Vec::new();
+        model.write_glb_to(&mut glb).unwrap();
+        assert_eq!(glb.len() % 4, 0);
+        let json_length = u32::from_le_bytes([glb[12], glb[13], glb[14], glb[15]]) as usize;
+        let document: serde_json::Value = serde_json::from_slice(&glb[20..20 + json_length]).unwrap();
+        let position = &document["accessors"][document["meshes"][0]["primitives"][0]["attributes"]["POSITION"].as_u64().unwrap() as usize];
+        assert_eq!(position["min"], serde_json::json!([0.0, 0.0, -2.0]));
+        assert_eq!(position["max"], serde_json::json!([1.0, 1.0, 0.0]));
+        for view in document["bufferViews"].as_array().unwrap() {
+            assert_eq!(view["byteOffset"].as_u64().unwrap() % 4, 0);
+        }
+        assert_eq!(document["images"][0]["uri"], "red%20paint.png");
+
+        //Every primitive refers to the whole vertex data, the corners of the triangles are compared
+        let corners = |model: &ObjModel| -> Vec<String> {
+            model.indices().iter().map(|&i| {
+                let i = i as usize;
+                format!("{:?} {:?} {:?} {:?}", model.vertices()[i], model.tex_coords()[i], model.tangents()[i], model.colors()[i])
+            }).collect()
+        };
+        let reloaded = ObjModel::from_gltf_bytes(&glb).unwrap().remove(0);
+        assert_eq!(corners(&reloaded), corners(&model));
+        //Normals are normalized once more on export
+        for (&reloaded_index, &index) in reloaded.indices().iter().zip(model.indices()) {
+            let difference = reloaded.normals()[reloaded_index as usize].normal - model.normals()[index as usize].normal;
+            assert!(difference.dot(difference) < 1e-10);
+        }
+        assert_eq!(reloaded.objects()[0].name, "quad");
+        assert_eq!(reloaded.sub_meshes().iter().map(|s| s.material).collect::<Vec<_>>(), vec![None, Some(0)]);
+        assert_eq!(reloaded.materials()[0].diffuse_map.as_ref().unwrap().path, "red paint.png");
+        assert!((reloaded.materials()[0].roughness.unwrap() - 0.1414214).abs() < 1e-6);
+
+        //Point clouds are written as points without indices
+        let cloud = ObjModel::from_str("v 0 0 0 1 0 0\nv 1 0 0 0 1 0\nv 0 1 0 0 0 1\n").unwrap();
+        let mut glb = Vec::new();
+        cloud.write_glb_to(&mut glb).unwrap();
+        let reloaded = ObjModel::from_gltf_bytes(&glb).unwrap().remove(0);
+        assert!(reloaded.indices().is_empty());
+        assert_eq!(format!("{:?}", reloaded.vertices()), format!("{:?}", cloud.vertices()));
+        assert_eq!(format!("{:?}", reloaded.colors()), format!("{:?}", cloud.colors()));
+
+        let dir = std::env::temp_dir().join(format!("vertexify_gltf_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("saved model.gltf");
+        model.save_gltf_to_file(path.to_str().unwrap()).unwrap();
+        assert!(dir.join("saved model.bin").exists());
+        let saved = ObjModel::load_gltf_from_file(path.to_str().unwrap()).unwrap().remove(0);
+        assert_eq!(corners(&saved), corners(&model));
+
+        let path = dir.join("empty.gltf");
+        ObjModel::empty().save_gltf_to_file(path.to_str().unwrap()).unwrap();
+        assert!(!dir.join("empty.bin").exists());
+        let document: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert!(document.get("buffers").is_none());
+        let mut glb = Vec::new();
+        ObjModel::empty().write_glb_to(&mut glb).unwrap();
+        assert!(ObjModel::from_gltf_bytes(&glb).unwrap().is_empty());
+    }
+
+    #[test]
+    fn load_ascii_and_binary_stl() {
+        //Two coplanar triangles share an edge, the third one has no normal in the file
+        let ascii = "solid plate\n\
+            facet normal 0 0 1\n outer loop\n vertex 0 0 0\n vertex 1 0 0\n vertex 1 1 0\n endloop\nendfacet\n\
+            facet normal 0 0 1\n outer loop\n vertex 0 0 0\n vertex 1 1 0\n vertex 0 1 0\n endloop\nendfacet\n\
+            facet normal 0 0 0\n outer loop\n vertex 0 0 0\n vertex 0 1 0\n vertex 0 0 1\n endloop\nendfacet\n\
+            endsolid plate\n";
+        let model = ObjModel::from_stl_bytes(ascii.as_bytes()).unwrap();
+        assert_eq!(model.indices().len(), 9);
+        assert_eq!(model.vertices().len(), 7);
+        assert_eq!(model.objects()[0].name, "plate");
+        let side_normal = model.normals()[model.indices()[6] as usize].normal;
+        assert_eq!((side_normal.x, side_normal.y, side_normal.z), (1.0, 0.0, 0.0));
+        assert!(model.colors().is_empty());
+        //A stored normal is kept even when it disagrees with the winding, one that is not unit length is derived from the winding
+        let flipped = "solid flipped\n\
+            facet normal 0 0 -1\n outer loop\n vertex 0 0 0\n vertex 1 0 0\n vertex 1 1 0\n endloop\nendfacet\n\
+            facet normal 0 0 2\n outer loop\n vertex 0 0 0\n vertex 1 1 0\n vertex 0 1 0\n endloop\nendfacet\n\
+            endsolid flipped\n";
+        let model = ObjModel::from_stl_bytes(flipped.as_bytes()).unwrap();
+        assert_eq!(model.vertices().len(), 6);
+        assert_eq!(model.normals()[model.indices()[0] as usize].normal.z, -1.0);
+        assert_eq!(model.normals()[model.indices()[3] as usize].normal.z, 1.0);
+
+        match ObjModel::from_stl_bytes(b"solid broken\nfacet normal 0 0\n") {
+            Err(ModelLoadingError::MissingComponents { location, .. }) => assert_eq!(location.line, 2),
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        //A binary file whose header starts with "solid", with a VisCAM color on the first triangle only
+        let mut binary = b"solid but actually binary".to_vec();
+        binary.resize(80, 0);
+        binary.extend_from_slice(&2u32.to_le_bytes());
+        for (corners, attribute) in &[([0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0], 0x8000u16 | 0x1F << 10), ([0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0], 0)] {
+            for value in [0.0f32, 0.0, 1.0].iter().chain(corners.iter()) {
+                binary.extend_from_slice(&value.to_le_bytes());
+            }
+            binary.extend_from_slice(&attribute.to_le_bytes());
+        }
+        let model = ObjModel::from_stl_bytes(&binary).unwrap();
+        assert_eq!(model.indices().len(), 6);
+        assert_eq!(model.vertices().len(), 6);
+        let red = model.colors()[0].color;
+        assert_eq!((red.x, red.y, red.z), (1.0, 0.0, 0.0));
+        let white = model.colors()[5].color;
+        assert_eq!((white.x, white.y, white.z), (1.0, 1.0, 1.0));
+
+        binary.truncate(100);
+        match ObjModel::from_stl_bytes(&binary) {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn write_and_reload_stl() {
+        let source = "v 0 0 0 1 0 0\nv 1 0 0 1 0 0\nv 1 1 0 1 0 0\nv 0 1 0 0 0 1\no part\nf 1 2 3\nf 1 3 4\n";
+        let model = ObjModel::from_str(source).unwrap();
+        let positions = |model: &ObjModel| -> Vec<(f32, f32, f32)> {
+            model.indices().iter().map(|&i| model.vertices()[i as usize].position).map(|p| (p.x, p.y, p.z)).collect()
+        };
+
+        for &format in &[StlFormat::Ascii, StlFormat::Binary, StlFormat::BinaryWithColors] {
+            let mut written = Vec::new();
+            model.write_stl_to(&mut written, format).unwrap();
+            let reloaded = ObjModel::from_stl_bytes(&written).unwrap();
+            assert_eq!(positions(&reloaded), positions(&model));
+            let normal = reloaded.normals()[0].normal;
+            assert_eq!((normal.x, normal.y, normal.z), (0.0, 0.0, 1.0));
+
+            match format {
+                StlFormat::Ascii => assert_eq!(reloaded.objects()[0].name, "part"),
+                StlFormat::Binary => assert!(reloaded.colors().is_empty()),
+                StlFormat::BinaryWithColors => {
+                    let first = reloaded.colors()[reloaded.indices()[0] as usize].color;
+                    assert_eq!((first.x, first.y, first.z), (1.0, 0.0, 0.0));
+                    //Two red corners and a blue one
+                    let second = reloaded.colors()[reloaded.indices()[3] as usize].color;
+                    assert_eq!((second.x, second.y, second.z), (21.0 / 31.0, 0.0, 10.0 / 31.0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn load_ascii_and_binary_fbx() {
+        let ascii = r#"; FBX 7.4.0 project file
+FBXHeaderExtension:  {
+	FBXVersion: 7400
+}
+Objects:  {
+	Geometry: 10, "Geometry::Quad", "Mesh" {
+		Vertices: *12 {
+			a: 0,0,0,1,0,0,1,1,0,0,1,0
+		}
+		PolygonVertexIndex: *6 {
+			a: 0,1,-3,0,2,-4
+		}
+		LayerElementNormal: 0 {
+			MappingInformationType: "ByVertice"
+			ReferenceInformationType: "Direct"
+			Normals: *12 {
+				a: 0,0,1,0,0,1,0,0,1,0,0,1
+			}
+		}
+		LayerElementUV: 0 {
+			MappingInformationType: "ByPolygonVertex"
+			ReferenceInformationType: "IndexToDirect"
+			UV: *8 {
+				a: 0,0,1,0,1,1,0,1
+			}
+			UVIndex: *6 {
+				a: 0,1,2,0,2,3
+			}
+		}
+		LayerElementMaterial: 0 {
+			MappingInformationType: "ByPolygon"
+			ReferenceInformationType: "IndexToDirect"
+			Materials: *2 {
+				a: 0,1
+			}
+		}
+	}
+	Model: 20, "Model::Quad", "Mesh" {
+	}
+	Material: 30, "Material::red", "" {
+		Properties70:  {
+			P: "DiffuseColor", "Color", "", "A",1,0,0
+		}
+	}
+	Material: 31, "Material::blue", "" {
+		Properties70:  {
+			P: "DiffuseColor", "Color", "", "A",0,0,1
+		}
+	}
+}
+Connections:  {
+	C: "OO",10,20
+	C: "OO",30,20
+	C: "OO",31,20
+}
+"#;
+        let models = ObjModel::from_fbx_bytes(ascii.as_bytes()).unwrap();
+        assert_eq!(models.len(), 1);
+        let quad = &models[0];
+        assert_eq!(quad.objects()[0].name, "Quad");
+        assert_eq!(quad.vertices().len(), 4);
+        assert_eq!(quad.indices().len(), 6);
+        let names: Vec<_> = quad.sub_meshes().iter().map(|s| s.material_name.clone().unwrap()).collect();
+        assert_eq!(names, vec!["red", "blue"]);
+        let red = quad.materials()[0].diffuse.unwrap();
+        assert_eq!((red.x, red.y, red.z), (1.0, 0.0, 0.0));
+        for (vertex, uv) in quad.vertices().iter().zip(quad.tex_coords()) {
+            assert_eq!((vertex.position.x, vertex.position.y), (uv.tex_coords.x, uv.tex_coords.y));
+        }
+        assert_eq!(quad.normals()[3].normal.z, 1.0);
+        //Properties60 records of FBX 6 have no label before the values
+        let version_6 = ascii.replacen("Properties70", "Properties60", 1)
+            .replacen(r#"P: "DiffuseColor", "Color", "", "A",1,0,0"#, r#"Property: "DiffuseColor", "ColorRGB", "A",0,1,0"#, 1);
+        let green = ObjModel::from_fbx_bytes(version_6.as_bytes()).unwrap()[0].materials()[0].diffuse.unwrap();
+        assert_eq!((green.x, green.y, green.z), (0.0, 1.0, 0.0));
+
+        match ObjModel::from_fbx_bytes(b"Objects:  {\n\tModel: 1, \"Model::Open\", \"Mesh\" {\n") {
+            Err(ModelLoadingError::InvalidData { location, .. }) => assert_eq!(location.line, 2),
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        //Node records of a 7.5 file with 64 bit offsets, closed by a null record when they have children
+        fn node(out: &mut Vec<u8>, name: &str, properties: &[Vec<u8>], children: &dyn Fn(&mut Vec<u8>)) {
+            let start = out.len();
+            let property_bytes = properties.concat();
+            out.extend_from_slice(&[0u8; 8]);
+            out.extend_from_slice(&(properties.len() as u64).to_le_bytes());
+            out.extend_from_slice(&(property_bytes.len() as u64).to_le_bytes());
+            out.push(name.len() as u8);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&property_bytes);
+            let children_start = out.len();
+            children(out);
+            if out.len() > children_start {
+                out.extend_from_slice(&[0u8; 25]);
+            }
+            let end = out.len() as u64;
+            out[start..start + 8].copy_from_slice(&end.to_le_bytes());
+        }
+        let string = |text: &[u8]| [&[b'S'][..], &(text.len() as u32).to_le_bytes(), text].concat();
+        let long = |value: i64| [&[b'L'][..], &value.to_le_bytes()].concat();
+
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        for value in &[0.0f64, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 2.0, 0.0] {
+            std::io::Write::write_all(&mut encoder, &value.to_le_bytes()).unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+        let vertices = [&[b'd'][..], &9u32.to_le_bytes(), &1u32.to_le_bytes(), &(compressed.len() as u32).to_le_bytes(), &compressed].concat();
+        let mut polygon = vec![b'i'];
+        polygon.extend_from_slice(&3u32.to_le_bytes());
+        polygon.extend_from_slice(&0u32.to_le_bytes());
+        polygon.extend_from_slice(&12u32.to_le_bytes());
+        for index in &[0i32, 1, -3] {
+            polygon.extend_from_slice(&index.to_le_bytes());
+        }
+
+        let mut binary = b"Kaydara FBX Binary  \0\x1a\0".to_vec();
+        binary.extend_from_slice(&7500u32.to_le_bytes());
+        node(&mut binary, "Objects", &[], &|out| {
+            node(out, "Geometry", &[long(1), string(b"Tri\0\x01Geometry"), string(b"Mesh")], &|out| {
+                node(out, "Vertices", std::slice::from_ref(&vertices), &|_| {});
+                node(out, "PolygonVertexIndex", std::slice::from_ref(&polygon), &|_| {});
+            });
+            node(out, "Model", &[long(2), string(b"Tri\0\x01Model"), string(b"Mesh")], &|_| {});
+        });
+        node(&mut binary, "Connections", &[], &|out| node(out, "C", &[string(b"OO"), long(1), long(2)], &|_| {}));
+        binary.extend_from_slice(&[0u8; 25]);
+
+        let models = ObjModel::from_fbx_bytes(&binary).unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].objects()[0].name, "Tri");
+        assert_eq!(models[0].indices(), &[0, 1, 2]);
+        let corner = models[0].vertices()[2].position;
+        assert_eq!((corner.x, corner.y, corner.z), (0.0, 2.0, 0.0));
+
+        let mut huge_count = binary.clone();
+        huge_count[35..43].copy_from_slice(&u64::MAX.to_le_bytes());
+        match ObjModel::from_fbx_bytes(&huge_count) {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        //Nesting deep enough to overflow the stack is rejected in both formats
+        fn nest(out: &mut Vec<u8>, levels: usize) {
+            node(out, "N", &[], &|out| if levels > 0 { nest(out, levels - 1) });
+        }
+        let mut deep = binary[..27].to_vec();
+        nest(&mut deep, 1000);
+        deep.extend_from_slice(&[0u8; 25]);
+        let deep_ascii = "N: {".repeat(100_000);
+        for bytes in &[&deep[..], deep_ascii.as_bytes()] {
+            match ObjModel::from_fbx_bytes(bytes) {
+                Err(ModelLoadingError::InvalidData { .. }) => {}
+                other => panic!("Unexpected result {:?}", other),
+            }
+        }
+
+        binary.truncate(60);
+        match ObjModel::from_fbx_bytes(&binary) {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_and_write_ply() {
+        let source = "ply\nformat ascii 1.0\ncomment a colored quad\nelement vertex 4\n\
+                      property float x\nproperty float y\nproperty float z\nproperty float nx\nproperty float ny\nproperty float nz\n\
+                      property float u\nproperty float v\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+                      element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+                      0 0 0 0 0 1 0 0 255 0 0\n1 0 0 0 0 1 1 0 255 0 0\n1 1 0 0 0 1 1 1 0 0 255\n0 1 0 0 0 1 0 1 0 0 255\n4 0 1 2 3\n";
+        let model = ObjModel::from_ply_bytes(source.as_bytes()).unwrap();
+        assert_eq!(model.vertices().len(), 4);
+        assert_eq!(model.indices().len(), 6);
+        assert_eq!(model.normals()[2].normal.z, 1.0);
+        assert_eq!((model.tex_coords()[2].tex_coords.x, model.tex_coords()[2].tex_coords.y), (1.0, 1.0));
+        let blue = model.colors()[3].color;
+        assert_eq!((blue.x, blue.y, blue.z, blue.w), (0.0, 0.0, 1.0, 1.0));
+
+        for &format in &[PlyFormat::Ascii, PlyFormat::BinaryLittleEndian, PlyFormat::BinaryBigEndian] {
+            let mut written = Vec::new();
+            model.write_ply_to(&mut written, format).unwrap();
+            let reloaded = ObjModel::from_ply_bytes(&written).unwrap();
+            assert_eq!(reloaded.indices(), model.indices());
+            let corner = reloaded.vertices()[2].position;
+            assert_eq!((corner.x, corner.y, corner.z), (1.0, 1.0, 0.0));
+            assert_eq!(reloaded.tex_coords()[1].tex_coords.x, 1.0);
+            assert_eq!(reloaded.colors()[0].color.x, 1.0);
+        }
+
+        match ObjModel::from_ply_bytes(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\nend_header\n0 0\n") {
+            Err(ModelLoadingError::InvalidData { location, .. }) => assert_eq!(location.line, 8),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        //Negative corners and vertex counts far beyond the data are errors instead of vertex 0 or an abort
+        let header = "ply\nformat ascii 1.0\nelement vertex {}\nproperty float x\nproperty float y\nproperty float z\n\
+                      element face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n1 0 0\n0 1 0\n";
+        for &(count, face) in &[("3", "3 -1 0 1\n"), ("99999999999999999", "")] {
+            match ObjModel::from_ply_bytes((header.replace("{}", count) + face).as_bytes()) {
+                Err(ModelLoadingError::InvalidData { .. }) => {}
+                other => panic!("Unexpected result {:?}", other),
+            }
+        }
+        //Elements without properties take no data, their count still has to fit in the file
+        match ObjModel::from_ply_bytes(b"ply\nformat binary_little_endian 1.0\nelement nothing 99999999999999999\nend_header\n") {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        //A comment mentioning end_header does not end the header
+        let commented = "ply\nformat ascii 1.0\ncomment written before end_header\nelement vertex 1\n\
+                         property float x\nproperty float y\nproperty float z\nend_header\n1 2 3\n";
+        let model = ObjModel::from_ply_bytes(commented.as_bytes()).unwrap();
+        assert_eq!(model.vertices().len(), 1);
+        assert_eq!(model.vertices()[0].position.y, 2.0);
+    }
+
+    #[test]
+    fn load_and_write_off() {
+        //ModelNet writes the counts right after the keyword
+        let source = "OFF4 2 0\n# a square with a red and an uncolored face\n0 0 0\n1 0 0\n1 1 0\n\n0 1 0\n3 0 1 2 255 0 0\n3 0 2 3\n";
+        let model = ObjModel::from_off_bytes(source.as_bytes()).unwrap();
+        assert_eq!(model.indices().len(), 6);
+        assert_eq!(model.vertices().len(), 6);
+        let red = model.colors()[0].color;
+        assert_eq!((red.x, red.y, red.z, red.w), (1.0, 0.0, 0.0, 1.0));
+        assert_eq!(model.colors()[5].color.y, 1.0);
+        //One scale for the whole file, 0 0 1 next to 255 128 0 is almost black
+        let model = ObjModel::from_off_bytes(b"COFF\n2 0 0\n0 0 0 255 128 0\n1 0 0 0 0 1\n").unwrap();
+        assert_eq!(model.colors()[1].color.z, 1.0 / 255.0);
+
+        let source = "STCNOFF\n3 1 0\n0 0 0 0 0 1 0 0 1 1 0 0\n1 0 0 0 0 1 0 1 0 0.5 1 0\n0 1 0 0 0 1 0 0 1 1 0 1\n3 0 1 2\n";
+        let model = ObjModel::from_off_bytes(source.as_bytes()).unwrap();
+        assert_eq!(model.vertices().len(), 3);
+        assert_eq!(model.normals()[1].normal.z, 1.0);
+        assert_eq!(model.colors()[1].color.w, 0.5);
+        assert_eq!(model.tex_coords()[2].tex_coords.y, 1.0);
+
+        let mut written = Vec::new();
+        model.write_off_to(&mut written).unwrap();
+        assert!(written.starts_with(b"STCNOFF\n3 1 0\n"));
+        let reloaded = ObjModel::from_off_bytes(&written).unwrap();
+        assert_eq!(reloaded.indices(), model.indices());
+        assert_eq!(reloaded.colors()[1].color.y, 1.0);
+        assert_eq!(reloaded.tex_coords()[1].tex_coords.x, 1.0);
+
+        match ObjModel::from_off_bytes(b"OFF\n2 1 0\n0 0 0\n1 0 0\n3 0 1 2\n") {
+            Err(ModelLoadingError::IndexOutOfRange { location, index: 2, .. }) => assert_eq!((location.line, location.column), (5, 7)),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        //Counts from the header are not trusted to size anything
+        match ObjModel::from_off_bytes(b"OFF\n0 99999999999999999 0\n") {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_collada() {
+        let source = r##"<?xml version="1.0" encoding="utf-8"?>
+<COLLADA xmlns="http://www.collada.org/2005/11/COLLADASchema" version="1.4.1">
+  <asset><up_axis>Z_UP</up_axis></asset>
+  <library_images>
+    <image id="wood-image"><init_from>wood.png</init_from></image>
+  </library_images>
+  <library_effects>
+    <effect id="red-effect"><profile_COMMON><technique sid="common">
+      <phong><diffuse><color>1 0 0 1</color></diffuse><shininess><float>20</float></shininess></phong>
+    </technique></profile_COMMON></effect>
+    <effect id="wood-effect"><profile_COMMON>
+      <newparam sid="wood-surface"><surface type="2D"><init_from>wood-image</init_from></surface></newparam>
+      <newparam sid="wood-sampler"><sampler2D><source>wood-surface</source></sampler2D></newparam>
+      <technique sid="common"><lambert><diffuse><texture texture="wood-sampler" texcoord="UVMap"/></diffuse></lambert></technique>
+    </profile_COMMON></effect>
+  </library_effects>
+  <library_materials>
+    <material id="red-material" name="red"><instance_effect url="#red-effect"/></material>
+    <material id="wood-material" name="wood"><instance_effect url="#wood-effect"/></material>
+  </library_materials>
+  <library_geometries>
+    <geometry id="box-mesh" name="box">
+      <mesh>
+        <source id="box-positions">
+          <float_array id="box-positions-array" count="15">0 0 0 1 0 0 1 1 0 0 1 0 0 0 1</float_array>
+          <technique_common><accessor source="#box-positions-array" count="5" stride="3"/></technique_common>
+        </source>
+        <source id="box-normals">
+          <float_array id="box-normals-array" count="3">0 0 1</float_array>
+          <technique_common><accessor source="#box-normals-array" count="1" stride="3"/></technique_common>
+        </source>
+        <source id="box-uvs">
+          <float_array id="box-uvs-array" count="8">0 0 1 0 1 1 0 1</float_array>
+          <technique_common><accessor source="#box-uvs-array" count="4" stride="2"/></technique_common>
+        </source>
+        <source id="wood-uvs">
+          <float_array id="wood-uvs-array" count="6">0.5 0.5 0.5 0.5 0.5 0.5</float_array>
+          <technique_common><accessor source="#wood-uvs-array" count="3" stride="2"/></technique_common>
+        </source>
+        <vertices id="box-vertices"><input semantic="POSITION" source="#box-positions"/></vertices>
+        <polylist material="red-symbol" count="1">
+          <input semantic="VERTEX" source="#box-vertices" offset="0"/>
+          <input semantic="NORMAL" source="#box-normals" offset="1"/>
+          <input semantic="TEXCOORD" source="#box-uvs" offset="2" set="0"/>
+          <vcount>4</vcount>
+          <p>0 0 0 1 0 1 2 0 2 3 0 3</p>
+        </polylist>
+        <triangles material="wood-symbol" count="1">
+          <input semantic="VERTEX" source="#box-vertices" offset="0"/>
+          <input semantic="NORMAL" source="#box-normals" offset="1"/>
+          <input semantic="TEXCOORD" source="#wood-uvs" offset="2" set="0"/>
+          <p>0 0 0 1 0 1 4 0 2</p>
+        </triangles>
+      </mesh>
+    </geometry>
+  </library_geometries>
+  <library_visual_scenes>
+    <visual_scene id="scene">
+      <node id="root" name="root">
+        <translate>10 0 0</translate>
+        <node id="box-node" name="Box">
+          <instance_geometry url="#box-mesh">
+            <bind_material><technique_common>
+              <instance_material symbol="red-symbol" target="#red-material"/>
+              <instance_material symbol="wood-symbol" target="#wood-material"/>
+            </technique_common></bind_material>
+          </instance_geometry>
+        </node>
+      </node>
+    </visual_scene>
+  </library_visual_scenes>
+  <scene><instance_visual_scene url="#scene"/></scene>
+</COLLADA>"##;
+        let models = ObjModel::from_collada_bytes(source.as_bytes()).unwrap();
+        assert_eq!(models.len(), 1);
+        let model = &models[0];
+        assert_eq!(model.objects()[0].name, "Box");
+        assert_eq!(model.indices().len(), 9);
+        //Z up is turned to Y up after the node transform moved the box
+        let top = model.vertices()[model.indices()[8] as usize].position;
+        assert_eq!((top.x, top.y, top.z), (10.0, 1.0, 0.0));
+        let corner = model.vertices()[2].position;
+        assert_eq!((corner.x, corner.y, corner.z), (11.0, 0.0, -1.0));
+        let normal = model.normals()[0].normal;
+        assert_eq!((normal.x, normal.y, normal.z), (0.0, 1.0, 0.0));
+        assert_eq!((model.tex_coords()[2].tex_coords.x, model.tex_coords()[2].tex_coords.y), (1.0, 1.0));
+        //The triangle repeats the indices of the quad's corners, but not its texture coordinates
+        assert!(model.indices()[6..].iter().all(|&index| model.tex_coords()[index as usize].tex_coords.x == 0.5));
+
+        let names: Vec<_> = model.sub_meshes().iter().map(|s| s.material_name.clone().unwrap()).collect();
+        assert_eq!(names, vec!["red", "wood"]);
+        assert_eq!(model.materials()[0].diffuse.unwrap().x, 1.0);
+        assert_eq!(model.materials()[0].shininess, Some(20.0));
+        assert_eq!(model.materials()[1].diffuse_map.as_ref().unwrap().path, "wood.png");
+
+        let flat_normals = source.replace(r#"count="1" stride="3""#, r#"count="1" stride="2""#);
+        match ObjModel::from_collada_bytes(flat_normals.as_bytes()) {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        //Instancing a node again is fine, instancing one of the nodes it is inside of is a cycle
+        let instanced = source.replace("</node>\n    </visual_scene>", "</node>\n      <node><instance_node url=\"#box-node\"/></node>\n    </visual_scene>");
+        assert_eq!(ObjModel::from_collada_bytes(instanced.as_bytes()).unwrap().len(), 2);
+        let cycle = source.replace("</instance_geometry>", "</instance_geometry>\n          <instance_node url=\"#root\"/>");
+        match ObjModel::from_collada_bytes(cycle.as_bytes()) {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        match ObjModel::from_collada_bytes(b"<COLLADA>\n  <asset>\n</COLLADA>") {
+            Err(ModelLoadingError::Xml { location, .. }) => assert_eq!(location.line, 3),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_3ds() {
+        fn chunk(id: u16, data: &[u8], children: &[Vec<u8>]) -> Vec<u8> {
+            let children = children.concat();
+            let mut bytes = id.to_le_bytes().to_vec();
+            bytes.extend_from_slice(&((6 + data.len() + children.len()) as u32).to_le_bytes());
+            bytes.extend_from_slice(data);
+            bytes.extend_from_slice(&children);
+            bytes
+        }
+        let floats = |values: &[f32]| values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<u8>>();
+        let shorts = |values: &[u16]| values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<u8>>();
+
+        let material = chunk(0xAFFF, &[], &[
+            chunk(0xA000, b"red\0", &[]),
+            chunk(0xA020, &[], &[chunk(0x0011, &[255, 0, 0], &[])]),
+            chunk(0xA050, &[], &[chunk(0x0030, &shorts(&[25]), &[])]),
+            chunk(0xA200, &[], &[chunk(0xA300, b"brick.png\0", &[]), chunk(0xA354, &floats(&[2.0]), &[])]),
+        ]);
+        //Two triangles folded along x = 1, the second one without a material
+        let vertices = [&shorts(&[4])[..], &floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 1.0])].concat();
+        let faces = [&shorts(&[2])[..], &shorts(&[0, 1, 2, 7, 1, 2, 3, 7])].concat();
+        let mesh = chunk(0x4100, &[], &[
+            chunk(0x4110, &vertices, &[]),
+            chunk(0x4140, &[&shorts(&[4])[..], &floats(&[0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0])].concat(), &[]),
+            chunk(0x4120, &faces, &[
+                chunk(0x4130, &[&b"red\0"[..], &shorts(&[1, 0])].concat(), &[]),
+                chunk(0x4150, &[1u32.to_le_bytes(), 2u32.to_le_bytes()].concat(), &[]),
+            ]),
+            chunk(0x4160, &floats(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]), &[]),
+        ]);
+        let object = chunk(0x4000, b"Fold\0", &[mesh]);
+        let file = chunk(0x4D4D, &[], &[chunk(0x0002, &3u32.to_le_bytes(), &[]), chunk(0x3D3D, &[], &[material, object])]);
+
+        let model = ObjModel::from_3ds_bytes(&file).unwrap();
+        assert_eq!(model.objects()[0].name, "Fold");
+        assert_eq!(model.indices().len(), 6);
+        assert_eq!(model.sub_meshes().len(), 2);
+        assert_eq!(model.sub_meshes()[0].material, Some(0));
+        assert_eq!(model.sub_meshes()[1].material_name, None);
+        assert_eq!(model.smoothing_groups(), &[1, 2]);
+        let red = &model.materials()[0];
+        assert_eq!(red.diffuse.unwrap().x, 1.0);
+        assert_eq!(red.dissolve, Some(0.75));
+        let map = red.diffuse_map.as_ref().unwrap();
+        assert_eq!((map.path.as_str(), map.options.scale.x), ("brick.png", 2.0));
+        //Different smoothing groups keep the fold sharp
+        let first = model.normals()[model.indices()[1] as usize].normal;
+        let second = model.normals()[model.indices()[4] as usize].normal;
+        assert_eq!((first.x, first.y, first.z), (0.0, 0.0, 1.0));
+        assert_eq!((second.x, second.y, second.z), (1.0, 0.0, 0.0));
+
+        //Smoothing groups are masks, faces that share any bit are smoothed together
+        let smoothing = file.windows(6).position(|w| w == [0x50, 0x41, 14, 0, 0, 0]).unwrap() + 6;
+        let mut shared_bit = file.clone();
+        shared_bit[smoothing..smoothing + 8].copy_from_slice(&[&3u32.to_le_bytes()[..], &2u32.to_le_bytes()].concat());
+        let model = ObjModel::from_3ds_bytes(&shared_bit).unwrap();
+        let first = model.normals()[model.indices()[1] as usize].normal;
+        assert!((first.x - first.z).abs() < 1e-6 && first.x > 0.0);
+        //Without smoothing groups every face is flat
+        let mut no_groups = file.clone();
+        no_groups[smoothing - 6] = 0x51;
+        let model = ObjModel::from_3ds_bytes(&no_groups).unwrap();
+        let second = model.normals()[model.indices()[4] as usize].normal;
+        assert_eq!((second.x, second.y, second.z), (1.0, 0.0, 0.0));
+
+        match ObjModel::from_3ds_bytes(&file[..40]) {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_md2_and_md3() {
+        fn name(text: &str, size: usize) -> Vec<u8> {
+            let mut bytes = text.as_bytes().to_vec();
+            bytes.resize(size, 0);
+            bytes
+        }
+        let ints = |values: &[i32]| values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<u8>>();
+        let floats = |values: &[f32]| values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<u8>>();
+        let shorts = |values: &[i16]| values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<u8>>();
+
+        //One triangle in two frames, the second frame is twice as large and raised by one
+        let md2 = [
+            &b"IDP2"[..], &ints(&[8, 64, 64, 52, 1, 3, 3, 1, 0, 2, 68, 132, 144, 156, 260, 260]),
+            &name("skin.pcx", 64),
+            &shorts(&[0, 0, 64, 0, 0, 32]),
+            &shorts(&[0, 1, 2, 0, 1, 2]),
+            &floats(&[1.0, 1.0, 1.0, 0.0, 0.0, 0.0]), &name("stand01", 16), &[0, 0, 0, 5, 10, 0, 0, 5, 0, 10, 0, 5],
+            &floats(&[2.0, 2.0, 2.0, 0.0, 0.0, 1.0]), &name("run01", 16), &[0, 0, 0, 5, 10, 0, 0, 5, 0, 10, 0, 5],
+        ].concat();
+        let model = MorphModel::from_md2_bytes(&md2).unwrap();
+        assert_eq!(model.skins(), &["skin.pcx".to_string()]);
+        assert_eq!(model.frames().iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), vec!["stand01", "run01"]);
+        //Clockwise triangles are turned around and Z up becomes Y up
+        assert_eq!(model.model().indices(), &[0, 1, 2]);
+        let p = model.model().vertices()[1].position;
+        assert_eq!((p.x, p.y, p.z), (0.0, 0.0, -10.0));
+        assert_eq!(model.model().tex_coords()[1].tex_coords.y, 0.5);
+        assert_eq!(model.model().normals()[0].normal.y, 1.0);
+        let (vertices, normals) = model.interpolate(0, 1, 0.5);
+        let p = vertices[2].position;
+        assert_eq!((p.x, p.y, p.z), (15.0, 0.5, 0.0));
+        assert_eq!(normals[2].normal.y, 1.0);
+        //Frame numbers wrap around
+        assert_eq!(model.interpolate(2, 3, 0.0).0[2].position.x, 10.0);
+
+        let frame = |frame_name: &str| [vec![0; 40], name(frame_name, 16)].concat();
+        let tag = |origin: &[f32]| [name("tag_weapon", 64), floats(origin), floats(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0])].concat();
+        let md3 = [
+            &b"IDP3"[..], &ints(&[15]), &name("player", 64), &ints(&[0, 2, 1, 1, 0, 108, 220, 444, 704]),
+            &frame("idle"), &frame("wave"),
+            &tag(&[1.0, 2.0, 3.0]), &tag(&[3.0, 2.0, 3.0]),
+            b"IDP3", &name("body", 64), &ints(&[0, 2, 1, 3, 1, 176, 108, 188, 212, 260]),
+            &name("models/body.tga", 64), &ints(&[0]),
+            &ints(&[0, 1, 2]),
+            &floats(&[0.0, 0.0, 1.0, 0.0, 0.0, 0.25]),
+            &shorts(&[0, 0, 0, 0, 64, 0, 0, 0, 0, 128, 0, 0]),
+            &shorts(&[0, 0, 0, 0, 128, 0, 0, 0, 0, 128, 0, 0]),
+        ].concat();
+        let model = MorphModel::from_md3_bytes(&md3).unwrap();
+        assert_eq!(model.model().objects()[0].name, "body");
+        assert_eq!(model.model().sub_meshes()[0].material_name.as_deref(), Some("models/body.tga"));
+        assert_eq!(model.frames()[1].name, "wave");
+        assert_eq!(model.model().indices(), &[0, 2, 1]);
+        let p = model.model().vertices()[2].position;
+        assert_eq!((p.x, p.y, p.z), (0.0, 0.0, -2.0));
+        assert_eq!(model.model().tex_coords()[2].tex_coords.y, 0.75);
+        assert_eq!(model.frames()[1].positions[1].x, 2.0);
+        let origin = model.tags(0)[0].origin;
+        assert_eq!((origin.x, origin.y, origin.z), (1.0, 3.0, -2.0));
+        assert_eq!(model.interpolate_tag("tag_weapon", 0, 1, 0.5).unwrap().origin.x, 2.0);
+        assert!(model.interpolate_tag("tag_head", 0, 1, 0.5).is_none());
+
+        match MorphModel::from_md3_bytes(&md3[..500]) {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        let mut huge_counts = md2.clone();
+        huge_counts[32..36].copy_from_slice(&i32::MAX.to_le_bytes());
+        match MorphModel::from_md2_bytes(&huge_counts) {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        let mut huge_counts = md3.clone();
+        huge_counts[76..80].copy_from_slice(&i32::MAX.to_le_bytes());
+        match MorphModel::from_md3_bytes(&huge_counts) {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_iqm() {
+        let ints = |values: &[i32]| values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<u8>>();
+        let floats = |values: &[f32]| values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<u8>>();
+        let mut text = b"\0body\0skin.png\0root\0arm\0wave\0".to_vec();
+        text.resize(32, 0);
+        let identity = [0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0];
+
+        //A triangle with one corner on the root joint and two on an arm that moves along x
+        let iqm = [
+            &b"INTERQUAKEMODEL\0"[..],
+            &ints(&[2, 652, 0, 32, 124, 1, 156, 4, 3, 180, 1, 344, 0, 2, 356, 2, 452, 1, 632, 2, 1, 628, 0, 0, 0, 0, 0]),
+            &text,
+            &ints(&[1, 6, 0, 3, 0, 1]),
+            &ints(&[0, 0, 7, 3, 260, 1, 0, 7, 2, 296, 4, 0, 1, 4, 320, 5, 0, 1, 4, 332]),
+            &floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]),
+            &floats(&[0.0, 0.0, 1.0, 0.0, 0.0, 0.25]),
+            &[0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0],
+            &[255, 0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0],
+            &ints(&[0, 1, 2]),
+            &ints(&[15, -1]), &floats(&[0.0, 0.0, 0.0]), &floats(&identity),
+            &ints(&[20, 0]), &floats(&[1.0, 0.0, 0.0]), &floats(&identity),
+            &ints(&[-1, 0]), &floats(&[0.0, 0.0, 0.0]), &floats(&identity), &floats(&[0.0; 10]),
+            &ints(&[0, 1]), &floats(&[1.0, 0.0, 0.0]), &floats(&identity), &floats(&[1.0]), &floats(&[0.0; 9]),
+            &[0, 0, 2, 0],
+            &ints(&[24, 0, 2]), &floats(&[10.0]), &ints(&[1]),
+        ].concat();
+        let model = SkinnedModel::from_iqm_bytes(&iqm).unwrap();
+        let mesh = model.model();
+        assert_eq!(mesh.objects()[0].name, "body");
+        assert_eq!(mesh.sub_meshes()[0].material_name.as_deref(), Some("skin.png"));
+        assert_eq!(mesh.indices(), &[0, 2, 1]);
+        let p = mesh.vertices()[2].position;
+        assert_eq!((p.x, p.y, p.z), (0.0, 0.0, -1.0));
+        assert_eq!(mesh.tex_coords()[2].tex_coords.y, 0.75);
+        assert_eq!(mesh.normals().len(), 3);
+        assert_eq!(model.joint_weights()[1].joints, [1, 0, 0, 0]);
+        assert_eq!(model.joint_weights()[1].weights.x, 1.0);
+        assert_eq!((model.joints()[1].name.as_str(), model.joints()[1].parent), ("arm", Some(0)));
+
+        let wave = &model.animations()[0];
+        assert_eq!((wave.name.as_str(), wave.looping, wave.frames.len()), ("wave", true, 2));
+        assert_eq!(model.sample(0, 0.05)[1].translation.x, 2.0);
+        assert_eq!(model.sample(0, 0.1)[1].translation.x, 3.0);
+        //Looping blends the last frame back into the first
+        assert_eq!(model.sample(0, 0.15)[1].translation.x, 2.0);
+        assert_eq!(model.skinning_matrices(&model.bind_pose())[1], [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+        assert_eq!(model.skinning_matrices(&model.sample(0, 0.1))[1][3][0], 2.0);
+
+        match SkinnedModel::from_iqm_bytes(&iqm[..400]) {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        for &count_offset in &[68, 76, 92] {
+            let mut huge_count = iqm.clone();
+            huge_count[count_offset..count_offset + 4].copy_from_slice(&i32::MAX.to_le_bytes());
+            match SkinnedModel::from_iqm_bytes(&huge_count) {
+                Err(ModelLoadingError::InvalidData { .. }) => {}
+                other => panic!("Unexpected result {:?}", other),
+            }
+        }
+        //Frames without channels take no space, so their count is not bounded by the file
+        let mut no_channels = iqm.clone();
+        no_channels[92..100].copy_from_slice(&ints(&[i32::MAX, 0]));
+        match SkinnedModel::from_iqm_bytes(&no_channels) {
+            Err(ModelLoadingError::InvalidData { .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_point_clouds() {
+        //Column names, comma separated colors from 0 to 255
+        let xyz = "X,Y,Z,R,G,B\n0,0,0,255,0,0\n1,2,3,0,51,255\n";
+        let model = ObjModel::from_xyz_bytes(xyz.as_bytes()).unwrap();
+        assert!(model.is_point_cloud());
+        assert_eq!(model.vertices()[1].position.z, 3.0);
+        assert_eq!((model.colors()[1].color.y, model.colors()[1].color.z), (0.2, 1.0));
+        assert!(model.normals().is_empty());
+
+        //Six columns of unit length are normals
+        let model = ObjModel::from_xyz_bytes(b"0 0 0 0 1 0\n1 0 0 0 0 1\n").unwrap();
+        assert_eq!(model.normals()[1].normal.z, 1.0);
+        assert!(model.colors().is_empty());
+        //A single color that happens to be a unit vector does not make the others normals
+        let model = ObjModel::from_xyz_bytes(b"0 0 0 0 1 0\n1 0 0 0 0.5 0.5\n").unwrap();
+        assert!(model.normals().is_empty());
+        assert_eq!(model.colors()[0].color.y, 1.0);
+
+        //Point counts between the scans, the intensity is only used without colors
+        let pts = "2\n0 0 0 -2048 255 255 255\n1 0 0 2047 0 0 0\n1\n2 0 0 0 255 0 0\n";
+        let model = ObjModel::from_pts_bytes(pts.as_bytes()).unwrap();
+        assert_eq!(model.vertices().len(), 3);
+        assert_eq!(model.colors()[2].color.x, 1.0);
+        let model = ObjModel::from_pts_bytes(b"2\n0 0 0 -2048\n1 0 0 2047\n").unwrap();
+        assert_eq!((model.colors()[0].color.x, model.colors()[1].color.x), (0.0, 1.0));
+        for pts in &["2\n0 0 0 0\n1\n1 0 0 0\n", "1\n0 0 0 0\n1 0 0 0\n", "0 0 0 0\n", "2\n0 0 0 0\n"] {
+            match ObjModel::from_pts_bytes(pts.as_bytes()) {
+                Err(ModelLoadingError::InvalidData { .. }) => {}
+                other => panic!("Unexpected result {:?}", other),
+            }
+        }
+
+        match ObjModel::from_xyz_bytes(b"0 0 0\n1 0\n") {
+            Err(ModelLoadingError::MissingComponents { expected: 3, found: 2, .. }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        //OBJ and PLY files without faces keep all their vertices
+        let model = ObjModel::from_str("v 0 0 0 1 0 0\nv 1 0 0 0 1 0\nv 0 1 0 0 0 1\n").unwrap();
+        assert!(model.is_point_cloud());
+        assert_eq!((model.vertices().len(), model.colors().len()), (3, 3));
+        let ply = "ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nproperty float y\nproperty float z\nend_header\n0 0 0\n1 1 1\n";
+        let model = ObjModel::from_ply_bytes(ply.as_bytes()).unwrap();
+        assert!(model.is_point_cloud());
+        assert_eq!(model.vertices()[1].position.y, 1.0);
+    }
+
     #[test]
     fn create_surface_normal() {
         let v0 = Vec3n::new(0.0, 0.0, 1.0);
@@ -57,6 +1357,33 @@ mod tests {
         println!("{}, {}, {}", n0, n1, n2);
     }
 
+    #[test]
+    fn triangulate_polygons() {
+        //Convex quad gets fanned
+        let quad = vec![
+            Vec3n::new(0.0, 0.0, 0.0),
+            Vec3n::new(1.0, 0.0, 0.0),
+            Vec3n::new(1.0, 1.0, 0.0),
+            Vec3n::new(0.0, 1.0, 0.0),
+        ];
+        assert_eq!(triangulate(&quad), vec![[0, 1, 2], [0, 2, 3]]);
+
+        //Concave arrow shape, fanning from the first corner would cover the notch at the last corner.
+        //Tolerances follow the size of the polygon, so a tiny arrow is no different
+        for &scale in &[1.0, 1e-7] {
+            let arrow: Vec<Vec3n> = [(-1.0, 1.0), (0.0, -1.0), (1.0, 1.0), (0.0, 0.5)].iter()
+                .map(|&(x, y)| Vec3n::new(x * scale, y * scale, 0.0))
+                .collect();
+            let triangles = triangulate(&arrow);
+            assert_eq!(triangles.len(), 2);
+            for t in triangles {
+                let e0 = arrow[t[1]] - arrow[t[0]];
+                let e1 = arrow[t[2]] - arrow[t[0]];
+                assert!(e0.cross(e1).z > 0.0);
+            }
+        }
+    }
+
     fn generate_normal_from_face(v0: Vec3n, v1: Vec3n, v2: Vec3n) -> Vec3n {
         let w0 = (v1 - v0).normalized();
         let w1 = (v2 - v0).normalized();
@@ -1,10 +1,12 @@
-use std::collections::{HashMap, HashSet};
-use std::error;
-use std::fmt;
+use std::collections::HashMap;
+use std::hash::Hash;
 use std::fs::File;
 use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
 use std::vec::*;
 
 use glium::Surface;
@@ -12,13 +14,136 @@ use straal::FloatType;
 use straal::vec3::Vec3;
 
 use super::*;
+use crate::error::{ModelLoadingError, READER_SOURCE_NAME, SourceLocation};
+use crate::mtl::Material;
+use crate::text::{LineContext, tokenize};
 
 #[derive(Debug)]
 pub struct ObjModel {
-    indices: Vec<u32>,
-    vertices: Vec<Vertex>,
-    normals: Vec<Normal>,
-    tex_coords: Vec<UV>,
+    pub(crate) indices: Vec<u32>,
+    pub(crate) vertices: Vec<Vertex>,
+    pub(crate) normals: Vec<Normal>,
+    pub(crate) tex_coords: Vec<UV>,
+    pub(crate) tangents: Vec<Tangent>,
+    pub(crate) colors: Vec<Color>,
+    pub(crate) materials: Vec<Material>,
+    pub(crate) material_libraries: Vec<String>,
+    pub(crate) sub_meshes: Vec<SubMesh>,
+    pub(crate) objects: Vec<NamedPart>,
+    pub(crate) groups: Vec<NamedPart>,
+    pub(crate) smoothing_groups: Vec<u32>,
+}
+
+//An object (o) or group (g) of the file and the parts of the index list its faces ended up in
+#[derive(Clone, Debug)]
+pub struct NamedPart {
+    pub name: String,
+    pub index_ranges: Vec<Range<usize>>,
+}
+
+//How the faces around a vertex are weighted when averaging their normals
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NormalWeighting {
+    //Every face counts the same, no matter its size or shape
+    Uniform,
+    //Faces contribute by their surface area, so slivers barely matter
+    Area,
+    //Faces contribute by the angle they make at the vertex
+    Angle,
+    //Both the area and the angle at the vertex
+    AreaAngle,
+}
+
+//How STL files are written
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StlFormat {
+    Ascii,
+    Binary,
+    //Binary with the vertex colors of every triangle averaged into its attribute word, using the VisCAM encoding
+    BinaryWithColors,
+}
+
+//Encoding of the data that follows the header of a PLY file
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+//A keyframe of a vertex animated model, with a position and normal for every vertex of the mesh
+#[derive(Clone, Debug)]
+pub struct MorphFrame {
+    pub name: String,
+    pub positions: Vec<straal::Vec3n>,
+    pub normals: Vec<straal::Vec3n>,
+}
+
+//An attachment point of an MD3 model in one frame, weapons and heads are attached to these
+#[derive(Clone, Debug)]
+pub struct Tag {
+    pub name: String,
+    pub origin: straal::Vec3n,
+    //Forward, left and up
+    pub axes: [straal::Vec3n; 3],
+}
+
+//A model animated by blending between whole frames of vertex positions, as MD2 and MD3 files are
+#[derive(Debug)]
+pub struct MorphModel {
+    //The mesh in its first frame
+    pub(crate) model: ObjModel,
+    pub(crate) frames: Vec<MorphFrame>,
+    //The tags of every frame, in the same order in each of them
+    pub(crate) tags: Vec<Vec<Tag>>,
+    pub(crate) skins: Vec<String>,
+}
+
+//Local transform of a joint relative to its parent, the rotation is a unit quaternion (x, y, z, w)
+#[derive(Copy, Clone, Debug)]
+pub struct JointPose {
+    pub translation: straal::Vec3n,
+    pub rotation: straal::Vec4n,
+    pub scale: straal::Vec3n,
+}
+
+#[derive(Clone, Debug)]
+pub struct Joint {
+    pub name: String,
+    //Parents always come before their children
+    pub parent: Option<usize>,
+    //The pose the mesh was bound to the skeleton in
+    pub bind_pose: JointPose,
+}
+
+//An animation of the skeleton, every frame has a pose for each joint
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    pub frame_rate: f32,
+    pub looping: bool,
+    pub frames: Vec<Vec<JointPose>>,
+}
+
+//A mesh deformed by a skeleton, as IQM files are
+#[derive(Debug)]
+pub struct SkinnedModel {
+    //The mesh in its bind pose
+    pub(crate) model: ObjModel,
+    //Empty when the file has no blend indices and weights
+    pub(crate) joint_weights: Vec<JointWeights>,
+    pub(crate) joints: Vec<Joint>,
+    pub(crate) animations: Vec<AnimationClip>,
+}
+
+//A part of the index list that is drawn with a single material
+#[derive(Clone, Debug)]
+pub struct SubMesh {
+    pub index_range: Range<usize>,
+    //Name from the usemtl statement, None when no material was selected
+    pub material_name: Option<String>,
+    //Index into the materials of the model, None when no material with that name was loaded
+    pub material: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -27,37 +152,79 @@ pub struct GliumBuffers {
     pub vertices: glium::VertexBuffer<Vertex>,
     pub normals: glium::VertexBuffer<Normal>,
     pub tex_coords: glium::VertexBuffer<UV>,
+    pub tangents: glium::VertexBuffer<Tangent>,
+    pub colors: glium::VertexBuffer<Color>,
+    pub joint_weights: glium::VertexBuffer<JointWeights>,
     pub has_normals: bool,
     pub has_tex_coords: bool,
+    pub has_tangents: bool,
+    pub has_colors: bool,
+    pub has_joint_weights: bool,
+    pub sub_meshes: Vec<SubMesh>,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct Vertex {
-    position: straal::Vec3n,
+    pub position: straal::Vec3n,
 }
 implement_vertex!(Vertex, position);
 
 #[derive(Copy, Clone, Debug)]
 pub struct Normal {
-    normal: straal::Vec3n,
+    pub normal: straal::Vec3n,
 }
 implement_vertex!(Normal, normal);
 
 #[derive(Copy, Clone, Debug)]
 pub struct UV {
-    tex_coords: straal::Vec2n,
+    pub tex_coords: straal::Vec2n,
 }
 implement_vertex!(UV, tex_coords);
 
+//The w component holds the handedness, bitangent = w * cross(normal, tangent)
+#[derive(Copy, Clone, Debug)]
+pub struct Tangent {
+    pub tangent: straal::Vec4n,
+}
+implement_vertex!(Tangent, tangent);
+
+//Linear RGBA, vertices of a file that only gave colors for some of them are white
+#[derive(Copy, Clone, Debug)]
+pub struct Color {
+    pub color: straal::Vec4n,
+}
+implement_vertex!(Color, color);
+
+//The four joints that move a vertex most and how much, the weights add up to 1
+#[derive(Copy, Clone, Debug)]
+pub struct JointWeights {
+    pub joints: [u32; 4],
+    pub weights: straal::Vec4n,
+}
+implement_vertex!(JointWeights, joints, weights);
 
-#[derive(Hash, PartialEq, Eq)]
+
+//0 based indices into the positions, normals and texture coordinates of the file
+#[derive(Hash, PartialEq, Eq, Copy, Clone)]
 struct FaceIndexTriplet {
     pub v: usize,
     pub n: Option<usize>,
     pub uv: Option<usize>,
 }
 
-#[derive(Debug)]
+struct Polygon {
+    corners: Vec<FaceIndexTriplet>,
+    //Index into the names of the materials selected with usemtl
+    material: Option<usize>,
+    //Index into the objects of the model
+    object: Option<usize>,
+    //Index into the group combinations used by the file, a face can be part of several groups
+    groups: Option<usize>,
+    //0 when the face is not smoothed
+    smoothing_group: u32,
+}
+
+#[derive(Debug, PartialEq)]
 enum TripletType {
     VertexOnly,
     VertexTexture,
@@ -65,126 +232,240 @@ enum TripletType {
     VertexTextureNormal,
 }
 
+impl ObjModel {
+    pub fn load_from_file(file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+        let file: File = File::open(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        //Material libraries are looked up next to the obj file
+        let material_dir = Path::new(file_path).parent();
+        ObjModel::parse(BufReader::new(file), file_path, material_dir, Vec::new())
+    }
 
-#[derive(Debug)]
-pub struct ModelLoadingError {
-    file_path: String,
-    message: String,
-    buffer_reader_error: Option<io::Error>,
-}
-
-impl fmt::Display for models::ModelLoadingError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Could not process the obj file")
+    //Models loaded from a reader have no location to resolve mtllib statements against,
+    //use from_reader_with_materials to supply the materials up front instead
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<ObjModel, ModelLoadingError> {
+        ObjModel::parse(reader, READER_SOURCE_NAME, None, Vec::new())
     }
-}
 
-impl error::Error for models::ModelLoadingError {
-    fn description(&self) -> &str { "Obj Error" }
-}
+    pub fn from_reader_with_materials<R: BufRead>(reader: R, materials: Vec<Material>) -> Result<ObjModel, ModelLoadingError> {
+        ObjModel::parse(reader, READER_SOURCE_NAME, None, materials)
+    }
 
-impl ObjModel {
-    pub fn load_from_file(file_path: &str) -> Result<ObjModel, ModelLoadingError> {
-        let file: File = match File::open(file_path) {
-            Ok(file) => file,
-            Err(e) => {
-                println!("{}", e);
-                return Err(ModelLoadingError {
-                    file_path: file_path.to_string(),
-                    message: "Could not open file!".to_string(),
-                    buffer_reader_error: None,
-                });
-            }
-        };
+    pub fn from_bytes(bytes: &[u8]) -> Result<ObjModel, ModelLoadingError> {
+        ObjModel::from_reader(bytes)
+    }
 
+    //The parsing core shared by all ways of loading a model, file_path is only used for error reporting.
+    //Material libraries are only loaded when there is a directory to find them in
+    fn parse<R: BufRead>(reader: R, file_path: &str, material_dir: Option<&Path>, mut materials: Vec<Material>)
+                         -> Result<ObjModel, ModelLoadingError> {
+        let mut material_libraries: Vec<String> = Vec::new();
         let mut vertices: Vec<straal::Vec3n> = Vec::new();
+        let mut colors: Vec<straal::Vec4n> = Vec::new();
+        let mut has_colors = false;
         let mut normals: Vec<straal::Vec3n> = Vec::new();
         let mut uvs: Vec<straal::Vec2n> = Vec::new();
-        let mut faces: Vec<FaceIndexTriplet> = Vec::new();
+        let mut polygons: Vec<Polygon> = Vec::new();
+        let mut material_names: Vec<String> = Vec::new();
+        let mut current_material: Option<usize> = None;
+        let mut objects: Vec<NamedPart> = Vec::new();
+        let mut groups: Vec<NamedPart> = Vec::new();
+        let mut group_combinations: Vec<Vec<usize>> = Vec::new();
+        let mut current_object: Option<usize> = None;
+        let mut current_groups: Option<usize> = None;
+        let mut current_smoothing_group: u32 = 0;
+        let mut has_smoothing_groups = false;
 
-        let mut line_no = 1;
-        for line in BufReader::new(file).lines() {
-            match line {
-                Ok(line) => {
-                    if !line.is_empty() {
-                        let tokens: Vec<&str> = line.split_whitespace().collect();
-                        if !tokens.is_empty() {
-                            match tokens[0] {
-                                "v" => {
-                                    //Parse vertex
-                                    //v x y z
-                                    let mut parsed = tokens.iter().skip(1).flat_map(|s: &&str| s.parse());
-
-                                    vertices.push(straal::Vec3 {
-                                        x: parsed.next().unwrap(),
-                                        y: parsed.next().unwrap(),
-                                        z: parsed.next().unwrap(),
-                                    });
-                                }
-                                "vn" => {
-                                    //Parse vertex normal
-                                    //vn x y z
-                                    let mut parsed = tokens.iter().skip(1).flat_map(|s: &&str| s.parse());
-                                    normals.push(straal::Vec3 {
-                                        x: parsed.next().unwrap(),
-                                        y: parsed.next().unwrap(),
-                                        z: parsed.next().unwrap(),
-                                    }.normalized());
-                                }
-                                "vt" => {
-                                    //Parse vertex texture coordinate
-                                    //vt x y
-                                    let mut parsed = tokens.iter().skip(1).flat_map(|s: &&str| s.parse());
-                                    uvs.push(straal::Vec2 {
-                                        x: parsed.next().unwrap(),
-                                        y: parsed.next().unwrap(),
-                                    });
-                                }
-                                "f" => {
-                                    //Parse polygon face
-                                    //f v1 v2 v3
-                                    //f v1/vt1 v2/vt2 v3/vt3
-                                    //f v1//vn1 v2//vn2 v3//vn3
-                                    //f v1/vt1/vn1 v2/vt2/vn2 v3/vt3/vn3
-                                    faces.append(&mut ObjModel::parse_face_line(&tokens));
-                                }
-                                "#" => { /*Comment, not much to do here*/ }
-                                "mtllib" => { /*Material file location*/ }
-                                "usemtl" => { /*Use material for the element following this statement*/ }
-                                "o" => { /*Object name*/ }
-                                "g" => { /*Group name*/ }
-                                "s" => { /*Smoothing enable/disable for smoothing group*/ }
-                                token => {
-                                    return Err(ModelLoadingError {
-                                        file_path: file_path.to_string(),
-                                        message: format!("Could not identify token {} for line: {}", token, line),
-                                        buffer_reader_error: None,
-                                    });
-                                }
-                            }
+        for (line_index, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| ModelLoadingError::Io {
+                location: SourceLocation::new(file_path, line_index + 1, 0),
+                source: e,
+            })?;
+            let context = LineContext { file_path, line_no: line_index + 1, line: &line };
+
+            let tokens = tokenize(&line);
+            if tokens.is_empty() {
+                continue;
+            }
+            match tokens[0].1 {
+                "v" => {
+                    //Parse vertex, optionally followed by a weight and/or a color
+                    //v x y z
+                    //v x y z w
+                    //v x y z r g b
+                    //v x y z r g b a
+                    //v x y z w r g b a
+                    let parsed = context.parse_floats(&tokens, 3, 8)?;
+                    vertices.push(straal::Vec3 {
+                        x: parsed[0],
+                        y: parsed[1],
+                        z: parsed[2],
+                    });
+                    //The weight only matters for rational curves and surfaces, it is ignored for polygons
+                    let color = match parsed.len() {
+                        3 | 4 => None,
+                        6 => Some(straal::Vec4n::new(parsed[3], parsed[4], parsed[5], 1.0)),
+                        7 => Some(straal::Vec4n::new(parsed[3], parsed[4], parsed[5], parsed[6])),
+                        8 => Some(straal::Vec4n::new(parsed[4], parsed[5], parsed[6], parsed[7])),
+                        found => return Err(ModelLoadingError::MissingComponents {
+                            location: context.location(tokens[0].0),
+                            text: line.to_string(),
+                            expected: 6,
+                            found,
+                        }),
+                    };
+                    has_colors |= color.is_some();
+                    colors.push(color.unwrap_or_else(|| straal::Vec4n::new(1.0, 1.0, 1.0, 1.0)));
+                }
+                "vn" => {
+                    //Parse vertex normal
+                    //vn x y z
+                    let parsed = context.parse_floats(&tokens, 3, 3)?;
+                    normals.push(straal::Vec3 {
+                        x: parsed[0],
+                        y: parsed[1],
+                        z: parsed[2],
+                    }.normalized());
+                }
+                "vt" => {
+                    //Parse vertex texture coordinate, v is optional and defaults to 0
+                    //vt u v
+                    let parsed = context.parse_floats(&tokens, 1, 2)?;
+                    uvs.push(straal::Vec2 {
+                        x: parsed[0],
+                        y: parsed.get(1).cloned().unwrap_or(0.0),
+                    });
+                }
+                "f" => {
+                    //Parse polygon face, any amount of corners is allowed
+                    //f v1 v2 v3 ...
+                    //f v1/vt1 v2/vt2 v3/vt3 ...
+                    //f v1//vn1 v2//vn2 v3//vn3 ...
+                    //f v1/vt1/vn1 v2/vt2/vn2 v3/vt3/vn3 ...
+                    polygons.push(Polygon {
+                        corners: ObjModel::parse_face_line(&context, &tokens, vertices.len(), uvs.len(), normals.len())?,
+                        material: current_material,
+                        object: current_object,
+                        groups: current_groups,
+                        smoothing_group: current_smoothing_group,
+                    });
+                }
+                "mtllib" => {
+                    //Material file location, multiple libraries can be listed on one line
+                    //mtllib file1.mtl file2.mtl ...
+                    for &(_, library) in &tokens[1..] {
+                        if let Some(dir) = material_dir {
+                            materials.append(&mut ObjModel::load_material_library(&dir.join(library))?);
                         }
+                        material_libraries.push(library.to_string());
                     }
                 }
-                Err(e) => {
-                    return Err(ModelLoadingError {
-                        file_path: file_path.to_string(),
-                        message: format!("Could not read line {}", line_no),
-                        buffer_reader_error: Some(e),
+                "usemtl" => {
+                    //Use material for the element following this statement
+                    //usemtl name
+                    let name = context.rest_of_line(&tokens, 1)?;
+                    current_material = match material_names.iter().position(|n| n == name) {
+                        Some(i) => Some(i),
+                        None => {
+                            material_names.push(name.to_string());
+                            Some(material_names.len() - 1)
+                        }
+                    };
+                }
+                "o" => {
+                    //Object name, faces up to the next o statement are part of it
+                    //o name
+                    let name = context.rest_of_line(&tokens, 1)?;
+                    current_object = Some(ObjModel::find_or_add_part(&mut objects, name));
+                }
+                "g" => {
+                    //Group names, the faces that follow belong to all of them, without names the group is "default"
+                    //g name1 name2 ...
+                    let mut combination: Vec<usize> = tokens[1..].iter()
+                        .map(|&(_, name)| ObjModel::find_or_add_part(&mut groups, name))
+                        .collect();
+                    if combination.is_empty() {
+                        combination.push(ObjModel::find_or_add_part(&mut groups, "default"));
+                    }
+                    combination.sort();
+                    combination.dedup();
+                    current_groups = match group_combinations.iter().position(|c| *c == combination) {
+                        Some(i) => Some(i),
+                        None => {
+                            group_combinations.push(combination);
+                            Some(group_combinations.len() - 1)
+                        }
+                    };
+                }
+                "s" => {
+                    //Smoothing group for the faces that follow, off and 0 disable smoothing
+                    //s 1
+                    //s off
+                    current_smoothing_group = match tokens.get(1) {
+                        Some(&(_, "off")) => 0,
+                        Some(&(column, text)) => text.parse().map_err(|e| ModelLoadingError::ParseIndex {
+                            location: context.location(column),
+                            text: text.to_string(),
+                            source: e,
+                        })?,
+                        None => {
+                            return Err(ModelLoadingError::MissingComponents {
+                                location: context.location(tokens[0].0),
+                                text: line.to_string(),
+                                expected: 1,
+                                found: 0,
+                            });
+                        }
+                    };
+                    has_smoothing_groups = true;
+                }
+                token if token.starts_with('#') => { /*Comment, not much to do here*/ }
+                token => {
+                    return Err(ModelLoadingError::UnsupportedStatement {
+                        location: context.location(tokens[0].0),
+                        text: token.to_string(),
                     });
                 }
             }
-            line_no += 1;
         }
 
-        let mut model = ObjModel {
-            indices: Vec::new(),
-            vertices: Vec::new(),
-            normals: Vec::new(),
-            tex_coords: Vec::new(),
-        };
+        let mut model = ObjModel { materials, material_libraries, objects, groups, ..ObjModel::empty() };
 
-        let mut mapped_triplets: HashMap<FaceIndexTriplet, u32> = HashMap::new();
+        //Split every polygon into triangles, now that all vertex positions are known.
+        //Every face corner becomes one index, so sub-mesh ranges can be tracked on the face list
+        let mut faces: Vec<FaceIndexTriplet> = Vec::new();
+        let mut sub_mesh_material = None;
+        for polygon in polygons {
+            if model.sub_meshes.is_empty() || sub_mesh_material != polygon.material {
+                model.start_sub_mesh(faces.len(), polygon.material.map(|m| material_names[m].as_str()));
+                sub_mesh_material = polygon.material;
+            }
 
+            let corners: Vec<straal::Vec3n> = polygon.corners.iter().map(|t| vertices[t.v]).collect();
+            let start = faces.len();
+            for triangle in triangulation::triangulate(&corners) {
+                faces.extend(triangle.iter().map(|&corner| polygon.corners[corner]));
+                if has_smoothing_groups {
+                    model.smoothing_groups.push(polygon.smoothing_group);
+                }
+            }
+
+            if let Some(object) = polygon.object {
+                model.objects[object].extend(start..faces.len());
+            }
+            if let Some(combination) = polygon.groups {
+                for &group in &group_combinations[combination] {
+                    model.groups[group].extend(start..faces.len());
+                }
+            }
+        }
+        if let Some(sub_mesh) = model.sub_meshes.last_mut() {
+            sub_mesh.index_range.end = faces.len();
+        }
+
+        let mut mapped_triplets: HashMap<FaceIndexTriplet, u32> = HashMap::new();
 
         for face_index_triplet in faces {
             match mapped_triplets.get(&face_index_triplet) {
@@ -192,13 +473,16 @@ impl ObjModel {
                     let index = mapped_triplets.len() as u32;
 
                     model.indices.push(index);
-                    model.vertices.push(Vertex { position: vertices[face_index_triplet.v - 1] });
+                    model.vertices.push(Vertex { position: vertices[face_index_triplet.v] });
+                    if has_colors {
+                        model.colors.push(Color { color: colors[face_index_triplet.v] });
+                    }
 
-                    if face_index_triplet.uv.is_some() {
-                        model.tex_coords.push(UV { tex_coords: uvs[face_index_triplet.uv.unwrap() - 1] });
+                    if let Some(uv) = face_index_triplet.uv {
+                        model.tex_coords.push(UV { tex_coords: uvs[uv] });
                     }
-                    if face_index_triplet.n.is_some() {
-                        model.normals.push(Normal { normal: normals[face_index_triplet.n.unwrap() - 1] });
+                    if let Some(n) = face_index_triplet.n {
+                        model.normals.push(Normal { normal: normals[n] });
                     }
 
                     mapped_triplets.insert(face_index_triplet, index);
@@ -209,106 +493,433 @@ impl ObjModel {
             }
         }
 
-        return Ok(model);
+        //Without faces the file is a point cloud, all vertices are kept and normals given in the same order belong to them
+        if model.indices.is_empty() {
+            model.vertices = vertices.iter().map(|&position| Vertex { position }).collect();
+            if has_colors {
+                model.colors = colors.iter().map(|&color| Color { color }).collect();
+            }
+            if normals.len() == vertices.len() {
+                model.normals = normals.iter().map(|&normal| Normal { normal }).collect();
+            }
+        }
+
+        Ok(model)
+    }
+
+    //A model without any geometry, loaders fill in what their format provides
+    pub(crate) fn empty() -> ObjModel {
+        ObjModel {
+            indices: Vec::new(),
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            tex_coords: Vec::new(),
+            tangents: Vec::new(),
+            colors: Vec::new(),
+            materials: Vec::new(),
+            material_libraries: Vec::new(),
+            sub_meshes: Vec::new(),
+            objects: Vec::new(),
+            groups: Vec::new(),
+            smoothing_groups: Vec::new(),
+        }
+    }
+
+    //Closes the current sub-mesh and starts a new one at the given index
+    fn start_sub_mesh(&mut self, start: usize, material_name: Option<&str>) {
+        if let Some(sub_mesh) = self.sub_meshes.last_mut() {
+            sub_mesh.index_range.end = start;
+        }
+        let material = material_name.and_then(|name| self.materials.iter().position(|m| m.name == name));
+        self.sub_meshes.push(SubMesh {
+            index_range: start..start,
+            material_name: material_name.map(|name| name.to_string()),
+            material,
+        });
+    }
+
+    //A referenced library that does not exist is not an error, the model is still usable without it
+    fn load_material_library(path: &Path) -> Result<Vec<Material>, ModelLoadingError> {
+        match Material::load_library_from_file(&path.to_string_lossy()) {
+            Err(ModelLoadingError::Io { ref source, .. }) if source.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            result => result,
+        }
     }
 
-    fn parse_face_line(tokens: &Vec<&str>) -> Vec<FaceIndexTriplet> {
-        let triplet_vec: Vec<&str> = tokens.iter().skip(1).flat_map(|s: &&str| s.split("/")).collect();
-        let mut parsed = triplet_vec.iter().flat_map(|s: &&str| s.parse());
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
 
-        let mut triangle = Vec::new();
-        match ObjModel::get_face_triplet_type(&triplet_vec) {
-            TripletType::VertexOnly => {
-                for _i in 0..3 {
-                    triangle.push(FaceIndexTriplet {
-                        v: parsed.next().unwrap(),
-                        uv: None,
-                        n: None,
-                    });
-                }
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn normals(&self) -> &[Normal] {
+        &self.normals
+    }
+
+    pub fn tex_coords(&self) -> &[UV] {
+        &self.tex_coords
+    }
+
+    pub fn tangents(&self) -> &[Tangent] {
+        &self.tangents
+    }
+
+    //Empty when the file has no vertex colors
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    //The names of all libraries referenced by mtllib statements, whether they could be loaded or not
+    pub fn material_libraries(&self) -> &[String] {
+        &self.material_libraries
+    }
+
+    //Consecutive runs of faces sharing a material, together they cover all indices
+    pub fn sub_meshes(&self) -> &[SubMesh] {
+        &self.sub_meshes
+    }
+
+    pub fn objects(&self) -> &[NamedPart] {
+        &self.objects
+    }
+
+    pub fn groups(&self) -> &[NamedPart] {
+        &self.groups
+    }
+
+    //Copies the faces of the named object into a model of its own, None when there is no such object
+    pub fn extract_object(&self, name: &str) -> Option<ObjModel> {
+        self.objects.iter().find(|o| o.name == name).map(|o| self.extract_index_ranges(&o.index_ranges))
+    }
+
+    //Copies the faces of the named group into a model of its own, None when there is no such group
+    pub fn extract_group(&self, name: &str) -> Option<ObjModel> {
+        self.groups.iter().find(|g| g.name == name).map(|g| self.extract_index_ranges(&g.index_ranges))
+    }
+
+    //Builds a model out of the given parts of the index list, only keeping the vertices they use.
+    //Sub-meshes, objects and groups are clipped to the extracted parts
+    fn extract_index_ranges(&self, ranges: &[Range<usize>]) -> ObjModel {
+        let mut model = ObjModel {
+            materials: self.materials.clone(),
+            material_libraries: self.material_libraries.clone(),
+            ..ObjModel::empty()
+        };
+
+        let mut mapped_vertices: HashMap<u32, u32> = HashMap::new();
+        for range in ranges {
+            for &index in &self.indices[range.clone()] {
+                let new_index = *mapped_vertices.entry(index).or_insert_with(|| {
+                    let i = index as usize;
+                    model.vertices.push(self.vertices[i]);
+                    if !self.normals.is_empty() {
+                        model.normals.push(self.normals[i]);
+                    }
+                    if !self.tex_coords.is_empty() {
+                        model.tex_coords.push(self.tex_coords[i]);
+                    }
+                    if !self.tangents.is_empty() {
+                        model.tangents.push(self.tangents[i]);
+                    }
+                    if !self.colors.is_empty() {
+                        model.colors.push(self.colors[i]);
+                    }
+                    (model.vertices.len() - 1) as u32
+                });
+                model.indices.push(new_index);
             }
-            TripletType::VertexTexture => {
-                for _i in 0..3 {
-                    triangle.push(FaceIndexTriplet {
-                        v: parsed.next().unwrap(),
-                        uv: Some(parsed.next().unwrap()),
-                        n: None,
-                    });
-                }
+            if !self.smoothing_groups.is_empty() {
+                model.smoothing_groups.extend_from_slice(&self.smoothing_groups[range.start / 3..range.end / 3]);
             }
-            TripletType::VertexNormal => {
-                //Need to filter the empty entries out
-                let mut parsed = triplet_vec.iter().skip_while(|s| s.is_empty()).flat_map(|s| s.parse());
-                for _i in 0..3 {
-                    triangle.push(FaceIndexTriplet {
-                        v: parsed.next().unwrap(),
-                        uv: None,
-                        n: Some(parsed.next().unwrap()),
-                    });
+        }
+
+        //Translates a range of the original index list into ranges of the extracted one
+        let clip = |part: &Range<usize>| -> Vec<Range<usize>> {
+            let mut clipped = Vec::new();
+            let mut offset = 0;
+            for range in ranges {
+                let start = part.start.max(range.start);
+                let end = part.end.min(range.end);
+                if start < end {
+                    clipped.push(start - range.start + offset..end - range.start + offset);
                 }
+                offset += range.len();
             }
-            TripletType::VertexTextureNormal => {
-                for _i in 0..3 {
-                    triangle.push(FaceIndexTriplet {
-                        v: parsed.next().unwrap(),
-                        uv: Some(parsed.next().unwrap()),
-                        n: Some(parsed.next().unwrap()),
-                    });
+            clipped
+        };
+
+        for sub_mesh in &self.sub_meshes {
+            for index_range in clip(&sub_mesh.index_range) {
+                model.sub_meshes.push(SubMesh { index_range, ..sub_mesh.clone() });
+            }
+        }
+        model.sub_meshes.sort_by_key(|s| s.index_range.start);
+        let clip_parts = |parts: &[NamedPart]| -> Vec<NamedPart> {
+            parts.iter().filter_map(|part| {
+                let mut clipped_part = NamedPart { name: part.name.clone(), index_ranges: Vec::new() };
+                for range in part.index_ranges.iter().flat_map(clip) {
+                    clipped_part.extend(range);
                 }
+                if clipped_part.index_ranges.is_empty() { None } else { Some(clipped_part) }
+            }).collect()
+        };
+        model.objects = clip_parts(&self.objects);
+        model.groups = clip_parts(&self.groups);
+
+        model
+    }
+
+    fn find_or_add_part(parts: &mut Vec<NamedPart>, name: &str) -> usize {
+        match parts.iter().position(|p| p.name == name) {
+            Some(i) => i,
+            None => {
+                parts.push(NamedPart { name: name.to_string(), index_ranges: Vec::new() });
+                parts.len() - 1
             }
         }
-        triangle
     }
 
-    fn get_face_triplet_type(triplets: &Vec<&str>) -> TripletType {
-        match triplets.len() {
-            3 => TripletType::VertexOnly,
-            6 => TripletType::VertexTexture,
-            9 => {
-                match triplets[1].len() {
-                    0 => TripletType::VertexNormal,
-                    _ => TripletType::VertexTextureNormal
+    fn parse_face_line(context: &LineContext, tokens: &[(usize, &str)], vertex_count: usize, uv_count: usize, normal_count: usize)
+                       -> Result<Vec<FaceIndexTriplet>, ModelLoadingError> {
+        let corners = &tokens[1..];
+        if corners.len() < 3 {
+            return Err(ModelLoadingError::UnsupportedFaceLayout {
+                location: context.location(tokens[0].0),
+                text: context.line.to_string(),
+            });
+        }
+
+        let mut polygon = Vec::with_capacity(corners.len());
+        let mut face_type = None;
+        for &(column, corner) in corners {
+            //Keep track of the column every part of the corner starts at
+            let mut parts = Vec::with_capacity(3);
+            let mut offset = 0;
+            for part in corner.split('/') {
+                parts.push((column + offset, part));
+                offset += part.len() + 1;
+            }
+
+            let triplet_type = ObjModel::get_face_triplet_type(&parts);
+            if triplet_type.is_none() || (face_type.is_some() && face_type != triplet_type) {
+                return Err(ModelLoadingError::UnsupportedFaceLayout {
+                    location: context.location(column),
+                    text: corner.to_string(),
+                });
+            }
+
+            let v = context.parse_index(parts[0].0, parts[0].1, vertex_count)?;
+            let triplet = match triplet_type {
+                Some(TripletType::VertexOnly) => FaceIndexTriplet { v, uv: None, n: None },
+                Some(TripletType::VertexTexture) => FaceIndexTriplet {
+                    v,
+                    uv: Some(context.parse_index(parts[1].0, parts[1].1, uv_count)?),
+                    n: None,
+                },
+                Some(TripletType::VertexNormal) => FaceIndexTriplet {
+                    v,
+                    uv: None,
+                    n: Some(context.parse_index(parts[2].0, parts[2].1, normal_count)?),
+                },
+                Some(TripletType::VertexTextureNormal) => FaceIndexTriplet {
+                    v,
+                    uv: Some(context.parse_index(parts[1].0, parts[1].1, uv_count)?),
+                    n: Some(context.parse_index(parts[2].0, parts[2].1, normal_count)?),
+                },
+                None => unreachable!(),
+            };
+            polygon.push(triplet);
+            face_type = triplet_type;
+        }
+        Ok(polygon)
+    }
+
+    fn get_face_triplet_type(parts: &[(usize, &str)]) -> Option<TripletType> {
+        match parts.len() {
+            1 => Some(TripletType::VertexOnly),
+            2 => Some(TripletType::VertexTexture),
+            3 => {
+                match parts[1].1.len() {
+                    0 => Some(TripletType::VertexNormal),
+                    _ => Some(TripletType::VertexTextureNormal)
                 }
             }
-            _ => panic!("Unknown face triplet type encountered")
+            _ => None
         }
     }
 
     pub fn generate_normals(&mut self) {
-        let mut normals = Vec::with_capacity(self.indices.len());
+        self.build_normals(NormalWeighting::Uniform, None, false);
+    }
+
+    //Like generate_normals, but the smoothing groups are bit masks as in 3DS files,
+    //faces smooth together when their masks share a bit
+    pub(crate) fn generate_normals_from_smoothing_masks(&mut self) {
+        self.build_normals(NormalWeighting::Uniform, None, true);
+    }
+
+    //Like generate_normals, but edges where the faces meet at an angle (in radians) larger than the crease angle
+    //stay sharp. The vertices along those edges get duplicated, rewriting the indices and vertex attributes
+    pub fn generate_normals_with_crease_angle(&mut self, crease_angle: f32) {
+        self.build_normals(NormalWeighting::Uniform, Some(crease_angle), false);
+    }
+
+    //Generates normals with the given weighting of the faces around every vertex, and an optional crease angle
+    pub fn generate_normals_weighted(&mut self, weighting: NormalWeighting, crease_angle: Option<f32>) {
+        self.build_normals(weighting, crease_angle, false);
+    }
+
+    fn build_normals(&mut self, weighting: NormalWeighting, crease_angle: Option<f32>, smoothing_masks: bool) {
+        let mut face_normals = Vec::with_capacity(self.indices.len() / 3);
         let mut i = 0;
-        while i < self.indices.len() {
+        while i + 2 < self.indices.len() {
             let v0 = self.vertices[self.indices[i] as usize].position;
             let v1 = self.vertices[self.indices[i + 1] as usize].position;
             let v2 = self.vertices[self.indices[i + 2] as usize].position;
             let n = ObjModel::three_vertices_to_normal(v0, v1, v2);
-            normals.push(n);
-            normals.push(n);
-            normals.push(n);
+            //Degenerate triangles have no direction to contribute
+            face_normals.push(if n.x.is_finite() && n.y.is_finite() && n.z.is_finite() { n } else { Vec3::zero() });
             i += 3;
         }
+        let corner_weights = self.corner_weights(weighting);
 
-        let mut normals_map = HashMap::with_capacity(self.vertices.len());
+        //Corners can only share a normal when they share a vertex and a smoothing group,
+        //faces outside of any smoothing group (s off) stay flat. Masks are compared per corner within the fan of a vertex
+        let smoothing_keys: Vec<(u32, u32, usize)> = self.indices.iter().enumerate().map(|(corner, &index)| {
+            match self.smoothing_groups.get(corner / 3) {
+                Some(0) => (index, 0, corner / 3),
+                Some(&group) if !smoothing_masks => (index, group, usize::MAX),
+                _ => (index, 0, usize::MAX),
+            }
+        }).collect();
+        let smooths_with = |corner: usize, other: usize| {
+            !smoothing_masks || corner / 3 == other / 3 || self.smoothing_groups.get(corner / 3).zip(self.smoothing_groups.get(other / 3)).map_or(true, |(a, b)| a & b != 0)
+        };
+        let mut fans: HashMap<(u32, u32, usize), Vec<usize>> = HashMap::with_capacity(self.vertices.len());
+        for (corner, key) in smoothing_keys.iter().enumerate() {
+            fans.entry(*key).or_default().push(corner);
+        }
 
-        for pair in self.indices.iter().zip(normals.iter()) {
-            if !normals_map.contains_key(pair.0) {
-                normals_map.insert(*pair.0, Vec::new());
+        //Within a fan every corner averages the faces that are within the crease angle of its own face
+        let min_cos = crease_angle.map(|angle| angle.cos());
+        let mut corner_normals = vec![Vec3::zero(); self.indices.len()];
+        for fan in fans.values() {
+            match min_cos {
+                None if !smoothing_masks => {
+                    let mut normal = Vec3::zero();
+                    for &corner in fan {
+                        normal += ObjModel::scaled(face_normals[corner / 3], corner_weights[corner]);
+                    }
+                    let normal = ObjModel::safe_normalized(normal);
+                    for &corner in fan {
+                        corner_normals[corner] = normal;
+                    }
+                }
+                _ => {
+                    for &corner in fan {
+                        let face_normal = face_normals[corner / 3];
+                        let mut normal = Vec3::zero();
+                        for &other in fan {
+                            let other_normal = face_normals[other / 3];
+                            if min_cos.map_or(true, |min_cos| face_normal.dot(other_normal) >= min_cos) && smooths_with(corner, other) {
+                                normal += ObjModel::scaled(other_normal, corner_weights[other]);
+                            }
+                        }
+                        corner_normals[corner] = ObjModel::safe_normalized(normal);
+                    }
+                }
             }
-            normals_map.get_mut(pair.0).unwrap().push(*pair.1);
         }
 
-        let mut final_normals = vec![Normal { normal: Vec3::zero() }; self.vertices.len()];
-        for normal_count_pair in normals_map {
-            let mut proper_normal = Vec3::zero();
-            for partial_normal in normal_count_pair.1 {
-                proper_normal += partial_normal;
+        //Corners of a fan that ended up with the same normal keep sharing a vertex
+        let corner_keys: Vec<((u32, u32, usize), [u32; 3])> = smoothing_keys.iter().zip(corner_normals.iter())
+            .map(|(&key, n)| (key, [n.x.to_bits(), n.y.to_bits(), n.z.to_bits()]))
+            .collect();
+        self.split_vertices(&corner_keys);
+
+        self.normals = vec![Normal { normal: Vec3::zero() }; self.vertices.len()];
+        for (&index, &normal) in self.indices.iter().zip(corner_normals.iter()) {
+            self.normals[index as usize] = Normal { normal };
+        }
+        //Tangents are built on top of the normals, so they have to be generated again
+        self.tangents.clear();
+    }
+
+    //How much the face of every corner contributes to the normal of the corner's vertex
+    fn corner_weights(&self, weighting: NormalWeighting) -> Vec<f32> {
+        let mut weights = Vec::with_capacity(self.indices.len());
+        for triangle in self.indices.chunks(3) {
+            let p: Vec<straal::Vec3n> = triangle.iter().map(|&i| self.vertices[i as usize].position).collect();
+            let area = match weighting {
+                NormalWeighting::Area | NormalWeighting::AreaAngle => {
+                    let cross = (p[1] - p[0]).cross(p[2] - p[0]);
+                    cross.dot(cross).sqrt() * 0.5
+                }
+                _ => 1.0,
+            };
+            for corner in 0..triangle.len() {
+                let angle = match weighting {
+                    NormalWeighting::Angle | NormalWeighting::AreaAngle => {
+                        let e0 = ObjModel::safe_normalized(p[(corner + 1) % 3] - p[corner]);
+                        let e1 = ObjModel::safe_normalized(p[(corner + 2) % 3] - p[corner]);
+                        e0.dot(e1).clamp(-1.0, 1.0).acos()
+                    }
+                    _ => 1.0,
+                };
+                weights.push(area * angle);
             }
-            proper_normal = proper_normal.normalized();
-            final_normals[normal_count_pair.0 as usize] = Normal { normal: proper_normal };
         }
-        self.normals = final_normals;
+        weights
+    }
+
+    pub(crate) fn scaled(v: straal::Vec3n, s: f32) -> straal::Vec3n {
+        straal::Vec3 { x: v.x * s, y: v.y * s, z: v.z * s }
     }
 
+    pub(crate) fn safe_normalized(v: straal::Vec3n) -> straal::Vec3n {
+        if v.dot(v) > 0.0 { v.normalized() } else { v }
+    }
+
+    //Gives every distinct key its own vertex, duplicating vertices that are used with different keys.
+    //Keys are given per index, corners that should keep sharing a vertex need equal keys
+    pub(crate) fn split_vertices<K: Hash + Eq>(&mut self, corner_keys: &[K]) {
+        let mut mapped_keys: HashMap<&K, u32> = HashMap::with_capacity(self.vertices.len());
+        let mut new_to_old: Vec<u32> = Vec::with_capacity(self.vertices.len());
+        for (index, key) in self.indices.iter_mut().zip(corner_keys.iter()) {
+            *index = *mapped_keys.entry(key).or_insert_with(|| {
+                new_to_old.push(*index);
+                (new_to_old.len() - 1) as u32
+            });
+        }
+        self.remap_vertices(&new_to_old);
+    }
+
+    //Rebuilds every per vertex attribute so that new vertex i is a copy of old vertex new_to_old[i]
+    fn remap_vertices(&mut self, new_to_old: &[u32]) {
+        fn remap<T: Copy>(attributes: &[T], new_to_old: &[u32]) -> Vec<T> {
+            if attributes.is_empty() {
+                return Vec::new();
+            }
+            new_to_old.iter().map(|&old| attributes[old as usize]).collect()
+        }
+        self.vertices = remap(&self.vertices, new_to_old);
+        self.normals = remap(&self.normals, new_to_old);
+        self.tex_coords = remap(&self.tex_coords, new_to_old);
+        self.tangents = remap(&self.tangents, new_to_old);
+        self.colors = remap(&self.colors, new_to_old);
+    }
+
+    //Smoothing group of every triangle, 0 means the triangle is not smoothed (s off).
+    //Empty when the file has no s statements, in which case all faces are smoothed together
+    pub fn smoothing_groups(&self) -> &[u32] {
+        &self.smoothing_groups
+    }
 
     //v0 is the point from where the normal is calculated
     fn three_vertices_to_normal(v0: straal::Vec3n, v1: straal::Vec3n, v2: straal::Vec3n) -> straal::Vec3n {
@@ -317,31 +928,99 @@ impl ObjModel {
         w0.cross(w1).normalized()
     }
 
+    //A model with vertices but no indices, as loaded from XYZ and PTS files or OBJ and PLY files without faces
+    pub fn is_point_cloud(&self) -> bool {
+        self.indices.is_empty() && !self.vertices.is_empty()
+    }
+
+    //Point clouds are drawn as points, with every vertex in a single sub-mesh
     pub fn gen_glium_buffer(&self, display: &glium::Display) -> GliumBuffers {
+        let points: Vec<u32>;
+        let (primitive, indices, sub_meshes) = if self.is_point_cloud() {
+            points = (0..self.vertices.len() as u32).collect();
+            let sub_mesh = SubMesh { index_range: 0..points.len(), material_name: None, material: None };
+            (glium::index::PrimitiveType::Points, &points[..], vec![sub_mesh])
+        } else {
+            (glium::index::PrimitiveType::TrianglesList, &self.indices[..], self.sub_meshes.clone())
+        };
         GliumBuffers {
-            indices: glium::IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList, &self.indices).unwrap(),
+            indices: glium::IndexBuffer::new(display, primitive, indices).unwrap(),
             vertices: glium::VertexBuffer::new(display, &self.vertices).unwrap(),
             normals: glium::VertexBuffer::new(display, &self.normals).unwrap(),
             tex_coords: glium::VertexBuffer::new(display, &self.tex_coords).unwrap(),
+            tangents: glium::VertexBuffer::new(display, &self.tangents).unwrap(),
+            colors: glium::VertexBuffer::new(display, &self.colors).unwrap(),
+            joint_weights: glium::VertexBuffer::new(display, &[]).unwrap(),
             has_normals: !self.normals.is_empty(),
             has_tex_coords: !self.tex_coords.is_empty(),
+            has_tangents: !self.tangents.is_empty(),
+            has_colors: !self.colors.is_empty(),
+            has_joint_weights: false,
+            sub_meshes,
         }
     }
 }
 
-impl GliumBuffers {
-    pub fn draw<U>(&self, target: &mut glium::Frame, program: &glium::Program, uniforms: &U, draw_params: &glium::DrawParameters) where U: glium::uniforms::Uniforms {
-        if self.has_tex_coords && self.has_normals {
-            target.draw((&self.vertices, &self.normals, &self.tex_coords), &self.indices, program, uniforms, draw_params).unwrap();
-        } else if self.has_tex_coords && !self.has_normals {
-            target.draw((&self.vertices, &self.tex_coords), &self.indices, program, uniforms, draw_params).unwrap();
-        } else if !self.has_tex_coords && self.has_normals {
-            target.draw((&self.vertices, &self.normals), &self.indices, program, uniforms, draw_params).unwrap();
-        } else {
-            target.draw(&self.vertices, &self.indices, program, uniforms, draw_params).unwrap();
+impl NamedPart {
+    //Adds a range of indices, merging it with the last range when they touch
+    pub(crate) fn extend(&mut self, range: Range<usize>) {
+        if range.start == range.end {
+            return;
+        }
+        match self.index_ranges.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => self.index_ranges.push(range),
         }
     }
 }
 
+impl FromStr for ObjModel {
+    type Err = ModelLoadingError;
+
+    fn from_str(source: &str) -> Result<ObjModel, ModelLoadingError> {
+        ObjModel::from_reader(source.as_bytes())
+    }
+}
+
+impl GliumBuffers {
+    pub fn draw<U>(&self, target: &mut glium::Frame, program: &glium::Program, uniforms: &U, draw_params: &glium::DrawParameters) where U: glium::uniforms::Uniforms {
+        self.draw_indices(target, &self.indices, program, uniforms, draw_params);
+    }
+
+    //Draws a single sub-mesh, so different uniforms (textures, colors) can be bound for every material
+    pub fn draw_sub_mesh<U>(&self, sub_mesh: usize, target: &mut glium::Frame, program: &glium::Program, uniforms: &U, draw_params: &glium::DrawParameters) where U: glium::uniforms::Uniforms {
+        let range = self.sub_meshes[sub_mesh].index_range.clone();
+        self.draw_indices(target, self.indices.slice(range).unwrap(), program, uniforms, draw_params);
+    }
+
+    //Draws all sub-meshes one after the other, asking for the uniforms to use for each of them
+    pub fn draw_sub_meshes<U, F>(&self, target: &mut glium::Frame, program: &glium::Program, mut uniforms: F, draw_params: &glium::DrawParameters)
+        where U: glium::uniforms::Uniforms, F: FnMut(&SubMesh) -> U {
+        for (i, sub_mesh) in self.sub_meshes.iter().enumerate() {
+            self.draw_sub_mesh(i, target, program, &uniforms(sub_mesh), draw_params);
+        }
+    }
 
+    fn draw_indices<'a, I, U>(&self, target: &mut glium::Frame, indices: I, program: &glium::Program, uniforms: &U, draw_params: &glium::DrawParameters)
+        where I: Into<glium::index::IndicesSource<'a>>, U: glium::uniforms::Uniforms {
+        let vertex_sources = (
+            &self.vertices,
+            self.optional_source(self.has_normals, &self.normals),
+            self.optional_source(self.has_tex_coords, &self.tex_coords),
+            self.optional_source(self.has_tangents, &self.tangents),
+            self.optional_source(self.has_colors, &self.colors),
+            self.optional_source(self.has_joint_weights, &self.joint_weights),
+        );
+        target.draw(vertex_sources, indices, program, uniforms, draw_params).unwrap();
+    }
 
+    //Attributes the model does not have are replaced by a marker that provides no attributes at all
+    fn optional_source<'a, T>(&self, present: bool, buffer: &'a glium::VertexBuffer<T>) -> glium::vertex::VerticesSource<'a>
+        where T: glium::Vertex + Copy {
+        if present {
+            buffer.into()
+        } else {
+            glium::vertex::EmptyVertexAttributes { len: self.vertices.len() }.into()
+        }
+    }
+}
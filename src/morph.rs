@@ -0,0 +1,65 @@
+use straal::Vec3n;
+
+use crate::models::*;
+
+impl MorphModel {
+    //The mesh in its first frame, with the indices, texture coordinates and sub-meshes every frame shares
+    pub fn model(&self) -> &ObjModel {
+        &self.model
+    }
+
+    pub fn frames(&self) -> &[MorphFrame] {
+        &self.frames
+    }
+
+    //Tags of a frame, empty for formats without tags
+    pub fn tags(&self, frame: usize) -> &[Tag] {
+        self.tags.get(frame).map(|tags| &tags[..]).unwrap_or(&[])
+    }
+
+    //Texture or shader names listed by the file
+    pub fn skins(&self) -> &[String] {
+        &self.skins
+    }
+
+    //Blends the vertices of two frames, t = 0 gives frame `from` and t = 1 frame `to`.
+    //Frame numbers wrap around so a looping animation can simply keep counting
+    pub fn interpolate(&self, from: usize, to: usize, t: f32) -> (Vec<Vertex>, Vec<Normal>) {
+        let a = &self.frames[from % self.frames.len()];
+        let b = &self.frames[to % self.frames.len()];
+        let vertices = a.positions.iter().zip(b.positions.iter()).map(|(&p, &q)| Vertex { position: lerp(p, q, t) }).collect();
+        let normals = a.normals.iter().zip(b.normals.iter())
+            .map(|(&n, &m)| Normal { normal: ObjModel::safe_normalized(lerp(n, m, t)) })
+            .collect();
+        (vertices, normals)
+    }
+
+    //Blends a tag between two frames, the axes are normalized again but not orthogonalized
+    pub fn interpolate_tag(&self, tag: &str, from: usize, to: usize, t: f32) -> Option<Tag> {
+        let find = |frame: usize| self.tags(frame % self.frames.len()).iter().find(|candidate| candidate.name == tag);
+        let (a, b) = (find(from)?, find(to)?);
+        let axis = |i: usize| ObjModel::safe_normalized(lerp(a.axes[i], b.axes[i], t));
+        Some(Tag { name: a.name.clone(), origin: lerp(a.origin, b.origin, t), axes: [axis(0), axis(1), axis(2)] })
+    }
+
+    //Creates buffers whose vertices and normals can be rewritten every frame with write_frame_to
+    pub fn gen_glium_buffer(&self, display: &glium::Display) -> GliumBuffers {
+        let mut buffers = self.model.gen_glium_buffer(display);
+        buffers.vertices = glium::VertexBuffer::dynamic(display, &self.model.vertices).unwrap();
+        buffers.normals = glium::VertexBuffer::dynamic(display, &self.model.normals).unwrap();
+        buffers
+    }
+
+    //Uploads a blend of two frames to buffers made by gen_glium_buffer
+    pub fn write_frame_to(&self, buffers: &GliumBuffers, from: usize, to: usize, t: f32) {
+        let (vertices, normals) = self.interpolate(from, to, t);
+        buffers.vertices.write(&vertices);
+        if buffers.has_normals {
+            buffers.normals.write(&normals);
+        }
+    }
+}
+
+fn lerp(a: Vec3n, b: Vec3n, t: f32) -> Vec3n {
+    a + ObjModel::scaled(b - a, t)
+}
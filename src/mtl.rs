@@ -0,0 +1,328 @@
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+
+use straal::Vec3n;
+
+use crate::error::{ModelLoadingError, READER_SOURCE_NAME, SourceLocation};
+use crate::text::{LineContext, tokenize};
+
+#[derive(Debug, Clone, Default)]
+pub struct Material {
+    pub name: String,
+
+    //Ka, Kd, Ks, Ke and Tf
+    pub ambient: Option<Vec3n>,
+    pub diffuse: Option<Vec3n>,
+    pub specular: Option<Vec3n>,
+    pub emissive: Option<Vec3n>,
+    pub transmission_filter: Option<Vec3n>,
+
+    //Ns, Ni, d (or 1 - Tr) and illum
+    pub shininess: Option<f32>,
+    pub optical_density: Option<f32>,
+    pub dissolve: Option<f32>,
+    pub illumination_model: Option<u32>,
+
+    //PBR extension: Pr, Pm, Ps, Pc, Pcr, aniso and anisor
+    pub roughness: Option<f32>,
+    pub metallic: Option<f32>,
+    pub sheen: Option<f32>,
+    pub clearcoat_thickness: Option<f32>,
+    pub clearcoat_roughness: Option<f32>,
+    pub anisotropy: Option<f32>,
+    pub anisotropy_rotation: Option<f32>,
+
+    //map_Ka, map_Kd, map_Ks, map_Ke, map_Ns and map_d
+    pub ambient_map: Option<TextureMap>,
+    pub diffuse_map: Option<TextureMap>,
+    pub specular_map: Option<TextureMap>,
+    pub emissive_map: Option<TextureMap>,
+    pub shininess_map: Option<TextureMap>,
+    pub dissolve_map: Option<TextureMap>,
+    //map_Bump or bump, norm, disp, decal and refl
+    pub bump_map: Option<TextureMap>,
+    pub normal_map: Option<TextureMap>,
+    pub displacement_map: Option<TextureMap>,
+    pub decal_map: Option<TextureMap>,
+    pub reflection_map: Option<TextureMap>,
+    //PBR extension: map_Pr, map_Pm and map_Ps
+    pub roughness_map: Option<TextureMap>,
+    pub metallic_map: Option<TextureMap>,
+    pub sheen_map: Option<TextureMap>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextureMap {
+    //The path exactly as written in the material library
+    pub path: String,
+    pub options: TextureOptions,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextureOptions {
+    pub blend_u: bool,
+    pub blend_v: bool,
+    pub color_correction: bool,
+    pub clamp: bool,
+    pub boost: Option<f32>,
+    //Base value and gain from -mm
+    pub range: (f32, f32),
+    pub offset: Vec3n,
+    pub scale: Vec3n,
+    pub turbulence: Vec3n,
+    pub resolution: Option<u32>,
+    pub bump_multiplier: f32,
+    pub channel: Option<String>,
+    pub reflection_type: Option<String>,
+}
+
+impl Default for TextureOptions {
+    fn default() -> TextureOptions {
+        TextureOptions {
+            blend_u: true,
+            blend_v: true,
+            color_correction: false,
+            clamp: false,
+            boost: None,
+            range: (0.0, 1.0),
+            offset: Vec3n::new(0.0, 0.0, 0.0),
+            scale: Vec3n::new(1.0, 1.0, 1.0),
+            turbulence: Vec3n::new(0.0, 0.0, 0.0),
+            resolution: None,
+            bump_multiplier: 1.0,
+            channel: None,
+            reflection_type: None,
+        }
+    }
+}
+
+impl Material {
+    pub fn load_library_from_file(file_path: &str) -> Result<Vec<Material>, ModelLoadingError> {
+        let file: File = File::open(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        Material::parse_library(BufReader::new(file), file_path)
+    }
+
+    pub fn load_library_from_reader<R: BufRead>(reader: R) -> Result<Vec<Material>, ModelLoadingError> {
+        Material::parse_library(reader, READER_SOURCE_NAME)
+    }
+
+    fn parse_library<R: BufRead>(reader: R, file_path: &str) -> Result<Vec<Material>, ModelLoadingError> {
+        let mut materials: Vec<Material> = Vec::new();
+
+        for (line_index, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| ModelLoadingError::Io {
+                location: SourceLocation::new(file_path, line_index + 1, 0),
+                source: e,
+            })?;
+            let context = LineContext { file_path, line_no: line_index + 1, line: &line };
+
+            let tokens = tokenize(&line);
+            if tokens.is_empty() || tokens[0].1.starts_with('#') {
+                continue;
+            }
+
+            if tokens[0].1 == "newmtl" {
+                //Material names are allowed to contain spaces
+                let name = context.rest_of_line(&tokens, 1)?;
+                materials.push(Material { name: name.to_string(), ..Default::default() });
+                continue;
+            }
+
+            //Statements before the first newmtl have nothing to apply to
+            let material = match materials.last_mut() {
+                Some(material) => material,
+                None => continue,
+            };
+
+            match tokens[0].1 {
+                "Ka" => material.ambient = parse_color(&context, &tokens)?,
+                "Kd" => material.diffuse = parse_color(&context, &tokens)?,
+                "Ks" => material.specular = parse_color(&context, &tokens)?,
+                "Ke" => material.emissive = parse_color(&context, &tokens)?,
+                "Tf" => material.transmission_filter = parse_color(&context, &tokens)?,
+                "Ns" => material.shininess = Some(parse_scalar(&context, &tokens)?),
+                "Ni" => material.optical_density = Some(parse_scalar(&context, &tokens)?),
+                "d" => material.dissolve = Some(parse_scalar(&context, &tokens)?),
+                "Tr" => material.dissolve = Some(1.0 - parse_scalar(&context, &tokens)?),
+                "illum" => material.illumination_model = Some(parse_scalar(&context, &tokens)? as u32),
+                "Pr" => material.roughness = Some(parse_scalar(&context, &tokens)?),
+                "Pm" => material.metallic = Some(parse_scalar(&context, &tokens)?),
+                "Ps" => material.sheen = Some(parse_scalar(&context, &tokens)?),
+                "Pc" => material.clearcoat_thickness = Some(parse_scalar(&context, &tokens)?),
+                "Pcr" => material.clearcoat_roughness = Some(parse_scalar(&context, &tokens)?),
+                "aniso" => material.anisotropy = Some(parse_scalar(&context, &tokens)?),
+                "anisor" => material.anisotropy_rotation = Some(parse_scalar(&context, &tokens)?),
+                "map_Ka" => material.ambient_map = Some(parse_texture_map(&context, &tokens)?),
+                "map_Kd" => material.diffuse_map = Some(parse_texture_map(&context, &tokens)?),
+                "map_Ks" => material.specular_map = Some(parse_texture_map(&context, &tokens)?),
+                "map_Ke" => material.emissive_map = Some(parse_texture_map(&context, &tokens)?),
+                "map_Ns" => material.shininess_map = Some(parse_texture_map(&context, &tokens)?),
+                "map_d" => material.dissolve_map = Some(parse_texture_map(&context, &tokens)?),
+                "map_Bump" | "map_bump" | "bump" => material.bump_map = Some(parse_texture_map(&context, &tokens)?),
+                "norm" => material.normal_map = Some(parse_texture_map(&context, &tokens)?),
+                "disp" => material.displacement_map = Some(parse_texture_map(&context, &tokens)?),
+                "decal" => material.decal_map = Some(parse_texture_map(&context, &tokens)?),
+                "refl" => material.reflection_map = Some(parse_texture_map(&context, &tokens)?),
+                "map_Pr" => material.roughness_map = Some(parse_texture_map(&context, &tokens)?),
+                "map_Pm" => material.metallic_map = Some(parse_texture_map(&context, &tokens)?),
+                "map_Ps" => material.sheen_map = Some(parse_texture_map(&context, &tokens)?),
+                //Exporters write plenty of vendor specific statements, anything unknown is skipped
+                _ => {}
+            }
+        }
+
+        Ok(materials)
+    }
+}
+
+//Ka r [g b], when only r is given it is used for all channels.
+//Ka xyz x [y z] uses CIE XYZ values which are stored as they are, spectral curves are not supported
+fn parse_color(context: &LineContext, tokens: &[(usize, &str)]) -> Result<Option<Vec3n>, ModelLoadingError> {
+    let tokens = match tokens.get(1) {
+        Some(&(_, "spectral")) => return Ok(None),
+        Some(&(_, "xyz")) => &tokens[1..],
+        _ => tokens,
+    };
+    let parsed = context.parse_floats(tokens, 1, 3)?;
+    match parsed.len() {
+        1 | 2 => Ok(Some(Vec3n::new(parsed[0], parsed[0], parsed[0]))),
+        _ => Ok(Some(Vec3n::new(parsed[0], parsed[1], parsed[2]))),
+    }
+}
+
+//Ns 10, also handles d -halo 0.5 by skipping the option
+fn parse_scalar(context: &LineContext, tokens: &[(usize, &str)]) -> Result<f32, ModelLoadingError> {
+    match tokens.get(1) {
+        Some(&(_, "-halo")) => context.parse_floats(&tokens[1..], 1, 1).map(|parsed| parsed[0]),
+        _ => context.parse_floats(tokens, 1, 1).map(|parsed| parsed[0]),
+    }
+}
+
+//map_Kd [-option values...] path
+fn parse_texture_map(context: &LineContext, tokens: &[(usize, &str)]) -> Result<TextureMap, ModelLoadingError> {
+    let mut options = TextureOptions::default();
+    let mut i = 1;
+    while i < tokens.len() {
+        let (column, option) = tokens[i];
+        let value_count = match option {
+            "-blendu" => {
+                options.blend_u = parse_switch(context, tokens, i)?;
+                1
+            }
+            "-blendv" => {
+                options.blend_v = parse_switch(context, tokens, i)?;
+                1
+            }
+            "-cc" => {
+                options.color_correction = parse_switch(context, tokens, i)?;
+                1
+            }
+            "-clamp" => {
+                options.clamp = parse_switch(context, tokens, i)?;
+                1
+            }
+            "-boost" => {
+                options.boost = Some(parse_option_floats(context, tokens, i, 1, 1)?[0]);
+                1
+            }
+            "-bm" => {
+                options.bump_multiplier = parse_option_floats(context, tokens, i, 1, 1)?[0];
+                1
+            }
+            "-texres" => {
+                options.resolution = Some(parse_option_floats(context, tokens, i, 1, 1)?[0] as u32);
+                1
+            }
+            "-mm" => {
+                let parsed = parse_option_floats(context, tokens, i, 2, 2)?;
+                options.range = (parsed[0], parsed[1]);
+                2
+            }
+            "-o" | "-s" | "-t" => {
+                let parsed = parse_option_floats(context, tokens, i, 1, 3)?;
+                let default = if option == "-s" { 1.0 } else { 0.0 };
+                let value = Vec3n::new(parsed[0],
+                                       parsed.get(1).cloned().unwrap_or(default),
+                                       parsed.get(2).cloned().unwrap_or(default));
+                match option {
+                    "-o" => options.offset = value,
+                    "-s" => options.scale = value,
+                    _ => options.turbulence = value,
+                }
+                parsed.len()
+            }
+            "-imfchan" => {
+                options.channel = Some(option_value(context, tokens, i)?.to_string());
+                1
+            }
+            "-type" => {
+                options.reflection_type = Some(option_value(context, tokens, i)?.to_string());
+                1
+            }
+            //Everything from the first token that is not an option onwards is the path
+            _ => {
+                return Ok(TextureMap {
+                    path: context.line[column - 1..].trim_end().to_string(),
+                    options,
+                });
+            }
+        };
+        i += 1 + value_count;
+    }
+
+    Err(ModelLoadingError::MissingComponents {
+        location: context.location(tokens[0].0),
+        text: context.line.to_string(),
+        expected: i,
+        found: tokens.len() - 1,
+    })
+}
+
+fn option_value<'a>(context: &LineContext, tokens: &[(usize, &'a str)], option_index: usize) -> Result<&'a str, ModelLoadingError> {
+    match tokens.get(option_index + 1) {
+        Some(&(_, value)) => Ok(value),
+        None => Err(ModelLoadingError::MissingComponents {
+            location: context.location(tokens[option_index].0),
+            text: context.line.to_string(),
+            expected: 1,
+            found: 0,
+        }),
+    }
+}
+
+fn parse_switch(context: &LineContext, tokens: &[(usize, &str)], option_index: usize) -> Result<bool, ModelLoadingError> {
+    match option_value(context, tokens, option_index)? {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        value => Err(ModelLoadingError::InvalidValue {
+            location: context.location(tokens[option_index + 1].0),
+            text: value.to_string(),
+            expected: "on or off",
+        }),
+    }
+}
+
+//Reads the numbers following an option, optional values stop at the first token that is not a number
+fn parse_option_floats(context: &LineContext, tokens: &[(usize, &str)], option_index: usize, required: usize, max: usize)
+                       -> Result<Vec<f32>, ModelLoadingError> {
+    let mut parsed = Vec::with_capacity(max);
+    for &(column, text) in tokens.iter().skip(option_index + 1).take(max) {
+        if parsed.len() >= required && text.parse::<f32>().is_err() {
+            break;
+        }
+        parsed.push(context.parse_float(column, text)?);
+    }
+    if parsed.len() < required {
+        return Err(ModelLoadingError::MissingComponents {
+            location: context.location(tokens[option_index].0),
+            text: context.line.to_string(),
+            expected: required,
+            found: parsed.len(),
+        });
+    }
+    Ok(parsed)
+}
@@ -0,0 +1,253 @@
+use std::fs;
+
+use straal::{Vec3n, Vec4n};
+
+use crate::error::{BYTES_SOURCE_NAME, ModelLoadingError, SourceLocation};
+use crate::models::*;
+use crate::text::{LineContext, tokenize};
+use crate::triangulation;
+
+//Which optional parts every vertex has, from the prefixes of the header keyword
+#[derive(Default)]
+struct OffHeader {
+    tex_coords: bool,
+    colors: bool,
+    normals: bool,
+    //4OFF has four coordinates and nOFF gives their number after the keyword
+    four_dimensional: bool,
+    any_dimension: bool,
+}
+
+struct Face {
+    corners: Vec<u32>,
+    color: Option<Vec4n>,
+}
+
+impl ObjModel {
+    //Loads an OFF file and the COFF, NOFF, STOFF, 4OFF and nOFF variants, polygons are triangulated.
+    //Face colors are kept by giving every colored face its own vertices when the vertices have no colors
+    pub fn load_off_from_file(file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+        let bytes = fs::read(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        ObjModel::parse_off(&String::from_utf8_lossy(&bytes), file_path)
+    }
+
+    pub fn from_off_bytes(bytes: &[u8]) -> Result<ObjModel, ModelLoadingError> {
+        ObjModel::parse_off(&String::from_utf8_lossy(bytes), BYTES_SOURCE_NAME)
+    }
+
+    fn parse_off(source: &str, file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+        //Comments start with # and run to the end of the line, empty lines carry no meaning
+        let mut lines = source.lines().enumerate().filter_map(|(line_index, line)| {
+            let content = line.split('#').next().unwrap_or("");
+            let tokens = tokenize(content);
+            if tokens.is_empty() { None } else { Some((LineContext { file_path, line_no: line_index + 1, line }, tokens)) }
+        });
+
+        let (header_context, header_tokens) = lines.next().ok_or_else(|| ModelLoadingError::InvalidData {
+            location: SourceLocation::file(file_path),
+            description: "the file is empty".to_string(),
+        })?;
+        let (keyword_column, keyword) = header_tokens[0];
+        let header = parse_off_keyword(keyword).ok_or_else(|| ModelLoadingError::InvalidValue {
+            location: header_context.location(keyword_column),
+            text: keyword.to_string(),
+            expected: "an OFF header such as OFF, COFF, NOFF or STCNOFF",
+        })?;
+
+        //The counts may share the line with the keyword, some exporters even leave out the space after it
+        let mut numbers: Vec<(usize, usize, String)> = Vec::new();
+        let glued = &keyword[keyword.find("OFF").unwrap_or(0) + 3..];
+        if !glued.is_empty() {
+            numbers.push((header_context.line_no, keyword_column + keyword.len() - glued.len(), glued.to_string()));
+        }
+        for &(column, text) in &header_tokens[1..] {
+            if text == "BINARY" {
+                return Err(ModelLoadingError::UnsupportedStatement { location: header_context.location(column), text: text.to_string() });
+            }
+            numbers.push((header_context.line_no, column, text.to_string()));
+        }
+        let required_numbers = if header.any_dimension { 3 } else { 2 };
+        while numbers.len() < required_numbers {
+            match lines.next() {
+                Some((context, tokens)) => numbers.extend(tokens.iter().map(|&(column, text)| (context.line_no, column, text.to_string()))),
+                None => return Err(ModelLoadingError::InvalidData {
+                    location: SourceLocation::file(file_path),
+                    description: "the header is missing the vertex and face counts".to_string(),
+                }),
+            }
+        }
+        let count = |(line, column, text): &(usize, usize, String)| text.parse::<usize>().map_err(|_| ModelLoadingError::InvalidValue {
+            location: SourceLocation::new(file_path, *line, *column),
+            text: text.clone(),
+            expected: "a count",
+        });
+        let (dimension, counts) = if header.any_dimension {
+            (count(&numbers[0])?, &numbers[1..])
+        } else if header.four_dimensional {
+            (4, &numbers[..])
+        } else {
+            (3, &numbers[..])
+        };
+        let vertex_count = count(&counts[0])?;
+        let face_count = count(&counts[1])?;
+        if dimension < 3 {
+            return Err(ModelLoadingError::InvalidValue {
+                location: SourceLocation::new(file_path, numbers[0].0, numbers[0].1),
+                text: numbers[0].2.clone(),
+                expected: "a dimension of at least 3",
+            });
+        }
+
+        let mut model = ObjModel::empty();
+        //Colors keep their values until the scale of the file is known
+        let mut vertex_colors: Vec<Vec<f32>> = Vec::new();
+        let normal_components = if header.normals { 3 } else { 0 };
+        let tex_coord_components = if header.tex_coords { 2 } else { 0 };
+        let color_components = if header.colors { 3 } else { 0 };
+        let required = dimension + normal_components + color_components + tex_coord_components;
+        let premature_end = |what: &str| ModelLoadingError::InvalidData {
+            location: SourceLocation::file(file_path),
+            description: format!("the file ends before all {} {} were read", if what == "vertices" { vertex_count } else { face_count }, what),
+        };
+
+        for _ in 0..vertex_count {
+            let (context, tokens) = lines.next().ok_or_else(|| premature_end("vertices"))?;
+            if tokens.len() < required {
+                return Err(ModelLoadingError::MissingComponents {
+                    location: context.location(tokens[0].0),
+                    text: context.line.to_string(),
+                    expected: required,
+                    found: tokens.len(),
+                });
+            }
+            let values = tokens.iter().map(|&(column, text)| context.parse_float(column, text)).collect::<Result<Vec<f32>, _>>()?;
+            model.vertices.push(Vertex { position: Vec3n::new(values[0], values[1], values[2]) });
+            let mut at = dimension;
+            if header.normals {
+                model.normals.push(Normal { normal: Vec3n::new(values[at], values[at + 1], values[at + 2]) });
+                at += 3;
+            }
+            if header.colors {
+                //Colors have an optional alpha, so the texture coordinates are counted from the end
+                let color_end = (values.len() - tex_coord_components).min(at + 4);
+                vertex_colors.push(values[at..color_end].to_vec());
+                at = color_end;
+            }
+            if header.tex_coords {
+                model.tex_coords.push(UV { tex_coords: straal::Vec2 { x: values[at], y: values[at + 1] } });
+            }
+        }
+
+        let mut faces: Vec<(Vec<u32>, Option<Vec<f32>>)> = Vec::new();
+        for _ in 0..face_count {
+            let (context, tokens) = lines.next().ok_or_else(|| premature_end("faces"))?;
+            let corner_count: usize = tokens[0].1.parse().map_err(|_| ModelLoadingError::InvalidValue {
+                location: context.location(tokens[0].0),
+                text: tokens[0].1.to_string(),
+                expected: "the number of corners of the face",
+            })?;
+            if tokens.len() < corner_count + 1 {
+                return Err(ModelLoadingError::MissingComponents {
+                    location: context.location(tokens[0].0),
+                    text: context.line.to_string(),
+                    expected: corner_count,
+                    found: tokens.len() - 1,
+                });
+            }
+            let mut corners = Vec::with_capacity(corner_count);
+            for &(column, text) in &tokens[1..=corner_count] {
+                let index: i64 = text.parse().map_err(|e| ModelLoadingError::ParseIndex {
+                    location: context.location(column),
+                    text: text.to_string(),
+                    source: e,
+                })?;
+                if index < 0 || index as usize >= vertex_count {
+                    return Err(ModelLoadingError::IndexOutOfRange { location: context.location(column), text: text.to_string(), index, count: vertex_count });
+                }
+                corners.push(index as u32);
+            }
+            //A face color has three or four components, a single number refers to a color map and is ignored
+            let extra = &tokens[corner_count + 1..];
+            let color = if extra.len() >= 3 {
+                Some(extra.iter().take(4).map(|&(column, text)| context.parse_float(column, text)).collect::<Result<Vec<f32>, _>>()?)
+            } else {
+                None
+            };
+            faces.push((corners, color));
+        }
+
+        //Colors are either all between 0 and 1 or integers up to 255, one value above 1 decides it for the whole file
+        let scale = if vertex_colors.iter().chain(faces.iter().filter_map(|(_, color)| color.as_ref())).flatten().any(|&v| v > 1.0) { 255.0 } else { 1.0 };
+        model.colors = vertex_colors.iter().map(|values| Color { color: parse_color(values, scale) }).collect();
+        let faces = faces.into_iter().map(|(corners, color)| Face { corners, color: color.map(|values| parse_color(&values, scale)) }).collect();
+
+        build_off_model(&mut model, faces);
+        model.sub_meshes.push(SubMesh { index_range: 0..model.indices.len(), material_name: None, material: None });
+        Ok(model)
+    }
+}
+
+//[ST][C][N][4][n]OFF, with anything after OFF being the start of the counts
+fn parse_off_keyword(keyword: &str) -> Option<OffHeader> {
+    let mut prefix = &keyword[..keyword.find("OFF")?];
+    let mut header = OffHeader::default();
+    let mut take = |marker: &str| if prefix.starts_with(marker) {
+        prefix = &prefix[marker.len()..];
+        true
+    } else {
+        false
+    };
+    header.tex_coords = take("ST");
+    header.colors = take("C");
+    header.normals = take("N");
+    header.four_dimensional = take("4");
+    header.any_dimension = take("n");
+    if prefix.is_empty() { Some(header) } else { None }
+}
+
+//Three or four channels, a missing alpha is opaque
+fn parse_color(values: &[f32], scale: f32) -> Vec4n {
+    let channel = |i: usize, default: f32| values.get(i).map(|&v| v / scale).unwrap_or(default);
+    Vec4n::new(channel(0, 1.0), channel(1, 1.0), channel(2, 1.0), channel(3, 1.0))
+}
+
+fn build_off_model(model: &mut ObjModel, faces: Vec<Face>) {
+    let split_for_colors = model.colors.is_empty() && faces.iter().any(|f| f.color.is_some());
+    if !split_for_colors {
+        for face in faces {
+            let positions: Vec<Vec3n> = face.corners.iter().map(|&c| model.vertices[c as usize].position).collect();
+            for triangle in triangulation::triangulate(&positions) {
+                model.indices.extend(triangle.iter().map(|&corner| face.corners[corner]));
+            }
+        }
+        return;
+    }
+
+    //Every face gets its own copy of its corners with the face color, faces without one are white
+    let (vertices, normals, tex_coords) = (model.vertices.clone(), model.normals.clone(), model.tex_coords.clone());
+    model.vertices.clear();
+    model.normals.clear();
+    model.tex_coords.clear();
+    let white = Vec4n::new(1.0, 1.0, 1.0, 1.0);
+    for face in faces {
+        let start = model.vertices.len() as u32;
+        for &corner in &face.corners {
+            let corner = corner as usize;
+            model.vertices.push(vertices[corner]);
+            if let Some(&normal) = normals.get(corner) {
+                model.normals.push(normal);
+            }
+            if let Some(&uv) = tex_coords.get(corner) {
+                model.tex_coords.push(uv);
+            }
+            model.colors.push(Color { color: face.color.unwrap_or(white) });
+        }
+        let positions: Vec<Vec3n> = face.corners.iter().map(|&c| vertices[c as usize].position).collect();
+        for triangle in triangulation::triangulate(&positions) {
+            model.indices.extend(triangle.iter().map(|&corner| start + corner as u32));
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::models::*;
+
+impl ObjModel {
+    //Writes the model as OFF, the header gets the ST, C and N prefixes for the attributes the model has
+    pub fn write_off_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let prefix = |present: bool, marker: &'static str| if present { marker } else { "" };
+        writeln!(writer, "{}{}{}OFF", prefix(!self.tex_coords.is_empty(), "ST"), prefix(!self.colors.is_empty(), "C"), prefix(!self.normals.is_empty(), "N"))?;
+        writeln!(writer, "{} {} 0", self.vertices.len(), self.indices.len() / 3)?;
+
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            let p = vertex.position;
+            write!(writer, "{} {} {}", p.x, p.y, p.z)?;
+            if let Some(normal) = self.normals.get(i) {
+                write!(writer, " {} {} {}", normal.normal.x, normal.normal.y, normal.normal.z)?;
+            }
+            if let Some(color) = self.colors.get(i) {
+                write!(writer, " {} {} {} {}", color.color.x, color.color.y, color.color.z, color.color.w)?;
+            }
+            if let Some(uv) = self.tex_coords.get(i) {
+                write!(writer, " {} {}", uv.tex_coords.x, uv.tex_coords.y)?;
+            }
+            writeln!(writer)?;
+        }
+        for triangle in self.indices.chunks(3) {
+            writeln!(writer, "3 {} {} {}", triangle[0], triangle[1], triangle[2])?;
+        }
+        Ok(())
+    }
+
+    pub fn save_off_to_file(&self, file_path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(file_path)?);
+        self.write_off_to(&mut writer)?;
+        writer.flush()
+    }
+}
@@ -0,0 +1,399 @@
+use std::fs;
+
+use straal::{Vec3n, Vec4n};
+
+use crate::error::{BYTES_SOURCE_NAME, ModelLoadingError, SourceLocation};
+use crate::models::*;
+use crate::text::{LineContext, tokenize};
+use crate::triangulation;
+
+const END_HEADER: &[u8] = b"end_header";
+
+//Pairs of property names that are used for texture coordinates
+const TEX_COORD_NAMES: [(&str, &str); 4] = [("s", "t"), ("u", "v"), ("texture_u", "texture_v"), ("texture_s", "texture_t")];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    //Both the original names and the ones with sizes are in use
+    fn parse(name: &str) -> Option<ScalarType> {
+        match name {
+            "char" | "int8" => Some(ScalarType::Int8),
+            "uchar" | "uint8" => Some(ScalarType::UInt8),
+            "short" | "int16" => Some(ScalarType::Int16),
+            "ushort" | "uint16" => Some(ScalarType::UInt16),
+            "int" | "int32" => Some(ScalarType::Int32),
+            "uint" | "uint32" => Some(ScalarType::UInt32),
+            "float" | "float32" => Some(ScalarType::Float32),
+            "double" | "float64" => Some(ScalarType::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+
+    //Integer colors use the whole range of their type, floating point ones go from 0 to 1
+    fn color_scale(self) -> f64 {
+        match self {
+            ScalarType::Int8 => f64::from(i8::MAX),
+            ScalarType::UInt8 => f64::from(u8::MAX),
+            ScalarType::Int16 => f64::from(i16::MAX),
+            ScalarType::UInt16 => f64::from(u16::MAX),
+            ScalarType::Int32 => f64::from(i32::MAX),
+            ScalarType::UInt32 => f64::from(u32::MAX),
+            ScalarType::Float32 | ScalarType::Float64 => 1.0,
+        }
+    }
+}
+
+struct PropertyDefinition {
+    name: String,
+    value_type: ScalarType,
+    //The type of the length in front of list properties, None for scalars
+    count_type: Option<ScalarType>,
+}
+
+struct ElementDefinition {
+    name: String,
+    count: usize,
+    properties: Vec<PropertyDefinition>,
+}
+
+//The values of one property for every instance of an element
+enum Column {
+    Scalars(Vec<f64>),
+    Lists(Vec<Vec<f64>>),
+}
+
+struct Element {
+    definition: ElementDefinition,
+    columns: Vec<Column>,
+}
+
+impl Element {
+    fn scalars(&self, name: &str) -> Option<(&[f64], ScalarType)> {
+        self.definition.properties.iter().zip(&self.columns)
+            .find(|(property, _)| property.name == name)
+            .and_then(|(property, column)| match column {
+                Column::Scalars(values) => Some((&values[..], property.value_type)),
+                Column::Lists(_) => None,
+            })
+    }
+
+    fn lists(&self, name: &str) -> Option<&[Vec<f64>]> {
+        self.definition.properties.iter().zip(&self.columns)
+            .find(|(property, _)| property.name == name)
+            .and_then(|(_, column)| match column {
+                Column::Lists(values) => Some(&values[..]),
+                Column::Scalars(_) => None,
+            })
+    }
+}
+
+impl ObjModel {
+    //Loads an ASCII or binary PLY file. Vertices take their position, normal, texture coordinates and color
+    //from the usual properties, faces are triangulated. Files without faces load as point clouds without indices
+    pub fn load_ply_from_file(file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+        let bytes = fs::read(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        ObjModel::parse_ply(&bytes, file_path)
+    }
+
+    pub fn from_ply_bytes(bytes: &[u8]) -> Result<ObjModel, ModelLoadingError> {
+        ObjModel::parse_ply(bytes, BYTES_SOURCE_NAME)
+    }
+
+    fn parse_ply(bytes: &[u8], file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+        let (format, definitions, header_lines, data_start) = parse_ply_header(bytes, file_path)?;
+        let mut reader = match format {
+            PlyFormat::Ascii => DataReader::ascii(&bytes[data_start..], file_path, header_lines),
+            _ => DataReader::Binary { bytes: &bytes[data_start..], at: 0, big_endian: format == PlyFormat::BinaryBigEndian, file_path },
+        };
+
+        let mut elements = Vec::with_capacity(definitions.len());
+        for definition in definitions {
+            let mut columns: Vec<Column> = definition.properties.iter().map(|p| match p.count_type {
+                //The counts of the header are not trusted to size anything, the data may be much shorter
+                Some(_) => Column::Lists(Vec::new()),
+                None => Column::Scalars(Vec::new()),
+            }).collect();
+            //Every record takes at least one token or byte, a count beyond what is left could never be read
+            let record_size = reader.min_record_size(&definition.properties).max(1);
+            if definition.count.checked_mul(record_size).map_or(true, |size| size > reader.remaining()) {
+                return Err(ModelLoadingError::InvalidData {
+                    location: reader.end_location(),
+                    description: format!("the file is too short for the {} {} elements of the header", definition.count, definition.name),
+                });
+            }
+            for _ in 0..definition.count {
+                for (property, column) in definition.properties.iter().zip(columns.iter_mut()) {
+                    match (column, property.count_type) {
+                        (Column::Lists(lists), Some(count_type)) => {
+                            let count = reader.read(count_type)?.max(0.0) as usize;
+                            let list = (0..count).map(|_| reader.read(property.value_type)).collect::<Result<_, _>>()?;
+                            lists.push(list);
+                        }
+                        (Column::Scalars(values), _) => values.push(reader.read(property.value_type)?),
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            elements.push(Element { definition, columns });
+        }
+
+        build_ply_model(&elements, file_path)
+    }
+}
+
+//ply
+//format ascii 1.0
+//element vertex 8
+//property float x
+//property list uchar int vertex_indices
+//end_header
+//Returns the format, the elements, the number of header lines and where the data starts
+fn parse_ply_header(bytes: &[u8], file_path: &str) -> Result<(PlyFormat, Vec<ElementDefinition>, usize, usize), ModelLoadingError> {
+    let invalid = |description: &str| ModelLoadingError::InvalidData { location: SourceLocation::file(file_path), description: description.to_string() };
+    if !bytes.starts_with(b"ply") {
+        return Err(invalid("the file does not start with ply"));
+    }
+    //Only a line of its own ends the header, comments may mention end_header. The line break may be \r\n
+    let mut data_start = None;
+    let mut line_start = 0;
+    while line_start < bytes.len() {
+        let line_end = bytes[line_start..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |p| line_start + p);
+        if String::from_utf8_lossy(&bytes[line_start..line_end]).trim().as_bytes() == END_HEADER {
+            data_start = Some((line_end + 1).min(bytes.len()));
+            break;
+        }
+        line_start = line_end + 1;
+    }
+    let data_start = data_start.ok_or_else(|| invalid("the header is not closed with end_header"))?;
+
+    let header = String::from_utf8_lossy(&bytes[..data_start]);
+    let mut format = None;
+    let mut elements: Vec<ElementDefinition> = Vec::new();
+    let mut header_lines = 0;
+    for (line_index, line) in header.lines().enumerate() {
+        header_lines = line_index + 1;
+        let context = LineContext { file_path, line_no: line_index + 1, line };
+        let tokens = tokenize(line);
+        if tokens.is_empty() {
+            continue;
+        }
+        let require = |count: usize| if tokens.len() < count {
+            Err(ModelLoadingError::MissingComponents { location: context.location(tokens[0].0), text: line.to_string(), expected: count - 1, found: tokens.len() - 1 })
+        } else {
+            Ok(())
+        };
+        let scalar_type = |(column, name): (usize, &str)| ScalarType::parse(name).ok_or_else(|| ModelLoadingError::InvalidValue {
+            location: context.location(column),
+            text: name.to_string(),
+            expected: "a PLY type such as uchar, int or float",
+        });
+
+        match tokens[0].1 {
+            "ply" | "comment" | "obj_info" | "end_header" => {}
+            "format" => {
+                require(2)?;
+                format = Some(match tokens[1].1 {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    text => return Err(ModelLoadingError::InvalidValue {
+                        location: context.location(tokens[1].0),
+                        text: text.to_string(),
+                        expected: "ascii, binary_little_endian or binary_big_endian",
+                    }),
+                });
+            }
+            "element" => {
+                require(3)?;
+                let count = tokens[2].1.parse().map_err(|_| ModelLoadingError::InvalidValue {
+                    location: context.location(tokens[2].0),
+                    text: tokens[2].1.to_string(),
+                    expected: "the number of elements",
+                })?;
+                elements.push(ElementDefinition { name: tokens[1].1.to_string(), count, properties: Vec::new() });
+            }
+            "property" => {
+                let property = if tokens.len() > 1 && tokens[1].1 == "list" {
+                    require(5)?;
+                    PropertyDefinition { name: tokens[4].1.to_string(), value_type: scalar_type(tokens[3])?, count_type: Some(scalar_type(tokens[2])?) }
+                } else {
+                    require(3)?;
+                    PropertyDefinition { name: tokens[2].1.to_string(), value_type: scalar_type(tokens[1])?, count_type: None }
+                };
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(property),
+                    None => return Err(ModelLoadingError::InvalidData {
+                        location: context.location(tokens[0].0),
+                        description: "a property is declared before any element".to_string(),
+                    }),
+                }
+            }
+            text => return Err(ModelLoadingError::UnsupportedStatement { location: context.location(tokens[0].0), text: text.to_string() }),
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid("the header has no format line"))?;
+    Ok((format, elements, header_lines, data_start))
+}
+
+enum DataReader<'b> {
+    //Tokens with their line and column, in reverse so the next one can be popped
+    Ascii { tokens: Vec<(usize, usize, &'b str)>, file_path: &'b str, last_line: usize },
+    Binary { bytes: &'b [u8], at: usize, big_endian: bool, file_path: &'b str },
+}
+
+impl<'b> DataReader<'b> {
+    fn ascii(bytes: &'b [u8], file_path: &'b str, header_lines: usize) -> DataReader<'b> {
+        //Numbers are ASCII, anything else in the data would fail to parse anyway
+        let source = std::str::from_utf8(bytes).unwrap_or_else(|e| std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or(""));
+        let mut tokens: Vec<(usize, usize, &str)> = source.lines().enumerate()
+            .flat_map(|(line_index, line)| tokenize(line).into_iter().map(move |(column, text)| (header_lines + line_index + 1, column, text)))
+            .collect();
+        let last_line = header_lines + source.lines().count();
+        tokens.reverse();
+        DataReader::Ascii { tokens, file_path, last_line }
+    }
+
+    //The tokens or bytes left in the data
+    fn remaining(&self) -> usize {
+        match self {
+            DataReader::Ascii { tokens, .. } => tokens.len(),
+            DataReader::Binary { bytes, at, .. } => bytes.len().saturating_sub(*at),
+        }
+    }
+
+    //Where running out of data is reported, the last line for ASCII files
+    fn end_location(&self) -> SourceLocation {
+        match self {
+            DataReader::Ascii { file_path, last_line, .. } => SourceLocation::new(file_path, *last_line, 0),
+            DataReader::Binary { file_path, .. } => SourceLocation::file(file_path),
+        }
+    }
+
+    //The fewest tokens or bytes one record of these properties can take, lists may be empty
+    fn min_record_size(&self, properties: &[PropertyDefinition]) -> usize {
+        match self {
+            DataReader::Ascii { .. } => properties.len(),
+            DataReader::Binary { .. } => properties.iter().map(|p| p.count_type.unwrap_or(p.value_type).size()).sum(),
+        }
+    }
+
+    fn read(&mut self, value_type: ScalarType) -> Result<f64, ModelLoadingError> {
+        match self {
+            DataReader::Ascii { tokens, file_path, last_line } => {
+                let (line, column, text) = tokens.pop().ok_or_else(|| ModelLoadingError::InvalidData {
+                    location: SourceLocation::new(file_path, *last_line, 0),
+                    description: "the file ends before all elements of the header were read".to_string(),
+                })?;
+                text.parse::<f64>().map_err(|e| ModelLoadingError::ParseFloat {
+                    location: SourceLocation::new(file_path, line, column),
+                    text: text.to_string(),
+                    source: e,
+                })
+            }
+            DataReader::Binary { bytes, at, big_endian, file_path } => {
+                let size = value_type.size();
+                let data = bytes.get(*at..*at + size).ok_or_else(|| ModelLoadingError::InvalidData {
+                    location: SourceLocation::file(file_path),
+                    description: format!("the file ends at byte {} of the data, before all elements of the header were read", at),
+                })?;
+                *at += size;
+                let mut buffer = [0u8; 8];
+                buffer[..size].copy_from_slice(data);
+                if *big_endian {
+                    buffer[..size].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = buffer;
+                Ok(match value_type {
+                    ScalarType::Int8 => f64::from(b0 as i8),
+                    ScalarType::UInt8 => f64::from(b0),
+                    ScalarType::Int16 => f64::from(i16::from_le_bytes([b0, b1])),
+                    ScalarType::UInt16 => f64::from(u16::from_le_bytes([b0, b1])),
+                    ScalarType::Int32 => f64::from(i32::from_le_bytes([b0, b1, b2, b3])),
+                    ScalarType::UInt32 => f64::from(u32::from_le_bytes([b0, b1, b2, b3])),
+                    ScalarType::Float32 => f64::from(f32::from_le_bytes([b0, b1, b2, b3])),
+                    ScalarType::Float64 => f64::from_le_bytes(buffer),
+                })
+            }
+        }
+    }
+}
+
+fn build_ply_model(elements: &[Element], file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+    let invalid = |description: String| ModelLoadingError::InvalidData { location: SourceLocation::file(file_path), description };
+    let mut model = ObjModel::empty();
+
+    if let Some(vertex) = elements.iter().find(|e| e.definition.name == "vertex") {
+        let components = |names: &[&str]| -> Option<Vec<(&[f64], ScalarType)>> {
+            names.iter().map(|name| vertex.scalars(name)).collect()
+        };
+        let positions = components(&["x", "y", "z"])
+            .ok_or_else(|| invalid("the vertex element has no x, y and z properties".to_string()))?;
+        let value = |values: &(&[f64], ScalarType), i: usize| values.0[i] as f32;
+
+        for i in 0..vertex.definition.count {
+            model.vertices.push(Vertex { position: Vec3n::new(value(&positions[0], i), value(&positions[1], i), value(&positions[2], i)) });
+        }
+        if let Some(normals) = components(&["nx", "ny", "nz"]) {
+            model.normals = (0..vertex.definition.count)
+                .map(|i| Normal { normal: Vec3n::new(value(&normals[0], i), value(&normals[1], i), value(&normals[2], i)) })
+                .collect();
+        }
+        if let Some(tex_coords) = TEX_COORD_NAMES.iter().find_map(|&(u, v)| components(&[u, v])) {
+            model.tex_coords = (0..vertex.definition.count)
+                .map(|i| UV { tex_coords: straal::Vec2 { x: value(&tex_coords[0], i), y: value(&tex_coords[1], i) } })
+                .collect();
+        }
+        if let Some(colors) = components(&["red", "green", "blue"]) {
+            let alpha = vertex.scalars("alpha");
+            let channel = |values: (&[f64], ScalarType), i: usize| (values.0[i] / values.1.color_scale()) as f32;
+            model.colors = (0..vertex.definition.count).map(|i| Color {
+                color: Vec4n::new(channel(colors[0], i), channel(colors[1], i), channel(colors[2], i), alpha.map(|a| channel(a, i)).unwrap_or(1.0)),
+            }).collect();
+        }
+    }
+
+    if let Some(face) = elements.iter().find(|e| e.definition.name == "face") {
+        let faces = face.lists("vertex_indices").or_else(|| face.lists("vertex_index"))
+            .ok_or_else(|| invalid("the face element has no vertex_indices list".to_string()))?;
+        for (face_index, corners) in faces.iter().enumerate() {
+            if let Some(&corner) = corners.iter().find(|&&c| c < 0.0 || c.fract() != 0.0) {
+                return Err(invalid(format!("face {} has the corner {}, which is not a vertex index", face_index, corner)));
+            }
+            if let Some(&corner) = corners.iter().find(|&&c| c >= model.vertices.len() as f64) {
+                return Err(invalid(format!("face {} refers to vertex {}, only {} are defined", face_index, corner, model.vertices.len())));
+            }
+            let corners: Vec<u32> = corners.iter().map(|&c| c as u32).collect();
+            let positions: Vec<Vec3n> = corners.iter().map(|&c| model.vertices[c as usize].position).collect();
+            for triangle in triangulation::triangulate(&positions) {
+                model.indices.extend(triangle.iter().map(|&corner| corners[corner]));
+            }
+        }
+    }
+
+    model.sub_meshes.push(SubMesh { index_range: 0..model.indices.len(), material_name: None, material: None });
+    Ok(model)
+}
@@ -0,0 +1,124 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::models::*;
+
+impl ObjModel {
+    //Writes the model as PLY with every vertex attribute it has, colors as bytes and faces as triangles.
+    //Models without indices are written as point clouds
+    pub fn write_ply_to<W: Write>(&self, mut writer: W, format: PlyFormat) -> io::Result<()> {
+        let format_name = match format {
+            PlyFormat::Ascii => "ascii",
+            PlyFormat::BinaryLittleEndian => "binary_little_endian",
+            PlyFormat::BinaryBigEndian => "binary_big_endian",
+        };
+        writeln!(writer, "ply")?;
+        writeln!(writer, "format {} 1.0", format_name)?;
+        writeln!(writer, "comment written by vertexify")?;
+        writeln!(writer, "element vertex {}", self.vertices.len())?;
+        let mut properties = vec!["x", "y", "z"];
+        if !self.normals.is_empty() {
+            properties.extend_from_slice(&["nx", "ny", "nz"]);
+        }
+        if !self.tex_coords.is_empty() {
+            properties.extend_from_slice(&["s", "t"]);
+        }
+        for property in &properties {
+            writeln!(writer, "property float {}", property)?;
+        }
+        if !self.colors.is_empty() {
+            for channel in &["red", "green", "blue", "alpha"] {
+                writeln!(writer, "property uchar {}", channel)?;
+            }
+        }
+        if !self.indices.is_empty() {
+            writeln!(writer, "element face {}", self.indices.len() / 3)?;
+            writeln!(writer, "property list uchar uint vertex_indices")?;
+        }
+        writeln!(writer, "end_header")?;
+
+        let mut data = PlyData { writer: &mut writer, format, first: true };
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            let p = vertex.position;
+            data.floats(&[p.x, p.y, p.z])?;
+            if let Some(normal) = self.normals.get(i) {
+                data.floats(&[normal.normal.x, normal.normal.y, normal.normal.z])?;
+            }
+            if let Some(uv) = self.tex_coords.get(i) {
+                data.floats(&[uv.tex_coords.x, uv.tex_coords.y])?;
+            }
+            if let Some(color) = self.colors.get(i) {
+                let c = color.color;
+                let byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+                data.bytes(&[byte(c.x), byte(c.y), byte(c.z), byte(c.w)])?;
+            }
+            data.end_element()?;
+        }
+        for triangle in self.indices.chunks(3) {
+            data.bytes(&[3])?;
+            data.integers(triangle)?;
+            data.end_element()?;
+        }
+        Ok(())
+    }
+
+    pub fn save_ply_to_file(&self, file_path: &str, format: PlyFormat) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(file_path)?);
+        self.write_ply_to(&mut writer, format)?;
+        writer.flush()
+    }
+}
+
+//Writes values as text separated by spaces with one element per line, or as binary in the byte order of the format
+struct PlyData<'w, W: Write> {
+    writer: &'w mut W,
+    format: PlyFormat,
+    first: bool,
+}
+
+impl<'w, W: Write> PlyData<'w, W> {
+    fn text(&mut self, text: &str) -> io::Result<()> {
+        if !self.first {
+            write!(self.writer, " ")?;
+        }
+        self.first = false;
+        write!(self.writer, "{}", text)
+    }
+
+    fn floats(&mut self, values: &[f32]) -> io::Result<()> {
+        for value in values {
+            match self.format {
+                PlyFormat::Ascii => self.text(&value.to_string())?,
+                PlyFormat::BinaryLittleEndian => self.writer.write_all(&value.to_le_bytes())?,
+                PlyFormat::BinaryBigEndian => self.writer.write_all(&value.to_be_bytes())?,
+            }
+        }
+        Ok(())
+    }
+
+    fn integers(&mut self, values: &[u32]) -> io::Result<()> {
+        for value in values {
+            match self.format {
+                PlyFormat::Ascii => self.text(&value.to_string())?,
+                PlyFormat::BinaryLittleEndian => self.writer.write_all(&value.to_le_bytes())?,
+                PlyFormat::BinaryBigEndian => self.writer.write_all(&value.to_be_bytes())?,
+            }
+        }
+        Ok(())
+    }
+
+    fn bytes(&mut self, values: &[u8]) -> io::Result<()> {
+        match self.format {
+            PlyFormat::Ascii => values.iter().try_for_each(|value| self.text(&value.to_string())),
+            _ => self.writer.write_all(values),
+        }
+    }
+
+    fn end_element(&mut self) -> io::Result<()> {
+        self.first = true;
+        match self.format {
+            PlyFormat::Ascii => writeln!(self.writer),
+            _ => Ok(()),
+        }
+    }
+}
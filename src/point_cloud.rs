@@ -0,0 +1,202 @@
+use std::fs;
+
+use straal::{Vec3n, Vec4n};
+
+use crate::error::{BYTES_SOURCE_NAME, ModelLoadingError, SourceLocation};
+use crate::models::*;
+use crate::text::{LineContext, tokenize};
+
+//Three components of the optional columns that are closer than this to unit length are taken as a normal
+const UNIT_LENGTH_EPSILON: f32 = 1e-3;
+
+//Which columns follow the position, decided by the number of columns of the first point:
+//3 x y z, 4 x y z i, 6 x y z r g b or x y z nx ny nz, 7 x y z i r g b,
+//9 x y z r g b nx ny nz and 10 x y z i r g b nx ny nz.
+//Six columns are read as colors until all points are known to have normals there
+struct Columns {
+    count: usize,
+    intensity: bool,
+    colors: bool,
+    normals: bool,
+}
+
+impl Columns {
+    fn from_point(values: &[f32]) -> Option<Columns> {
+        let columns = |intensity: bool, colors: bool, normals: bool| Columns { count: values.len(), intensity, colors, normals };
+        match values.len() {
+            3 => Some(columns(false, false, false)),
+            4 => Some(columns(true, false, false)),
+            6 => Some(columns(false, true, false)),
+            7 => Some(columns(true, true, false)),
+            9 => Some(columns(false, true, true)),
+            10 => Some(columns(true, true, true)),
+            _ => None,
+        }
+    }
+}
+
+impl ObjModel {
+    //Loads a point cloud of lines with a position and optional intensity, color and normal columns.
+    //Commas and semicolons separate columns as well as spaces, a line of column names before the first point is skipped
+    pub fn load_xyz_from_file(file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+        let bytes = fs::read(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        ObjModel::parse_points(&String::from_utf8_lossy(&bytes), file_path, false)
+    }
+
+    pub fn from_xyz_bytes(bytes: &[u8]) -> Result<ObjModel, ModelLoadingError> {
+        ObjModel::parse_points(&String::from_utf8_lossy(bytes), BYTES_SOURCE_NAME, false)
+    }
+
+    //Leica PTS has the columns of an XYZ file, usually x y z i r g b, but every scan starts with a line
+    //holding its point count and has to have exactly that many points
+    pub fn load_pts_from_file(file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+        let bytes = fs::read(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        ObjModel::parse_points(&String::from_utf8_lossy(&bytes), file_path, true)
+    }
+
+    pub fn from_pts_bytes(bytes: &[u8]) -> Result<ObjModel, ModelLoadingError> {
+        ObjModel::parse_points(&String::from_utf8_lossy(bytes), BYTES_SOURCE_NAME, true)
+    }
+
+    fn parse_points(source: &str, file_path: &str, scans: bool) -> Result<ObjModel, ModelLoadingError> {
+        let mut columns: Option<Columns> = None;
+        //Points left in the current scan of a PTS file, with the line and count of the scan
+        let mut scan: Option<(usize, usize, usize)> = None;
+        let mut positions = Vec::new();
+        let mut intensities = Vec::new();
+        let mut colors = Vec::new();
+        let mut normals = Vec::new();
+
+        for (line_index, line) in source.lines().enumerate() {
+            //Separators are replaced by spaces of the same width, so columns still point into the original line
+            let separated = line.replace([',', ';'], " ");
+            let context = LineContext { file_path, line_no: line_index + 1, line };
+            let tokens = tokenize(&separated);
+            if tokens.is_empty() || tokens[0].1.starts_with('#') || tokens[0].1.starts_with("//") {
+                continue;
+            }
+            //Single numbers are the point counts of PTS files, some XYZ files start with one as well
+            if tokens.len() == 1 {
+                if scans {
+                    if let Some((line_no, left, count)) = scan.filter(|&(_, left, _)| left > 0) {
+                        return Err(ModelLoadingError::InvalidData {
+                            location: context.location(tokens[0].0),
+                            description: format!("the scan of line {} ends after {} of its {} points", line_no, count - left, count),
+                        });
+                    }
+                    let count = tokens[0].1.parse::<usize>().map_err(|e| ModelLoadingError::ParseIndex {
+                        location: context.location(tokens[0].0),
+                        text: tokens[0].1.to_string(),
+                        source: e,
+                    })?;
+                    scan = Some((line_index + 1, count, count));
+                }
+                continue;
+            }
+            if columns.is_none() && tokens[0].1.parse::<f32>().is_err() {
+                continue;
+            }
+            let values = tokens.iter().map(|&(column, text)| context.parse_float(column, text)).collect::<Result<Vec<f32>, _>>()?;
+            if scans {
+                match scan.as_mut() {
+                    Some((_, left, _)) if *left > 0 => *left -= 1,
+                    Some((line_no, _, count)) => return Err(ModelLoadingError::InvalidData {
+                        location: context.location(tokens[0].0),
+                        description: format!("the scan of line {} already has its {} points", line_no, count),
+                    }),
+                    None => return Err(ModelLoadingError::InvalidData {
+                        location: context.location(tokens[0].0),
+                        description: "the point comes before the point count of its scan".to_string(),
+                    }),
+                }
+            }
+
+            if columns.is_none() {
+                columns = Some(Columns::from_point(&values).ok_or_else(|| ModelLoadingError::InvalidData {
+                    location: context.location(tokens[0].0),
+                    description: format!("a point has {} columns, expected 3, 4, 6, 7, 9 or 10", values.len()),
+                })?);
+            }
+            let columns = columns.as_ref().unwrap();
+            if values.len() < columns.count {
+                return Err(ModelLoadingError::MissingComponents {
+                    location: context.location(tokens[0].0),
+                    text: line.to_string(),
+                    expected: columns.count,
+                    found: values.len(),
+                });
+            }
+            if values.len() > columns.count {
+                return Err(ModelLoadingError::InvalidData {
+                    location: context.location(tokens[columns.count].0),
+                    description: format!("the point has {} columns, the first point has {}", values.len(), columns.count),
+                });
+            }
+
+            positions.push(Vec3n::new(values[0], values[1], values[2]));
+            let mut at = 3;
+            if columns.intensity {
+                intensities.push(values[at]);
+                at += 1;
+            }
+            if columns.colors {
+                colors.push(Vec3n::new(values[at], values[at + 1], values[at + 2]));
+                at += 3;
+            }
+            if columns.normals {
+                normals.push(Vec3n::new(values[at], values[at + 1], values[at + 2]));
+            }
+        }
+
+        if let Some((line_no, left, count)) = scan.filter(|&(_, left, _)| left > 0) {
+            return Err(ModelLoadingError::InvalidData {
+                location: SourceLocation::file(file_path),
+                description: format!("the scan of line {} ends after {} of its {} points", line_no, count - left, count),
+            });
+        }
+        //Six columns are normals only when every point has a unit vector there
+        let unit_length = |c: &Vec3n| ((c.x * c.x + c.y * c.y + c.z * c.z).sqrt() - 1.0).abs() < UNIT_LENGTH_EPSILON;
+        if columns.is_some_and(|columns| columns.count == 6) && colors.iter().all(unit_length) {
+            normals = std::mem::take(&mut colors);
+        }
+
+        let mut model = ObjModel::empty();
+        model.vertices = positions.into_iter().map(|position| Vertex { position }).collect();
+        model.normals = normals.into_iter().map(|normal| Normal { normal }).collect();
+        //Colors are either all between 0 and 1 or integers up to 255, the intensity is only used as a gray level
+        //for points without a color
+        if !colors.is_empty() {
+            let scale = if colors.iter().any(|c| c.x > 1.0 || c.y > 1.0 || c.z > 1.0) { 255.0 } else { 1.0 };
+            model.colors = colors.into_iter().map(|c| Color { color: Vec4n::new(c.x / scale, c.y / scale, c.z / scale, 1.0) }).collect();
+        } else if !intensities.is_empty() {
+            let gray = intensity_scale(&intensities);
+            model.colors = intensities.into_iter().map(|i| {
+                let level = gray(i);
+                Color { color: Vec4n::new(level, level, level, 1.0) }
+            }).collect();
+        }
+        Ok(model)
+    }
+}
+
+//Scanners use signed 12 bit values (PTS), fractions, bytes or 16 bit values for the intensity
+fn intensity_scale(intensities: &[f32]) -> impl Fn(f32) -> f32 {
+    let min = intensities.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = intensities.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let (offset, range) = if min < 0.0 {
+        (2048.0, 4095.0)
+    } else if max <= 1.0 {
+        (0.0, 1.0)
+    } else if max <= 255.0 {
+        (0.0, 255.0)
+    } else {
+        (0.0, 65535.0)
+    };
+    move |intensity: f32| ((intensity + offset) / range).clamp(0.0, 1.0)
+}
@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fs;
+
+use straal::{Vec2n, Vec3n};
+
+use crate::binary::{BinaryReader, y_up};
+use crate::error::{BYTES_SOURCE_NAME, ModelLoadingError, SourceLocation};
+use crate::models::*;
+
+const MD2_IDENT: &[u8] = b"IDP2";
+const MD2_VERSION: i32 = 8;
+const MD2_SKIN_NAME_SIZE: usize = 64;
+const MD2_FRAME_NAME_SIZE: usize = 16;
+
+const MD3_IDENT: &[u8] = b"IDP3";
+const MD3_VERSION: i32 = 15;
+const MD3_NAME_SIZE: usize = 64;
+const MD3_FRAME_SIZE: usize = 56;
+const MD3_FRAME_NAME_OFFSET: usize = 40;
+const MD3_FRAME_NAME_SIZE: usize = 16;
+const MD3_TAG_SIZE: usize = 112;
+const MD3_SHADER_SIZE: usize = 68;
+const MD3_SURFACE_HEADER_SIZE: usize = 108;
+//Vertex positions are fixed point with 6 fractional bits
+const MD3_XYZ_SCALE: f32 = 1.0 / 64.0;
+
+impl MorphModel {
+    //Loads a Quake 2 model with all its animation frames, the first frame is the mesh of model()
+    pub fn load_md2_from_file(file_path: &str) -> Result<MorphModel, ModelLoadingError> {
+        let bytes = fs::read(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        MorphModel::parse_md2(&bytes, file_path)
+    }
+
+    pub fn from_md2_bytes(bytes: &[u8]) -> Result<MorphModel, ModelLoadingError> {
+        MorphModel::parse_md2(bytes, BYTES_SOURCE_NAME)
+    }
+
+    //Loads a Quake 3 model, every surface becomes an object and a sub-mesh named after its shader
+    pub fn load_md3_from_file(file_path: &str) -> Result<MorphModel, ModelLoadingError> {
+        let bytes = fs::read(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        MorphModel::parse_md3(&bytes, file_path)
+    }
+
+    pub fn from_md3_bytes(bytes: &[u8]) -> Result<MorphModel, ModelLoadingError> {
+        MorphModel::parse_md3(bytes, BYTES_SOURCE_NAME)
+    }
+
+    fn parse_md2(bytes: &[u8], file_path: &str) -> Result<MorphModel, ModelLoadingError> {
+        let reader = BinaryReader { bytes, file_path };
+        check_header(&reader, MD2_IDENT, MD2_VERSION, "MD2")?;
+        let skin_width = reader.count(8, "skin width")?.max(1) as f32;
+        let skin_height = reader.count(12, "skin height")?.max(1) as f32;
+        let frame_size = reader.count(16, "frame size")?;
+        let skin_count = reader.record_count(20, MD2_SKIN_NAME_SIZE, "number of skins")?;
+        let vertex_count = reader.record_count(24, 4, "number of vertices")?;
+        let st_count = reader.record_count(28, 4, "number of texture coordinates")?;
+        let triangle_count = reader.record_count(32, 12, "number of triangles")?;
+        let frame_count = reader.record_count(40, frame_size.max(24 + MD2_FRAME_NAME_SIZE), "number of frames")?;
+        let skins_offset = reader.count(44, "skin offset")?;
+        let st_offset = reader.count(48, "texture coordinate offset")?;
+        let triangles_offset = reader.count(52, "triangle offset")?;
+        let frames_offset = reader.count(56, "frame offset")?;
+        if frame_count == 0 {
+            return Err(reader.invalid("the model has no frames".to_string()));
+        }
+
+        let skins = (0..skin_count).map(|i| reader.name(skins_offset + i * MD2_SKIN_NAME_SIZE, MD2_SKIN_NAME_SIZE)).collect::<Result<Vec<_>, _>>()?;
+        let tex_coords = (0..st_count).map(|i| {
+            let offset = st_offset + i * 4;
+            let (s, t) = (reader.i16(offset)? as f32, reader.i16(offset + 2)? as f32);
+            Ok(Vec2n { x: s / skin_width, y: 1.0 - t / skin_height })
+        }).collect::<Result<Vec<_>, ModelLoadingError>>()?;
+
+        //Positions and texture coordinates have separate indices, every pair that is used becomes a vertex
+        let mut vertex_of_pair: HashMap<(u16, u16), u32> = HashMap::new();
+        let mut pairs: Vec<(u16, u16)> = Vec::new();
+        let mut indices = Vec::new();
+        for triangle in 0..triangle_count {
+            let offset = triangles_offset + triangle * 12;
+            //Quake triangles are clockwise
+            for &corner in &[0, 2, 1] {
+                let pair = (reader.u16(offset + corner * 2)?, reader.u16(offset + 6 + corner * 2)?);
+                if pair.0 as usize >= vertex_count || pair.1 as usize >= st_count {
+                    return Err(reader.invalid(format!("triangle {} refers to vertex {} and texture coordinate {}, only {} and {} are defined",
+                                                      triangle, pair.0, pair.1, vertex_count, st_count)));
+                }
+                indices.push(*vertex_of_pair.entry(pair).or_insert_with(|| {
+                    pairs.push(pair);
+                    (pairs.len() - 1) as u32
+                }));
+            }
+        }
+
+        let mut frames = Vec::new();
+        for frame in 0..frame_count {
+            let offset = frames_offset + frame * frame_size;
+            let scale = reader.vec3(offset)?;
+            let translate = reader.vec3(offset + 12)?;
+            let name = reader.name(offset + 24, MD2_FRAME_NAME_SIZE)?;
+            let vertices_offset = offset + 24 + MD2_FRAME_NAME_SIZE;
+            let mut positions = Vec::with_capacity(pairs.len());
+            let mut normals = Vec::with_capacity(pairs.len());
+            for &(vertex, _) in &pairs {
+                let at = vertices_offset + vertex as usize * 4;
+                let packed = Vec3n::new(reader.u8(at)? as f32, reader.u8(at + 1)? as f32, reader.u8(at + 2)? as f32);
+                let position = Vec3n::new(packed.x * scale.x, packed.y * scale.y, packed.z * scale.z) + translate;
+                let normal_index = reader.u8(at + 3)? as usize;
+                let normal = ANORMS.get(normal_index)
+                    .ok_or_else(|| reader.invalid(format!("frame {} uses normal {}, the normal table has {}", name, normal_index, ANORMS.len())))?;
+                positions.push(y_up(position));
+                normals.push(y_up(Vec3n::new(normal[0], normal[1], normal[2])));
+            }
+            frames.push(MorphFrame { name, positions, normals });
+        }
+
+        let mut model = ObjModel {
+            indices,
+            tex_coords: pairs.iter().map(|&(_, st)| UV { tex_coords: tex_coords[st as usize] }).collect(),
+            ..ObjModel::empty()
+        };
+        model.sub_meshes.push(SubMesh { index_range: 0..model.indices.len(), material_name: skins.first().cloned(), material: None });
+        Ok(MorphModel::from_frames(model, frames, Vec::new(), skins))
+    }
+
+    fn parse_md3(bytes: &[u8], file_path: &str) -> Result<MorphModel, ModelLoadingError> {
+        let reader = BinaryReader { bytes, file_path };
+        check_header(&reader, MD3_IDENT, MD3_VERSION, "MD3")?;
+        let frame_count = reader.record_count(76, MD3_FRAME_SIZE, "number of frames")?;
+        let tag_count = reader.record_count(80, MD3_TAG_SIZE, "number of tags")?;
+        let surface_count = reader.record_count(84, MD3_SURFACE_HEADER_SIZE, "number of surfaces")?;
+        let frames_offset = reader.count(92, "frame offset")?;
+        let tags_offset = reader.count(96, "tag offset")?;
+        let mut surface_offset = reader.count(100, "surface offset")?;
+        if frame_count == 0 {
+            return Err(reader.invalid("the model has no frames".to_string()));
+        }
+
+        let mut frames = (0..frame_count).map(|frame| {
+            let name = reader.name(frames_offset + frame * MD3_FRAME_SIZE + MD3_FRAME_NAME_OFFSET, MD3_FRAME_NAME_SIZE)?;
+            Ok(MorphFrame { name, positions: Vec::new(), normals: Vec::new() })
+        }).collect::<Result<Vec<_>, ModelLoadingError>>()?;
+
+        //Tags are stored frame by frame
+        let mut tags = Vec::new();
+        for frame in 0..frame_count {
+            let frame_tags = (0..tag_count).map(|tag| {
+                let offset = tags_offset + (frame * tag_count + tag) * MD3_TAG_SIZE;
+                let name = reader.name(offset, MD3_NAME_SIZE)?;
+                let origin = y_up(reader.vec3(offset + MD3_NAME_SIZE)?);
+                let axis = |i: usize| reader.vec3(offset + MD3_NAME_SIZE + 12 + i * 12).map(y_up);
+                Ok(Tag { name, origin, axes: [axis(0)?, axis(1)?, axis(2)?] })
+            }).collect::<Result<Vec<_>, ModelLoadingError>>()?;
+            tags.push(frame_tags);
+        }
+
+        let mut model = ObjModel::empty();
+        let mut skins = Vec::new();
+        for _ in 0..surface_count {
+            let s = surface_offset;
+            if reader.bytes(s, 4)? != MD3_IDENT {
+                return Err(reader.invalid(format!("the surface at byte {} does not start with IDP3", s)));
+            }
+            let name = reader.name(s + 4, MD3_NAME_SIZE)?;
+            let surface_frames = reader.count(s + 72, "number of surface frames")?;
+            let shader_count = reader.record_count(s + 76, MD3_SHADER_SIZE, "number of shaders")?;
+            let vertex_count = reader.record_count(s + 80, 8, "number of surface vertices")?;
+            let triangle_count = reader.record_count(s + 84, 12, "number of surface triangles")?;
+            let triangles_offset = s + reader.count(s + 88, "triangle offset")?;
+            let shaders_offset = s + reader.count(s + 92, "shader offset")?;
+            let st_offset = s + reader.count(s + 96, "texture coordinate offset")?;
+            let xyz_normal_offset = s + reader.count(s + 100, "vertex offset")?;
+            let end_offset = reader.count(s + 104, "surface size")?;
+            if surface_frames != frame_count {
+                return Err(reader.invalid(format!("surface {} has {} frames, the model has {}", name, surface_frames, frame_count)));
+            }
+
+            let shaders = (0..shader_count).map(|i| reader.name(shaders_offset + i * MD3_SHADER_SIZE, MD3_NAME_SIZE)).collect::<Result<Vec<_>, _>>()?;
+            //The positions are filled in from the first frame once all surfaces are read
+            let first_vertex = model.tex_coords.len() as u32;
+            let start = model.indices.len();
+            for triangle in 0..triangle_count {
+                let offset = triangles_offset + triangle * 12;
+                for &corner in &[0, 2, 1] {
+                    let index = reader.i32(offset + corner * 4)?;
+                    if index < 0 || index as usize >= vertex_count {
+                        return Err(reader.invalid(format!("a triangle of surface {} refers to vertex {}, only {} are defined", name, index, vertex_count)));
+                    }
+                    model.indices.push(first_vertex + index as u32);
+                }
+            }
+            for vertex in 0..vertex_count {
+                let offset = st_offset + vertex * 8;
+                model.tex_coords.push(UV { tex_coords: Vec2n { x: reader.f32(offset)?, y: 1.0 - reader.f32(offset + 4)? } });
+            }
+            for (frame_index, frame) in frames.iter_mut().enumerate() {
+                for vertex in 0..vertex_count {
+                    let offset = xyz_normal_offset + (frame_index * vertex_count + vertex) * 8;
+                    let position = Vec3n::new(reader.i16(offset)? as f32, reader.i16(offset + 2)? as f32, reader.i16(offset + 4)? as f32);
+                    frame.positions.push(y_up(ObjModel::scaled(position, MD3_XYZ_SCALE)));
+                    frame.normals.push(y_up(decode_md3_normal(reader.u16(offset + 6)?)));
+                }
+            }
+
+            let index_range = start..model.indices.len();
+            let mut object = NamedPart { name, index_ranges: Vec::new() };
+            object.extend(index_range.clone());
+            model.objects.push(object);
+            model.sub_meshes.push(SubMesh { index_range, material_name: shaders.first().cloned(), material: None });
+            skins.extend(shaders);
+            surface_offset = s + end_offset;
+        }
+        Ok(MorphModel::from_frames(model, frames, tags, skins))
+    }
+
+    //The mesh of the model gets the positions and normals of the first frame
+    fn from_frames(mut model: ObjModel, frames: Vec<MorphFrame>, tags: Vec<Vec<Tag>>, skins: Vec<String>) -> MorphModel {
+        model.vertices = frames[0].positions.iter().map(|&position| Vertex { position }).collect();
+        model.normals = frames[0].normals.iter().map(|&normal| Normal { normal }).collect();
+        MorphModel { model, frames, tags, skins }
+    }
+}
+
+fn check_header(reader: &BinaryReader, ident: &[u8], version: i32, format: &str) -> Result<(), ModelLoadingError> {
+    if reader.bytes(0, 4)? != ident {
+        return Err(reader.invalid(format!("the file does not start with the {} identifier {}", format, String::from_utf8_lossy(ident))));
+    }
+    let found = reader.i32(4)?;
+    if found != version {
+        return Err(reader.invalid(format!("{} version {} is not supported, only version {} is", format, found, version)));
+    }
+    Ok(())
+}
+
+//Normals are stored as latitude and longitude, a byte each
+fn decode_md3_normal(packed: u16) -> Vec3n {
+    let lat = ((packed >> 8) & 255) as f32 * 2.0 * PI / 255.0;
+    let lng = (packed & 255) as f32 * 2.0 * PI / 255.0;
+    Vec3n::new(lat.cos() * lng.sin(), lat.sin() * lng.sin(), lng.cos())
+}
+
+//The precalculated normals MD2 vertices refer to, anorms.h of the Quake 2 source
+#[allow(clippy::approx_constant)]
+const ANORMS: [[f32; 3]; 162] = [
+    [-0.525731, 0.000000, 0.850651],
+    [-0.442863, 0.238856, 0.864188],
+    [-0.295242, 0.000000, 0.955423],
+    [-0.309017, 0.500000, 0.809017],
+    [-0.162460, 0.262866, 0.951056],
+    [0.000000, 0.000000, 1.000000],
+    [0.000000, 0.850651, 0.525731],
+    [-0.147621, 0.716567, 0.681718],
+    [0.147621, 0.716567, 0.681718],
+    [0.000000, 0.525731, 0.850651],
+    [0.309017, 0.500000, 0.809017],
+    [0.525731, 0.000000, 0.850651],
+    [0.295242, 0.000000, 0.955423],
+    [0.442863, 0.238856, 0.864188],
+    [0.162460, 0.262866, 0.951056],
+    [-0.681718, 0.147621, 0.716567],
+    [-0.809017, 0.309017, 0.500000],
+    [-0.587785, 0.425325, 0.688191],
+    [-0.850651, 0.525731, 0.000000],
+    [-0.864188, 0.442863, 0.238856],
+    [-0.716567, 0.681718, 0.147621],
+    [-0.688191, 0.587785, 0.425325],
+    [-0.500000, 0.809017, 0.309017],
+    [-0.238856, 0.864188, 0.442863],
+    [-0.425325, 0.688191, 0.587785],
+    [-0.716567, 0.681718, -0.147621],
+    [-0.500000, 0.809017, -0.309017],
+    [-0.525731, 0.850651, 0.000000],
+    [0.000000, 0.850651, -0.525731],
+    [-0.238856, 0.864188, -0.442863],
+    [0.000000, 0.955423, -0.295242],
+    [-0.262866, 0.951056, -0.162460],
+    [0.000000, 1.000000, 0.000000],
+    [0.000000, 0.955423, 0.295242],
+    [-0.262866, 0.951056, 0.162460],
+    [0.238856, 0.864188, 0.442863],
+    [0.262866, 0.951056, 0.162460],
+    [0.500000, 0.809017, 0.309017],
+    [0.238856, 0.864188, -0.442863],
+    [0.262866, 0.951056, -0.162460],
+    [0.500000, 0.809017, -0.309017],
+    [0.850651, 0.525731, 0.000000],
+    [0.716567, 0.681718, 0.147621],
+    [0.716567, 0.681718, -0.147621],
+    [0.525731, 0.850651, 0.000000],
+    [0.425325, 0.688191, 0.587785],
+    [0.864188, 0.442863, 0.238856],
+    [0.688191, 0.587785, 0.425325],
+    [0.809017, 0.309017, 0.500000],
+    [0.681718, 0.147621, 0.716567],
+    [0.587785, 0.425325, 0.688191],
+    [0.955423, 0.295242, 0.000000],
+    [1.000000, 0.000000, 0.000000],
+    [0.951056, 0.162460, 0.262866],
+    [0.850651, -0.525731, 0.000000],
+    [0.955423, -0.295242, 0.000000],
+    [0.864188, -0.442863, 0.238856],
+    [0.951056, -0.162460, 0.262866],
+    [0.809017, -0.309017, 0.500000],
+    [0.681718, -0.147621, 0.716567],
+    [0.850651, 0.000000, 0.525731],
+    [0.864188, 0.442863, -0.238856],
+    [0.809017, 0.309017, -0.500000],
+    [0.951056, 0.162460, -0.262866],
+    [0.525731, 0.000000, -0.850651],
+    [0.681718, 0.147621, -0.716567],
+    [0.681718, -0.147621, -0.716567],
+    [0.850651, 0.000000, -0.525731],
+    [0.809017, -0.309017, -0.500000],
+    [0.864188, -0.442863, -0.238856],
+    [0.951056, -0.162460, -0.262866],
+    [0.147621, 0.716567, -0.681718],
+    [0.309017, 0.500000, -0.809017],
+    [0.425325, 0.688191, -0.587785],
+    [0.442863, 0.238856, -0.864188],
+    [0.587785, 0.425325, -0.688191],
+    [0.688191, 0.587785, -0.425325],
+    [-0.147621, 0.716567, -0.681718],
+    [-0.309017, 0.500000, -0.809017],
+    [0.000000, 0.525731, -0.850651],
+    [-0.525731, 0.000000, -0.850651],
+    [-0.442863, 0.238856, -0.864188],
+    [-0.295242, 0.000000, -0.955423],
+    [-0.162460, 0.262866, -0.951056],
+    [0.000000, 0.000000, -1.000000],
+    [0.295242, 0.000000, -0.955423],
+    [0.162460, 0.262866, -0.951056],
+    [-0.442863, -0.238856, -0.864188],
+    [-0.309017, -0.500000, -0.809017],
+    [-0.162460, -0.262866, -0.951056],
+    [0.000000, -0.850651, -0.525731],
+    [-0.147621, -0.716567, -0.681718],
+    [0.147621, -0.716567, -0.681718],
+    [0.000000, -0.525731, -0.850651],
+    [0.309017, -0.500000, -0.809017],
+    [0.442863, -0.238856, -0.864188],
+    [0.162460, -0.262866, -0.951056],
+    [0.238856, -0.864188, -0.442863],
+    [0.500000, -0.809017, -0.309017],
+    [0.425325, -0.688191, -0.587785],
+    [0.716567, -0.681718, -0.147621],
+    [0.688191, -0.587785, -0.425325],
+    [0.587785, -0.425325, -0.688191],
+    [0.000000, -0.955423, -0.295242],
+    [0.000000, -1.000000, 0.000000],
+    [0.262866, -0.951056, -0.162460],
+    [0.000000, -0.850651, 0.525731],
+    [0.000000, -0.955423, 0.295242],
+    [0.238856, -0.864188, 0.442863],
+    [0.262866, -0.951056, 0.162460],
+    [0.500000, -0.809017, 0.309017],
+    [0.716567, -0.681718, 0.147621],
+    [0.525731, -0.850651, 0.000000],
+    [-0.238856, -0.864188, -0.442863],
+    [-0.500000, -0.809017, -0.309017],
+    [-0.262866, -0.951056, -0.162460],
+    [-0.850651, -0.525731, 0.000000],
+    [-0.716567, -0.681718, -0.147621],
+    [-0.716567, -0.681718, 0.147621],
+    [-0.525731, -0.850651, 0.000000],
+    [-0.500000, -0.809017, 0.309017],
+    [-0.238856, -0.864188, 0.442863],
+    [-0.262866, -0.951056, 0.162460],
+    [-0.864188, -0.442863, 0.238856],
+    [-0.809017, -0.309017, 0.500000],
+    [-0.688191, -0.587785, 0.425325],
+    [-0.681718, -0.147621, 0.716567],
+    [-0.442863, -0.238856, 0.864188],
+    [-0.587785, -0.425325, 0.688191],
+    [-0.309017, -0.500000, 0.809017],
+    [-0.147621, -0.716567, 0.681718],
+    [-0.425325, -0.688191, 0.587785],
+    [-0.162460, -0.262866, 0.951056],
+    [0.442863, -0.238856, 0.864188],
+    [0.162460, -0.262866, 0.951056],
+    [0.309017, -0.500000, 0.809017],
+    [0.147621, -0.716567, 0.681718],
+    [0.000000, -0.525731, 0.850651],
+    [0.425325, -0.688191, 0.587785],
+    [0.587785, -0.425325, 0.688191],
+    [0.688191, -0.587785, 0.425325],
+    [-0.955423, 0.295242, 0.000000],
+    [-0.951056, 0.162460, 0.262866],
+    [-1.000000, 0.000000, 0.000000],
+    [-0.850651, 0.000000, 0.525731],
+    [-0.955423, -0.295242, 0.000000],
+    [-0.951056, -0.162460, 0.262866],
+    [-0.864188, 0.442863, -0.238856],
+    [-0.951056, 0.162460, -0.262866],
+    [-0.809017, 0.309017, -0.500000],
+    [-0.864188, -0.442863, -0.238856],
+    [-0.951056, -0.162460, -0.262866],
+    [-0.809017, -0.309017, -0.500000],
+    [-0.681718, 0.147621, -0.716567],
+    [-0.681718, -0.147621, -0.716567],
+    [-0.850651, 0.000000, -0.525731],
+    [-0.688191, 0.587785, -0.425325],
+    [-0.587785, 0.425325, -0.688191],
+    [-0.425325, 0.688191, -0.587785],
+    [-0.425325, -0.688191, -0.587785],
+    [-0.587785, -0.425325, -0.688191],
+    [-0.688191, -0.587785, -0.425325],
+];
@@ -0,0 +1,129 @@
+use straal::{Vec3n, Vec4n};
+
+use crate::models::*;
+
+//Column major, the layout glium takes for mat4 uniforms
+type Matrix = [[f32; 4]; 4];
+
+impl SkinnedModel {
+    //The mesh in its bind pose
+    pub fn model(&self) -> &ObjModel {
+        &self.model
+    }
+
+    //One entry per vertex of model(), empty when the mesh is not skinned
+    pub fn joint_weights(&self) -> &[JointWeights] {
+        &self.joint_weights
+    }
+
+    pub fn joints(&self) -> &[Joint] {
+        &self.joints
+    }
+
+    pub fn animations(&self) -> &[AnimationClip] {
+        &self.animations
+    }
+
+    pub fn bind_pose(&self) -> Vec<JointPose> {
+        self.joints.iter().map(|joint| joint.bind_pose).collect()
+    }
+
+    //The pose of an animation at a time in seconds, blending the two frames around it.
+    //Looping clips wrap around to their first frame, others hold their last one
+    pub fn sample(&self, clip: usize, time: f32) -> Vec<JointPose> {
+        let clip = &self.animations[clip];
+        let count = clip.frames.len();
+        if count == 0 {
+            return self.bind_pose();
+        }
+        let position = time * clip.frame_rate;
+        let (position, next) = if clip.looping {
+            let position = position.rem_euclid(count as f32);
+            (position, (position as usize + 1) % count)
+        } else {
+            let position = position.max(0.0).min((count - 1) as f32);
+            (position, (position as usize + 1).min(count - 1))
+        };
+        let frame = (position as usize).min(count - 1);
+        let t = position - position.floor();
+        clip.frames[frame].iter().zip(clip.frames[next].iter()).map(|(a, b)| blend(a, b, t)).collect()
+    }
+
+    //The matrices that move vertices from the bind pose to a pose, indexed like the joints
+    pub fn skinning_matrices(&self, pose: &[JointPose]) -> Vec<Matrix> {
+        let mut world: Vec<Matrix> = Vec::with_capacity(self.joints.len());
+        let mut inverse_bind: Vec<Matrix> = Vec::with_capacity(self.joints.len());
+        for (joint, local) in self.joints.iter().zip(pose.iter()) {
+            let local = transform_matrix(local.translation, local.rotation, local.scale);
+            let local_inverse = inverse_transform_matrix(&joint.bind_pose);
+            match joint.parent {
+                Some(parent) => {
+                    world.push(multiply(&world[parent], &local));
+                    inverse_bind.push(multiply(&local_inverse, &inverse_bind[parent]));
+                }
+                None => {
+                    world.push(local);
+                    inverse_bind.push(local_inverse);
+                }
+            }
+        }
+        world.iter().zip(inverse_bind.iter()).map(|(w, i)| multiply(w, i)).collect()
+    }
+
+    //Creates buffers that include the joint weights, the skinning itself is left to the vertex shader
+    pub fn gen_glium_buffer(&self, display: &glium::Display) -> GliumBuffers {
+        let mut buffers = self.model.gen_glium_buffer(display);
+        buffers.joint_weights = glium::VertexBuffer::new(display, &self.joint_weights).unwrap();
+        buffers.has_joint_weights = !self.joint_weights.is_empty();
+        buffers
+    }
+}
+
+//Translation and scale are blended linearly, rotations along the shortest way and normalized again
+fn blend(a: &JointPose, b: &JointPose, t: f32) -> JointPose {
+    let mix = |x: f32, y: f32| x + (y - x) * t;
+    let mix3 = |p: Vec3n, q: Vec3n| Vec3n::new(mix(p.x, q.x), mix(p.y, q.y), mix(p.z, q.z));
+    let (p, mut q) = (a.rotation, b.rotation);
+    if p.x * q.x + p.y * q.y + p.z * q.z + p.w * q.w < 0.0 {
+        q = Vec4n::new(-q.x, -q.y, -q.z, -q.w);
+    }
+    let rotation = Vec4n::new(mix(p.x, q.x), mix(p.y, q.y), mix(p.z, q.z), mix(p.w, q.w));
+    JointPose { translation: mix3(a.translation, b.translation), rotation: normalized_quaternion(rotation), scale: mix3(a.scale, b.scale) }
+}
+
+pub(crate) fn normalized_quaternion(q: Vec4n) -> Vec4n {
+    let length = (q.x * q.x + q.y * q.y + q.z * q.z + q.w * q.w).sqrt();
+    if length > 0.0 { Vec4n::new(q.x / length, q.y / length, q.z / length, q.w / length) } else { Vec4n::new(0.0, 0.0, 0.0, 1.0) }
+}
+
+//Scales, then rotates, then translates
+fn transform_matrix(translation: Vec3n, rotation: Vec4n, scale: Vec3n) -> Matrix {
+    let (x, y, z, w) = (rotation.x, rotation.y, rotation.z, rotation.w);
+    [
+        [(1.0 - 2.0 * (y * y + z * z)) * scale.x, 2.0 * (x * y + w * z) * scale.x, 2.0 * (x * z - w * y) * scale.x, 0.0],
+        [2.0 * (x * y - w * z) * scale.y, (1.0 - 2.0 * (x * x + z * z)) * scale.y, 2.0 * (y * z + w * x) * scale.y, 0.0],
+        [2.0 * (x * z + w * y) * scale.z, 2.0 * (y * z - w * x) * scale.z, (1.0 - 2.0 * (x * x + y * y)) * scale.z, 0.0],
+        [translation.x, translation.y, translation.z, 1.0],
+    ]
+}
+
+fn inverse_transform_matrix(pose: &JointPose) -> Matrix {
+    let identity_rotation = Vec4n::new(0.0, 0.0, 0.0, 1.0);
+    let inverse = |s: f32| if s != 0.0 { 1.0 / s } else { 0.0 };
+    let t = pose.translation;
+    let r = pose.rotation;
+    let scale = transform_matrix(Vec3n::zero(), identity_rotation, Vec3n::new(inverse(pose.scale.x), inverse(pose.scale.y), inverse(pose.scale.z)));
+    let rotation = transform_matrix(Vec3n::zero(), Vec4n::new(-r.x, -r.y, -r.z, r.w), Vec3n::new(1.0, 1.0, 1.0));
+    let translation = transform_matrix(Vec3n::new(-t.x, -t.y, -t.z), identity_rotation, Vec3n::new(1.0, 1.0, 1.0));
+    multiply(&scale, &multiply(&rotation, &translation))
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (column, result_column) in result.iter_mut().enumerate() {
+        for (row, value) in result_column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    result
+}
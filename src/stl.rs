@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fs;
+
+use straal::{Vec3n, Vec4n};
+
+use crate::error::{BYTES_SOURCE_NAME, ModelLoadingError, SourceLocation};
+use crate::models::*;
+use crate::text::{LineContext, tokenize};
+use crate::triangulation;
+
+const BINARY_HEADER_SIZE: usize = 80;
+const BINARY_TRIANGLE_SIZE: usize = 50;
+
+//Materialise Magics stores a default color in the header after this marker
+const MAGICS_COLOR_MARKER: &[u8] = b"COLOR=";
+
+//Stored normals further than this from unit length are not trusted and derived from the winding instead
+const UNIT_LENGTH_EPSILON: f32 = 1e-3;
+
+struct Facet {
+    normal: Vec3n,
+    corners: Vec<Vec3n>,
+    color: Option<Vec4n>,
+    //Index into the solids of an ASCII file
+    solid: Option<usize>,
+}
+
+impl ObjModel {
+    //Loads an ASCII or a binary STL file. Triangles that share a corner, facet normal and color share the vertex,
+    //so the mesh is indexed while the flat shading of the file is kept
+    pub fn load_stl_from_file(file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+        let bytes = fs::read(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        ObjModel::parse_stl(&bytes, file_path)
+    }
+
+    pub fn from_stl_bytes(bytes: &[u8]) -> Result<ObjModel, ModelLoadingError> {
+        ObjModel::parse_stl(bytes, BYTES_SOURCE_NAME)
+    }
+
+    fn parse_stl(bytes: &[u8], file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+        //Plenty of binary files start their header with "solid" too. A size that matches the triangle count settles it,
+        //otherwise text never contains zero bytes while binary numbers nearly always do
+        let binary_size_matches = bytes.len() >= BINARY_HEADER_SIZE + 4
+            && BINARY_HEADER_SIZE + 4 + binary_triangle_count(bytes) * BINARY_TRIANGLE_SIZE == bytes.len();
+        let starts_with_solid = String::from_utf8_lossy(&bytes[..bytes.len().min(BINARY_HEADER_SIZE)]).trim_start().starts_with("solid");
+        let is_ascii = starts_with_solid && !binary_size_matches && !bytes.contains(&0);
+
+        let (facets, solids) = if is_ascii {
+            parse_ascii_stl(bytes, file_path)?
+        } else {
+            (parse_binary_stl(bytes, file_path)?, Vec::new())
+        };
+        Ok(build_stl_model(facets, solids))
+    }
+}
+
+fn binary_triangle_count(bytes: &[u8]) -> usize {
+    let at = BINARY_HEADER_SIZE;
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) as usize
+}
+
+//solid name
+//  facet normal nx ny nz
+//    outer loop
+//      vertex x y z
+//      ...
+//    endloop
+//  endfacet
+//endsolid name
+fn parse_ascii_stl(bytes: &[u8], file_path: &str) -> Result<(Vec<Facet>, Vec<String>), ModelLoadingError> {
+    let source = String::from_utf8_lossy(bytes);
+    let mut facets = Vec::new();
+    let mut solids: Vec<String> = Vec::new();
+    let mut current_solid = None;
+    let mut current_facet: Option<Facet> = None;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let context = LineContext { file_path, line_no: line_index + 1, line };
+        let tokens = tokenize(line);
+        if tokens.is_empty() {
+            continue;
+        }
+        match tokens[0].1 {
+            "solid" => {
+                let name = if tokens.len() > 1 { context.rest_of_line(&tokens, 1)? } else { "default" };
+                solids.push(name.to_string());
+                current_solid = Some(solids.len() - 1);
+            }
+            "facet" => {
+                //"normal" takes the place of the keyword so the numbers after it are parsed
+                let parsed = context.parse_floats(&tokens[1..], 3, 3)?;
+                current_facet = Some(Facet {
+                    normal: Vec3n::new(parsed[0], parsed[1], parsed[2]),
+                    corners: Vec::new(),
+                    color: None,
+                    solid: current_solid,
+                });
+            }
+            "vertex" => {
+                let parsed = context.parse_floats(&tokens, 3, 3)?;
+                //Vertices outside of a facet are accepted, their normal is derived from the winding
+                current_facet.get_or_insert_with(|| Facet { normal: Vec3n::zero(), corners: Vec::new(), color: None, solid: current_solid })
+                    .corners.push(Vec3n::new(parsed[0], parsed[1], parsed[2]));
+            }
+            "endfacet" => {
+                if let Some(facet) = current_facet.take() {
+                    facets.push(facet);
+                }
+            }
+            "endsolid" => current_solid = None,
+            "outer" | "endloop" => {}
+            _ => {
+                return Err(ModelLoadingError::UnsupportedStatement {
+                    location: context.location(tokens[0].0),
+                    text: tokens[0].1.to_string(),
+                });
+            }
+        }
+    }
+    if let Some(facet) = current_facet {
+        facets.push(facet);
+    }
+
+    Ok((facets, solids))
+}
+
+//80 byte header, triangle count, then per triangle a normal, three corners and a 16 bit attribute
+fn parse_binary_stl(bytes: &[u8], file_path: &str) -> Result<Vec<Facet>, ModelLoadingError> {
+    if bytes.len() < BINARY_HEADER_SIZE + 4 {
+        return Err(ModelLoadingError::InvalidData {
+            location: SourceLocation::file(file_path),
+            description: format!("the file holds {} bytes, which is too short for a binary STL header", bytes.len()),
+        });
+    }
+    let count = binary_triangle_count(bytes);
+    let expected_size = BINARY_HEADER_SIZE + 4 + count * BINARY_TRIANGLE_SIZE;
+    if bytes.len() < expected_size {
+        return Err(ModelLoadingError::InvalidData {
+            location: SourceLocation::file(file_path),
+            description: format!("the header announces {} triangles, which need {} bytes but the file holds {}", count, expected_size, bytes.len()),
+        });
+    }
+
+    let header = &bytes[..BINARY_HEADER_SIZE];
+    let magics_default_color = header.windows(MAGICS_COLOR_MARKER.len())
+        .position(|w| w == MAGICS_COLOR_MARKER)
+        .map(|at| at + MAGICS_COLOR_MARKER.len())
+        .filter(|&at| at + 4 <= BINARY_HEADER_SIZE)
+        .map(|at| Vec4n::new(f32::from(header[at]) / 255.0, f32::from(header[at + 1]) / 255.0,
+                             f32::from(header[at + 2]) / 255.0, f32::from(header[at + 3]) / 255.0));
+
+    let read_vec3 = |at: usize| {
+        let float = |at: usize| f32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+        Vec3n::new(float(at), float(at + 4), float(at + 8))
+    };
+    let mut facets = Vec::with_capacity(count);
+    for triangle in 0..count {
+        let at = BINARY_HEADER_SIZE + 4 + triangle * BINARY_TRIANGLE_SIZE;
+        let attribute = u16::from_le_bytes([bytes[at + 48], bytes[at + 49]]);
+        facets.push(Facet {
+            normal: read_vec3(at),
+            corners: vec![read_vec3(at + 12), read_vec3(at + 24), read_vec3(at + 36)],
+            color: decode_color(attribute, magics_default_color),
+            solid: None,
+        });
+    }
+    Ok(facets)
+}
+
+//The attribute word is officially unused, two color encodings are common.
+//VisCAM and SolidView set bit 15 for a valid color with blue in the lowest bits.
+//Materialise Magics has a default color in the header, clears bit 15 for a face color and puts red in the lowest bits
+fn decode_color(attribute: u16, magics_default_color: Option<Vec4n>) -> Option<Vec4n> {
+    let channel = |shift: u16| f32::from((attribute >> shift) & 0x1F) / 31.0;
+    let flag_set = attribute & 0x8000 != 0;
+    match magics_default_color {
+        Some(default) if flag_set => Some(default),
+        Some(_) => Some(Vec4n::new(channel(0), channel(5), channel(10), 1.0)),
+        None if flag_set => Some(Vec4n::new(channel(10), channel(5), channel(0), 1.0)),
+        None => None,
+    }
+}
+
+fn build_stl_model(facets: Vec<Facet>, solids: Vec<String>) -> ObjModel {
+    let mut model = ObjModel::empty();
+    model.objects = solids.into_iter().map(|name| NamedPart { name, index_ranges: Vec::new() }).collect();
+    let has_colors = facets.iter().any(|f| f.color.is_some());
+    let white = Vec4n::new(1.0, 1.0, 1.0, 1.0);
+
+    //Position, normal and color bits
+    type VertexKey = ([u32; 3], [u32; 3], [u32; 4]);
+    let mut mapped_vertices: HashMap<VertexKey, u32> = HashMap::new();
+    for facet in facets {
+        let normal = facet_normal(&facet);
+        let color = facet.color.unwrap_or(white);
+        let start = model.indices.len();
+        for triangle in triangulation::triangulate(&facet.corners) {
+            for &corner in &triangle {
+                let position = facet.corners[corner];
+                let key = (bits3(position), bits3(normal), [color.x.to_bits(), color.y.to_bits(), color.z.to_bits(), color.w.to_bits()]);
+                let index = *mapped_vertices.entry(key).or_insert_with(|| {
+                    model.vertices.push(Vertex { position });
+                    model.normals.push(Normal { normal });
+                    if has_colors {
+                        model.colors.push(Color { color });
+                    }
+                    (model.vertices.len() - 1) as u32
+                });
+                model.indices.push(index);
+            }
+        }
+        if let Some(solid) = facet.solid {
+            model.objects[solid].extend(start..model.indices.len());
+        }
+    }
+
+    model.objects.retain(|o| !o.index_ranges.is_empty());
+    model.sub_meshes.push(SubMesh { index_range: 0..model.indices.len(), material_name: None, material: None });
+    model
+}
+
+//The stored normal is kept as it is, many exporters write zero normals though, those are derived from the winding
+fn facet_normal(facet: &Facet) -> Vec3n {
+    let stored = facet.normal;
+    let length = stored.dot(stored).sqrt();
+    if (length - 1.0).abs() < UNIT_LENGTH_EPSILON {
+        return stored;
+    }
+    let mut normal = Vec3n::zero();
+    for i in 1..facet.corners.len().saturating_sub(1) {
+        normal += (facet.corners[i] - facet.corners[0]).cross(facet.corners[i + 1] - facet.corners[0]);
+    }
+    ObjModel::safe_normalized(normal)
+}
+
+fn bits3(v: Vec3n) -> [u32; 3] {
+    [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()]
+}
+
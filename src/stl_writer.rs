@@ -0,0 +1,90 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use straal::Vec3n;
+
+use crate::models::*;
+
+//Binary headers must not start with "solid", or readers may take the file for ASCII
+const BINARY_HEADER: &[u8] = b"binary STL written by vertexify";
+
+impl ObjModel {
+    //Writes the triangles as STL, facet normals are computed from the winding
+    pub fn write_stl_to<W: Write>(&self, mut writer: W, format: StlFormat) -> io::Result<()> {
+        match format {
+            StlFormat::Ascii => self.write_ascii_stl(&mut writer),
+            StlFormat::Binary => self.write_binary_stl(&mut writer, false),
+            StlFormat::BinaryWithColors => self.write_binary_stl(&mut writer, true),
+        }
+    }
+
+    pub fn save_stl_to_file(&self, file_path: &str, format: StlFormat) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(file_path)?);
+        self.write_stl_to(&mut writer, format)?;
+        writer.flush()
+    }
+
+    fn write_ascii_stl<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let name = self.objects.first().map(|o| o.name.as_str()).unwrap_or("vertexify");
+        writeln!(writer, "solid {}", name)?;
+        for triangle in self.indices.chunks(3) {
+            let corners = self.triangle_corners(triangle);
+            let normal = facet_normal(&corners);
+            writeln!(writer, "facet normal {} {} {}", normal.x, normal.y, normal.z)?;
+            writeln!(writer, "  outer loop")?;
+            for corner in &corners {
+                writeln!(writer, "    vertex {} {} {}", corner.x, corner.y, corner.z)?;
+            }
+            writeln!(writer, "  endloop")?;
+            writeln!(writer, "endfacet")?;
+        }
+        writeln!(writer, "endsolid {}", name)
+    }
+
+    fn write_binary_stl<W: Write>(&self, writer: &mut W, with_colors: bool) -> io::Result<()> {
+        let mut header = BINARY_HEADER.to_vec();
+        header.resize(80, 0);
+        writer.write_all(&header)?;
+        writer.write_all(&((self.indices.len() / 3) as u32).to_le_bytes())?;
+
+        for triangle in self.indices.chunks(3) {
+            let corners = self.triangle_corners(triangle);
+            let normal = facet_normal(&corners);
+            for v in [normal].iter().chain(corners.iter()) {
+                for component in &[v.x, v.y, v.z] {
+                    writer.write_all(&component.to_le_bytes())?;
+                }
+            }
+            let attribute = if with_colors { self.face_color(triangle) } else { 0 };
+            writer.write_all(&attribute.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn triangle_corners(&self, triangle: &[u32]) -> [Vec3n; 3] {
+        [self.vertices[triangle[0] as usize].position,
+            self.vertices[triangle[1] as usize].position,
+            self.vertices[triangle[2] as usize].position]
+    }
+
+    //Average color of the corners as 5 bits per channel, blue in the lowest bits and bit 15 marking it valid.
+    //Models without colors write no color at all
+    fn face_color(&self, triangle: &[u32]) -> u16 {
+        if self.colors.is_empty() {
+            return 0;
+        }
+        let mut sum = [0.0; 3];
+        for &index in triangle {
+            let color = self.colors[index as usize].color;
+            sum[0] += color.x;
+            sum[1] += color.y;
+            sum[2] += color.z;
+        }
+        let channel = |value: f32| ((value / 3.0).clamp(0.0, 1.0) * 31.0).round() as u16;
+        0x8000 | channel(sum[0]) << 10 | channel(sum[1]) << 5 | channel(sum[2])
+    }
+}
+
+fn facet_normal(corners: &[Vec3n; 3]) -> Vec3n {
+    ObjModel::safe_normalized((corners[1] - corners[0]).cross(corners[2] - corners[0]))
+}
@@ -0,0 +1,88 @@
+use straal::Vec3n;
+
+use super::models::*;
+
+//Below this the texture coordinates of a triangle are considered degenerate
+const UV_AREA_EPSILON: f32 = 1e-12;
+
+impl ObjModel {
+    //Generates a tangent for every vertex, following the MikkTSpace approach: the tangent of every corner is
+    //projected on the plane of the vertex normal and weighted by the corner angle, and vertices are split where
+    //triangles with mirrored texture coordinates meet so the handedness stored in w is correct on both sides.
+    //Needs texture coordinates, normals are generated first when the model has none
+    pub fn generate_tangents(&mut self) {
+        if self.tex_coords.is_empty() {
+            self.tangents.clear();
+            return;
+        }
+        if self.normals.is_empty() {
+            self.generate_normals();
+        }
+
+        //Tangent and bitangent directions of every triangle, and whether its texture mapping is mirrored
+        let mut face_directions = Vec::with_capacity(self.indices.len() / 3);
+        for triangle in self.indices.chunks(3) {
+            let p: Vec<Vec3n> = triangle.iter().map(|&i| self.vertices[i as usize].position).collect();
+            let uv: Vec<straal::Vec2n> = triangle.iter().map(|&i| self.tex_coords[i as usize].tex_coords).collect();
+
+            let e1 = p[1] - p[0];
+            let e2 = p[2] - p[0];
+            let (s1, t1) = (uv[1].x - uv[0].x, uv[1].y - uv[0].y);
+            let (s2, t2) = (uv[2].x - uv[0].x, uv[2].y - uv[0].y);
+            let det = s1 * t2 - s2 * t1;
+            if det.abs() < UV_AREA_EPSILON {
+                face_directions.push((Vec3n::zero(), Vec3n::zero(), false));
+                continue;
+            }
+
+            let r = 1.0 / det;
+            let tangent = ObjModel::scaled(ObjModel::scaled(e1, t2) - ObjModel::scaled(e2, t1), r);
+            let bitangent = ObjModel::scaled(ObjModel::scaled(e2, s1) - ObjModel::scaled(e1, s2), r);
+            face_directions.push((tangent, bitangent, det < 0.0));
+        }
+
+        //Mirrored and regular triangles can not share a tangent
+        let corner_keys: Vec<(u32, bool)> = self.indices.iter().enumerate()
+            .map(|(corner, &index)| (index, face_directions[corner / 3].2))
+            .collect();
+        self.split_vertices(&corner_keys);
+
+        let mut tangent_sums = vec![Vec3n::zero(); self.vertices.len()];
+        let mut bitangent_sums = vec![Vec3n::zero(); self.vertices.len()];
+        for (corner, &index) in self.indices.iter().enumerate() {
+            let triangle = corner - corner % 3;
+            let position = |offset: usize| self.vertices[self.indices[triangle + (corner + offset) % 3] as usize].position;
+            let e0 = ObjModel::safe_normalized(position(1) - position(0));
+            let e1 = ObjModel::safe_normalized(position(2) - position(0));
+            let angle = e0.dot(e1).clamp(-1.0, 1.0).acos();
+
+            let normal = self.normals[index as usize].normal;
+            let (tangent, bitangent, _) = face_directions[corner / 3];
+            tangent_sums[index as usize] += ObjModel::scaled(ObjModel::safe_normalized(project_on_plane(tangent, normal)), angle);
+            bitangent_sums[index as usize] += ObjModel::scaled(ObjModel::safe_normalized(project_on_plane(bitangent, normal)), angle);
+        }
+
+        self.tangents = self.normals.iter().zip(tangent_sums.iter().zip(bitangent_sums.iter()))
+            .map(|(normal, (&tangent, &bitangent))| {
+                let normal = normal.normal;
+                let mut tangent = ObjModel::safe_normalized(project_on_plane(tangent, normal));
+                if tangent.dot(tangent) == 0.0 {
+                    //No usable texture mapping around this vertex, any direction along the surface will do
+                    tangent = any_perpendicular(normal);
+                }
+                let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+                Tangent { tangent: straal::Vec4n::new(tangent.x, tangent.y, tangent.z, handedness) }
+            })
+            .collect();
+    }
+}
+
+//Removes the part of v that lies along the (unit length) normal
+fn project_on_plane(v: Vec3n, normal: Vec3n) -> Vec3n {
+    v - ObjModel::scaled(normal, normal.dot(v))
+}
+
+fn any_perpendicular(normal: Vec3n) -> Vec3n {
+    let axis = if normal.x.abs() < 0.9 { Vec3n::new(1.0, 0.0, 0.0) } else { Vec3n::new(0.0, 1.0, 0.0) };
+    ObjModel::safe_normalized(project_on_plane(axis, normal))
+}
@@ -0,0 +1,102 @@
+use crate::error::{ModelLoadingError, SourceLocation};
+
+//Position of the line currently being parsed, used to give errors a location
+pub(crate) struct LineContext<'a> {
+    pub file_path: &'a str,
+    pub line_no: usize,
+    pub line: &'a str,
+}
+
+impl<'a> LineContext<'a> {
+    pub fn location(&self, column: usize) -> SourceLocation {
+        SourceLocation::new(self.file_path, self.line_no, column)
+    }
+
+    //Parses the components following the statement keyword, at least `required` and at most `max` of them
+    pub fn parse_floats(&self, tokens: &[(usize, &str)], required: usize, max: usize) -> Result<Vec<f32>, ModelLoadingError> {
+        let components = &tokens[1..];
+        if components.len() < required {
+            return Err(ModelLoadingError::MissingComponents {
+                location: self.location(tokens[0].0),
+                text: self.line.to_string(),
+                expected: required,
+                found: components.len(),
+            });
+        }
+        components.iter().take(max).map(|&(column, text)| self.parse_float(column, text)).collect()
+    }
+
+    //The remainder of the line starting at the given token, for names and paths that may contain spaces
+    pub fn rest_of_line(&self, tokens: &[(usize, &str)], from: usize) -> Result<&'a str, ModelLoadingError> {
+        match tokens.get(from) {
+            Some(&(column, _)) => Ok(self.line[column - 1..].trim_end()),
+            None => Err(ModelLoadingError::MissingComponents {
+                location: self.location(tokens[0].0),
+                text: self.line.to_string(),
+                expected: from,
+                found: tokens.len() - 1,
+            }),
+        }
+    }
+
+    pub fn parse_float(&self, column: usize, text: &str) -> Result<f32, ModelLoadingError> {
+        text.parse::<f32>().map_err(|e| ModelLoadingError::ParseFloat {
+            location: self.location(column),
+            text: text.to_string(),
+            source: e,
+        })
+    }
+
+    //Parses a 1 based index, negative indices are relative to the end of the elements defined so far.
+    //Returns the 0 based index of the element it refers to
+    pub fn parse_index(&self, column: usize, text: &str, count: usize) -> Result<usize, ModelLoadingError> {
+        let index: i64 = text.parse().map_err(|e| ModelLoadingError::ParseIndex {
+            location: self.location(column),
+            text: text.to_string(),
+            source: e,
+        })?;
+        let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(ModelLoadingError::IndexOutOfRange {
+                location: self.location(column),
+                text: text.to_string(),
+                index,
+                count,
+            });
+        }
+        Ok(resolved as usize)
+    }
+}
+
+//Splits a line on whitespace, keeping the 1 based column every token starts at
+pub(crate) fn tokenize(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push((s + 1, &line[s..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s + 1, &line[s..]));
+    }
+    tokens
+}
+
+//Writes a number with at most `precision` decimals, dropping trailing zeros so round numbers stay short
+pub(crate) fn format_float(value: f32, precision: usize) -> String {
+    let mut text = format!("{:.*}", precision, value);
+    if text.contains('.') {
+        let trimmed = text.trim_end_matches('0').trim_end_matches('.').len();
+        text.truncate(trimmed);
+    }
+    if text == "-0" {
+        text.remove(0);
+    }
+    text
+}
@@ -0,0 +1,366 @@
+use std::fs;
+
+use straal::Vec3n;
+
+use crate::error::{BYTES_SOURCE_NAME, ModelLoadingError, SourceLocation};
+use crate::models::*;
+use crate::mtl::{Material, TextureMap, TextureOptions};
+
+const CHUNK_HEADER_SIZE: usize = 6;
+
+const MAIN3DS: u16 = 0x4D4D;
+const EDIT3DS: u16 = 0x3D3D;
+const EDIT_OBJECT: u16 = 0x4000;
+const OBJ_TRIMESH: u16 = 0x4100;
+const VERTEX_LIST: u16 = 0x4110;
+const FACE_LIST: u16 = 0x4120;
+const FACE_MATERIAL: u16 = 0x4130;
+const MAPPING_COORDS: u16 = 0x4140;
+const SMOOTHING_GROUPS: u16 = 0x4150;
+const LOCAL_MATRIX: u16 = 0x4160;
+
+const MAT_ENTRY: u16 = 0xAFFF;
+const MAT_NAME: u16 = 0xA000;
+const MAT_AMBIENT: u16 = 0xA010;
+const MAT_DIFFUSE: u16 = 0xA020;
+const MAT_SPECULAR: u16 = 0xA030;
+const MAT_SHININESS: u16 = 0xA040;
+const MAT_TRANSPARENCY: u16 = 0xA050;
+const MAT_TEXTURE_MAP: u16 = 0xA200;
+const MAT_SPECULAR_MAP: u16 = 0xA204;
+const MAT_OPACITY_MAP: u16 = 0xA210;
+const MAT_REFLECTION_MAP: u16 = 0xA220;
+const MAT_BUMP_MAP: u16 = 0xA230;
+const MAP_FILE_NAME: u16 = 0xA300;
+const MAP_U_SCALE: u16 = 0xA354;
+const MAP_V_SCALE: u16 = 0xA356;
+const MAP_U_OFFSET: u16 = 0xA358;
+const MAP_V_OFFSET: u16 = 0xA35A;
+
+const COLOR_FLOAT: u16 = 0x0010;
+const COLOR_24: u16 = 0x0011;
+const LINEAR_COLOR_24: u16 = 0x0012;
+const LINEAR_COLOR_FLOAT: u16 = 0x0013;
+const PERCENT_INT: u16 = 0x0030;
+const PERCENT_FLOAT: u16 = 0x0031;
+
+//Shininess is stored as a percentage, OBJ exponents use the range of fixed function OpenGL
+const MAX_SHININESS: f32 = 128.0;
+
+//A chunk is an id and a length that includes the 6 byte header, followed by data and child chunks
+struct Chunk<'b> {
+    id: u16,
+    data: &'b [u8],
+    //Where the data starts in the file
+    offset: usize,
+}
+
+//Reads the fixed part of a chunk before its children
+struct ChunkReader<'b, 'p> {
+    chunk: &'b Chunk<'b>,
+    at: usize,
+    file_path: &'p str,
+}
+
+impl<'b, 'p> ChunkReader<'b, 'p> {
+    fn new(chunk: &'b Chunk<'b>, file_path: &'p str) -> ChunkReader<'b, 'p> {
+        ChunkReader { chunk, at: 0, file_path }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'b [u8], ModelLoadingError> {
+        let bytes = self.chunk.data.get(self.at..self.at + length).ok_or_else(|| ModelLoadingError::InvalidData {
+            location: SourceLocation::file(self.file_path),
+            description: format!("chunk 0x{:04X} at byte {} is too short for its contents", self.chunk.id, self.chunk.offset - CHUNK_HEADER_SIZE),
+        })?;
+        self.at += length;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, ModelLoadingError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, ModelLoadingError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn f32(&mut self) -> Result<f32, ModelLoadingError> {
+        let b = self.bytes(4)?;
+        Ok(f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    //Zero terminated, names are usually plain ASCII
+    fn string(&mut self) -> Result<String, ModelLoadingError> {
+        let rest = &self.chunk.data[self.at.min(self.chunk.data.len())..];
+        let length = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        let text = String::from_utf8_lossy(&rest[..length]).into_owned();
+        self.at += (length + 1).min(rest.len());
+        Ok(text)
+    }
+
+    //The chunks that follow the fixed part
+    fn children(&self) -> Result<Vec<Chunk<'b>>, ModelLoadingError> {
+        let start = self.at.min(self.chunk.data.len());
+        chunks(&self.chunk.data[start..], self.chunk.offset + start, self.file_path)
+    }
+}
+
+fn chunks<'b>(bytes: &'b [u8], offset: usize, file_path: &str) -> Result<Vec<Chunk<'b>>, ModelLoadingError> {
+    let mut chunks = Vec::new();
+    let mut at = 0;
+    while at + CHUNK_HEADER_SIZE <= bytes.len() {
+        let id = u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        let length = u32::from_le_bytes([bytes[at + 2], bytes[at + 3], bytes[at + 4], bytes[at + 5]]) as usize;
+        if length < CHUNK_HEADER_SIZE || at + length > bytes.len() {
+            return Err(ModelLoadingError::InvalidData {
+                location: SourceLocation::file(file_path),
+                description: format!("chunk 0x{:04X} at byte {} claims {} bytes, but only {} are left", id, offset + at, length, bytes.len() - at),
+            });
+        }
+        chunks.push(Chunk { id, data: &bytes[at + CHUNK_HEADER_SIZE..at + length], offset: offset + at + CHUNK_HEADER_SIZE });
+        at += length;
+    }
+    Ok(chunks)
+}
+
+//One OBJ_TRIMESH of an EDIT_OBJECT
+#[derive(Default)]
+struct TriangleMesh {
+    name: String,
+    vertices: Vec<Vec3n>,
+    tex_coords: Vec<straal::Vec2n>,
+    faces: Vec<[u16; 3]>,
+    //Material names with the faces that use them
+    face_materials: Vec<(String, Vec<u16>)>,
+    //A bit mask per face, faces smooth together when they share a bit
+    smoothing_groups: Vec<u32>,
+    //The axes and origin of the object, the vertices are already stored in world space
+    local_matrix: Option<[f32; 12]>,
+}
+
+impl ObjModel {
+    //Loads the triangle meshes of a 3DS file into a single model with an object per mesh and a sub-mesh per material.
+    //Normals are generated from the smoothing groups, which are kept per triangle as the bit masks of the file.
+    //Meshes without smoothing groups are flat shaded
+    pub fn load_3ds_from_file(file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+        let bytes = fs::read(file_path).map_err(|e| ModelLoadingError::Io {
+            location: SourceLocation::file(file_path),
+            source: e,
+        })?;
+        ObjModel::parse_3ds(&bytes, file_path)
+    }
+
+    pub fn from_3ds_bytes(bytes: &[u8]) -> Result<ObjModel, ModelLoadingError> {
+        ObjModel::parse_3ds(bytes, BYTES_SOURCE_NAME)
+    }
+
+    fn parse_3ds(bytes: &[u8], file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+        let top_level = chunks(bytes, 0, file_path)?;
+        let main = top_level.iter().find(|c| c.id == MAIN3DS).ok_or_else(|| ModelLoadingError::InvalidData {
+            location: SourceLocation::file(file_path),
+            description: "the file does not start with a 3DS main chunk".to_string(),
+        })?;
+
+        let mut materials = Vec::new();
+        let mut meshes = Vec::new();
+        for editor in ChunkReader::new(main, file_path).children()?.iter().filter(|c| c.id == EDIT3DS) {
+            for chunk in ChunkReader::new(editor, file_path).children()? {
+                match chunk.id {
+                    MAT_ENTRY => materials.push(read_material(&chunk, file_path)?),
+                    EDIT_OBJECT => {
+                        let mut object = ChunkReader::new(&chunk, file_path);
+                        let name = object.string()?;
+                        //Lights and cameras are objects too
+                        for mesh in object.children()?.iter().filter(|c| c.id == OBJ_TRIMESH) {
+                            meshes.push(read_triangle_mesh(mesh, name.clone(), file_path)?);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut model = build_3ds_model(meshes, materials, file_path)?;
+        if !model.indices.is_empty() {
+            model.generate_normals_from_smoothing_masks();
+        }
+        Ok(model)
+    }
+}
+
+fn read_triangle_mesh(chunk: &Chunk, name: String, file_path: &str) -> Result<TriangleMesh, ModelLoadingError> {
+    let mut mesh = TriangleMesh { name, ..Default::default() };
+    for child in ChunkReader::new(chunk, file_path).children()? {
+        let mut reader = ChunkReader::new(&child, file_path);
+        match child.id {
+            VERTEX_LIST => {
+                let count = reader.u16()?;
+                for _ in 0..count {
+                    mesh.vertices.push(Vec3n::new(reader.f32()?, reader.f32()?, reader.f32()?));
+                }
+            }
+            MAPPING_COORDS => {
+                let count = reader.u16()?;
+                for _ in 0..count {
+                    mesh.tex_coords.push(straal::Vec2 { x: reader.f32()?, y: reader.f32()? });
+                }
+            }
+            LOCAL_MATRIX => {
+                let mut matrix = [0.0; 12];
+                for value in matrix.iter_mut() {
+                    *value = reader.f32()?;
+                }
+                mesh.local_matrix = Some(matrix);
+            }
+            FACE_LIST => {
+                let count = reader.u16()?;
+                for _ in 0..count {
+                    mesh.faces.push([reader.u16()?, reader.u16()?, reader.u16()?]);
+                    //Edge visibility flags
+                    reader.u16()?;
+                }
+                for face_chunk in reader.children()? {
+                    let mut face_reader = ChunkReader::new(&face_chunk, file_path);
+                    match face_chunk.id {
+                        FACE_MATERIAL => {
+                            let material = face_reader.string()?;
+                            let face_count = face_reader.u16()?;
+                            let faces = (0..face_count).map(|_| face_reader.u16()).collect::<Result<_, _>>()?;
+                            mesh.face_materials.push((material, faces));
+                        }
+                        SMOOTHING_GROUPS => {
+                            mesh.smoothing_groups = (0..count).map(|_| face_reader.u32()).collect::<Result<_, _>>()?;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(mesh)
+}
+
+fn read_material(chunk: &Chunk, file_path: &str) -> Result<Material, ModelLoadingError> {
+    let mut material = Material::default();
+    for child in ChunkReader::new(chunk, file_path).children()? {
+        let mut reader = ChunkReader::new(&child, file_path);
+        match child.id {
+            MAT_NAME => material.name = reader.string()?,
+            MAT_AMBIENT => material.ambient = read_color(&child, file_path)?,
+            MAT_DIFFUSE => material.diffuse = read_color(&child, file_path)?,
+            MAT_SPECULAR => material.specular = read_color(&child, file_path)?,
+            MAT_SHININESS => material.shininess = read_percentage(&child, file_path)?.map(|p| p * MAX_SHININESS),
+            MAT_TRANSPARENCY => material.dissolve = read_percentage(&child, file_path)?.map(|p| 1.0 - p),
+            MAT_TEXTURE_MAP => material.diffuse_map = read_map(&child, file_path)?,
+            MAT_SPECULAR_MAP => material.specular_map = read_map(&child, file_path)?,
+            MAT_OPACITY_MAP => material.dissolve_map = read_map(&child, file_path)?,
+            MAT_REFLECTION_MAP => material.reflection_map = read_map(&child, file_path)?,
+            MAT_BUMP_MAP => material.bump_map = read_map(&child, file_path)?,
+            _ => {}
+        }
+    }
+    Ok(material)
+}
+
+//Colors are stored in a child chunk, some exporters add a gamma corrected copy after the first one
+fn read_color(chunk: &Chunk, file_path: &str) -> Result<Option<Vec3n>, ModelLoadingError> {
+    for child in ChunkReader::new(chunk, file_path).children()? {
+        let mut reader = ChunkReader::new(&child, file_path);
+        match child.id {
+            COLOR_FLOAT | LINEAR_COLOR_FLOAT => return Ok(Some(Vec3n::new(reader.f32()?, reader.f32()?, reader.f32()?))),
+            COLOR_24 | LINEAR_COLOR_24 => {
+                let rgb = reader.bytes(3)?;
+                return Ok(Some(Vec3n::new(f32::from(rgb[0]) / 255.0, f32::from(rgb[1]) / 255.0, f32::from(rgb[2]) / 255.0)));
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+//A percentage as a fraction between 0 and 1
+fn read_percentage(chunk: &Chunk, file_path: &str) -> Result<Option<f32>, ModelLoadingError> {
+    for child in ChunkReader::new(chunk, file_path).children()? {
+        let mut reader = ChunkReader::new(&child, file_path);
+        match child.id {
+            PERCENT_INT => return Ok(Some(f32::from(reader.u16()?) / 100.0)),
+            PERCENT_FLOAT => return Ok(Some(reader.f32()? / 100.0)),
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+fn read_map(chunk: &Chunk, file_path: &str) -> Result<Option<TextureMap>, ModelLoadingError> {
+    let mut path = None;
+    let mut options = TextureOptions::default();
+    for child in ChunkReader::new(chunk, file_path).children()? {
+        let mut reader = ChunkReader::new(&child, file_path);
+        match child.id {
+            MAP_FILE_NAME => path = Some(reader.string()?),
+            MAP_U_SCALE => options.scale.x = reader.f32()?,
+            MAP_V_SCALE => options.scale.y = reader.f32()?,
+            MAP_U_OFFSET => options.offset.x = reader.f32()?,
+            MAP_V_OFFSET => options.offset.y = reader.f32()?,
+            _ => {}
+        }
+    }
+    Ok(path.filter(|p| !p.is_empty()).map(|path| TextureMap { path, options }))
+}
+
+fn build_3ds_model(meshes: Vec<TriangleMesh>, materials: Vec<Material>, file_path: &str) -> Result<ObjModel, ModelLoadingError> {
+    let mut model = ObjModel { materials, ..ObjModel::empty() };
+    let has_tex_coords = meshes.iter().any(|m| !m.tex_coords.is_empty());
+
+    for mesh in meshes {
+        let base = model.vertices.len() as u32;
+        let object_start = model.indices.len();
+        model.vertices.extend(mesh.vertices.iter().map(|&position| Vertex { position }));
+        if has_tex_coords {
+            let zero = straal::Vec2 { x: 0.0, y: 0.0 };
+            model.tex_coords.extend((0..mesh.vertices.len()).map(|i| UV { tex_coords: mesh.tex_coords.get(i).copied().unwrap_or(zero) }));
+        }
+        if let Some(face) = mesh.faces.iter().flatten().find(|&&i| i as usize >= mesh.vertices.len()) {
+            return Err(ModelLoadingError::InvalidData {
+                location: SourceLocation::file(file_path),
+                description: format!("a face of {} refers to vertex {}, only {} are defined", mesh.name, face, mesh.vertices.len()),
+            });
+        }
+
+        //Objects that were mirrored have their faces turned inside out by the transform
+        let mirrored = mesh.local_matrix.map(|m| {
+            m[0] * (m[4] * m[8] - m[5] * m[7]) - m[1] * (m[3] * m[8] - m[5] * m[6]) + m[2] * (m[3] * m[7] - m[4] * m[6]) < 0.0
+        }).unwrap_or(false);
+
+        //Faces are sorted into a sub-mesh per material group, the ones without a material come last
+        let mut face_groups: Vec<(Option<String>, Vec<usize>)> = Vec::new();
+        let mut assigned = vec![false; mesh.faces.len()];
+        for (material, faces) in &mesh.face_materials {
+            let faces: Vec<usize> = faces.iter().map(|&f| f as usize).filter(|&f| f < mesh.faces.len() && !assigned[f]).collect();
+            faces.iter().for_each(|&f| assigned[f] = true);
+            face_groups.push((Some(material.clone()), faces));
+        }
+        face_groups.push((None, (0..mesh.faces.len()).filter(|&f| !assigned[f]).collect()));
+
+        for (material_name, faces) in face_groups.into_iter().filter(|(_, faces)| !faces.is_empty()) {
+            let start = model.indices.len();
+            for face in faces {
+                let [a, b, c] = mesh.faces[face];
+                let corners = if mirrored { [a, c, b] } else { [a, b, c] };
+                model.indices.extend(corners.iter().map(|&i| base + u32::from(i)));
+                //Faces without a smoothing group, like those of a mesh without the chunk, stay flat
+                model.smoothing_groups.push(mesh.smoothing_groups.get(face).copied().unwrap_or(0));
+            }
+            let material = material_name.as_ref().and_then(|name| model.materials.iter().position(|m| &m.name == name));
+            model.sub_meshes.push(SubMesh { index_range: start..model.indices.len(), material_name, material });
+        }
+
+        let mut object = NamedPart { name: mesh.name, index_ranges: Vec::new() };
+        object.extend(object_start..model.indices.len());
+        model.objects.push(object);
+    }
+    Ok(model)
+}
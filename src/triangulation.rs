@@ -0,0 +1,142 @@
+use straal::Vec3n;
+
+//Tolerance for the cross products relative to the squared size of the polygon, as they grow with its area
+const RELATIVE_EPSILON: f32 = 1e-6;
+
+//Splits a polygon into triangles, the result holds indices into the given corner list.
+//Convex polygons are fanned, concave ones are ear clipped on the polygon's best-fit plane.
+//The winding of the produced triangles matches the winding of the polygon.
+pub(crate) fn triangulate(corners: &[Vec3n]) -> Vec<[usize; 3]> {
+    match corners.len() {
+        0..=2 => Vec::new(),
+        3 => vec![[0, 1, 2]],
+        count => {
+            let epsilon = RELATIVE_EPSILON * squared_size(corners);
+            let points = match project_to_best_fit_plane(corners, epsilon) {
+                Some(points) => points,
+                //Degenerate polygon without a usable plane, there is nothing better to do than fanning
+                None => return fan(count),
+            };
+            if is_convex(&points, epsilon) {
+                fan(count)
+            } else {
+                ear_clip(&points, epsilon)
+            }
+        }
+    }
+}
+
+fn fan(count: usize) -> Vec<[usize; 3]> {
+    (1..count - 1).map(|i| [0, i, i + 1]).collect()
+}
+
+//The squared diagonal of the bounding box
+fn squared_size(corners: &[Vec3n]) -> f32 {
+    let mut min = corners[0];
+    let mut max = corners[0];
+    for c in corners {
+        min = Vec3n::new(min.x.min(c.x), min.y.min(c.y), min.z.min(c.z));
+        max = Vec3n::new(max.x.max(c.x), max.y.max(c.y), max.z.max(c.z));
+    }
+    let diagonal = max - min;
+    diagonal.dot(diagonal)
+}
+
+//Uses Newell's method to find the plane normal, which is robust against slightly non-planar polygons
+fn best_fit_normal(corners: &[Vec3n]) -> Vec3n {
+    let mut normal = Vec3n::zero();
+    for i in 0..corners.len() {
+        let current = corners[i];
+        let next = corners[(i + 1) % corners.len()];
+        normal += Vec3n::new((current.y - next.y) * (current.z + next.z),
+                             (current.z - next.z) * (current.x + next.x),
+                             (current.x - next.x) * (current.y + next.y));
+    }
+    normal
+}
+
+//Projects the corners on a 2D basis (u, v) for which (u, v, normal) is right handed,
+//so polygons that wind counter clockwise around their normal stay counter clockwise in 2D.
+//The first corner is the origin, which keeps the precision for polygons far away from the model's origin
+fn project_to_best_fit_plane(corners: &[Vec3n], epsilon: f32) -> Option<Vec<(f32, f32)>> {
+    let normal = best_fit_normal(corners);
+    //Newell's normal is twice the area of the polygon long
+    if normal.dot(normal).sqrt() <= epsilon {
+        return None;
+    }
+    let n = normal.normalized();
+
+    //Start from the axis that is the least aligned with the normal to avoid a degenerate basis
+    let axis = if n.x.abs() <= n.y.abs() && n.x.abs() <= n.z.abs() {
+        Vec3n::new(1.0, 0.0, 0.0)
+    } else if n.y.abs() <= n.z.abs() {
+        Vec3n::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3n::new(0.0, 0.0, 1.0)
+    };
+    let u = axis.cross(n).normalized();
+    let v = n.cross(u);
+
+    let origin = corners[0];
+    Some(corners.iter()
+        .map(|&c| {
+            let c = c - origin;
+            (c.dot(u), c.dot(v))
+        })
+        .collect())
+}
+
+fn is_convex(points: &[(f32, f32)], epsilon: f32) -> bool {
+    let count = points.len();
+    (0..count).all(|i| {
+        let prev = points[(i + count - 1) % count];
+        let next = points[(i + 1) % count];
+        corner_cross(prev, points[i], next) >= -epsilon
+    })
+}
+
+fn ear_clip(points: &[(f32, f32)], epsilon: f32) -> Vec<[usize; 3]> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let prev = remaining[(i + count - 1) % count];
+            let current = remaining[i];
+            let next = remaining[(i + 1) % count];
+            if corner_cross(points[prev], points[current], points[next]) <= epsilon {
+                return false;
+            }
+            !remaining.iter()
+                .filter(|&&j| j != prev && j != current && j != next)
+                .any(|&j| point_in_triangle(points[j], points[prev], points[current], points[next]))
+        });
+
+        match ear {
+            Some(i) => {
+                triangles.push([remaining[(i + count - 1) % count], remaining[i], remaining[(i + 1) % count]]);
+                remaining.remove(i);
+            }
+            None => {
+                //Self intersecting or otherwise broken polygon, fan whatever is left so no face gets lost
+                for i in 1..count - 1 {
+                    triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+                }
+                return triangles;
+            }
+        }
+    }
+
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+//Positive when the corner at b turns counter clockwise
+fn corner_cross(a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (c.1 - b.1) - (b.1 - a.1) * (c.0 - b.0)
+}
+
+fn point_in_triangle(p: (f32, f32), a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> bool {
+    corner_cross(a, b, p) >= 0.0 && corner_cross(b, c, p) >= 0.0 && corner_cross(c, a, p) >= 0.0
+}
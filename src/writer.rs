@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use straal::Vec3n;
+
+use crate::models::*;
+use crate::mtl::{Material, TextureMap, TextureOptions};
+use crate::text::format_float;
+
+impl ObjModel {
+    //Writes the model as OBJ, numbers get at most `precision` decimals.
+    //Attributes shared by several vertices are written once, so vertices that were only split for their normals
+    //share their position again. The material library is referenced with mtllib but not written
+    pub fn write_to<W: Write>(&self, mut writer: W, precision: usize, material_library: Option<&str>) -> io::Result<()> {
+        let float = |value: f32| format_float(value, precision);
+
+        if let Some(library) = material_library {
+            writeln!(writer, "mtllib {}", library)?;
+        }
+
+        let (positions, position_indices) = deduplicate(self.vertices.len(), |i| {
+            let color = self.colors.get(i).map(|c| bits(&[c.color.x, c.color.y, c.color.z, c.color.w]));
+            (bits(&vec3_components(self.vertices[i].position)), color)
+        });
+        for &i in &positions {
+            let p = self.vertices[i].position;
+            write!(writer, "v {} {} {}", float(p.x), float(p.y), float(p.z))?;
+            if let Some(color) = self.colors.get(i) {
+                let c = color.color;
+                write!(writer, " {} {} {}", float(c.x), float(c.y), float(c.z))?;
+                if c.w != 1.0 {
+                    write!(writer, " {}", float(c.w))?;
+                }
+            }
+            writeln!(writer)?;
+        }
+
+        let (tex_coords, tex_coord_indices) = deduplicate(self.tex_coords.len(), |i| {
+            let uv = self.tex_coords[i].tex_coords;
+            bits(&[uv.x, uv.y])
+        });
+        for &i in &tex_coords {
+            let uv = self.tex_coords[i].tex_coords;
+            writeln!(writer, "vt {} {}", float(uv.x), float(uv.y))?;
+        }
+
+        let (normals, normal_indices) = deduplicate(self.normals.len(), |i| bits(&vec3_components(self.normals[i].normal)));
+        for &i in &normals {
+            let n = self.normals[i].normal;
+            writeln!(writer, "vn {} {} {}", float(n.x), float(n.y), float(n.z))?;
+        }
+
+        //Objects, groups and materials are stored as index ranges, OBJ needs them as state changes between faces
+        let triangle_count = self.indices.len() / 3;
+        let mut triangle_objects: Vec<Option<usize>> = vec![None; triangle_count];
+        for (i, object) in self.objects.iter().enumerate() {
+            for range in &object.index_ranges {
+                triangle_objects[range.start / 3..range.end / 3].iter_mut().for_each(|o| *o = Some(i));
+            }
+        }
+        let mut triangle_groups: Vec<Vec<usize>> = vec![Vec::new(); triangle_count];
+        for (i, group) in self.groups.iter().enumerate() {
+            for range in &group.index_ranges {
+                triangle_groups[range.start / 3..range.end / 3].iter_mut().for_each(|g| g.push(i));
+            }
+        }
+        let mut triangle_materials: Vec<Option<&str>> = vec![None; triangle_count];
+        for sub_mesh in &self.sub_meshes {
+            let range = sub_mesh.index_range.start / 3..sub_mesh.index_range.end / 3;
+            triangle_materials[range].iter_mut().for_each(|m| *m = sub_mesh.material_name.as_deref());
+        }
+
+        //OBJ has no way to leave an object, group or material. Faces without one are written after the statements
+        //of the faces before them, so they join those on reload. A bare g would add a group named default instead
+        let mut current_object = None;
+        let mut current_groups: &[usize] = &[];
+        let mut current_material = None;
+        //The first s statement is always written, so a file of only flat faces still reloads as flat
+        let mut current_smoothing_group = None;
+        for triangle in 0..triangle_count {
+            if triangle_objects[triangle].is_some() && triangle_objects[triangle] != current_object {
+                current_object = triangle_objects[triangle];
+                writeln!(writer, "o {}", self.objects[current_object.unwrap()].name)?;
+            }
+            if !triangle_groups[triangle].is_empty() && triangle_groups[triangle].as_slice() != current_groups {
+                current_groups = &triangle_groups[triangle];
+                let names: Vec<&str> = current_groups.iter().map(|&g| self.groups[g].name.as_str()).collect();
+                writeln!(writer, "g {}", names.join(" "))?;
+            }
+            if triangle_materials[triangle].is_some() && triangle_materials[triangle] != current_material {
+                current_material = triangle_materials[triangle];
+                writeln!(writer, "usemtl {}", current_material.unwrap())?;
+            }
+            if let Some(&smoothing_group) = self.smoothing_groups.get(triangle) {
+                if current_smoothing_group != Some(smoothing_group) {
+                    current_smoothing_group = Some(smoothing_group);
+                    match smoothing_group {
+                        0 => writeln!(writer, "s off")?,
+                        group => writeln!(writer, "s {}", group)?,
+                    }
+                }
+            }
+
+            write!(writer, "f")?;
+            for &index in &self.indices[triangle * 3..triangle * 3 + 3] {
+                let i = index as usize;
+                write!(writer, " {}", position_indices[i] + 1)?;
+                match (tex_coord_indices.get(i), normal_indices.get(i)) {
+                    (Some(uv), Some(n)) => write!(writer, "/{}/{}", uv + 1, n + 1)?,
+                    (Some(uv), None) => write!(writer, "/{}", uv + 1)?,
+                    (None, Some(n)) => write!(writer, "//{}", n + 1)?,
+                    (None, None) => {}
+                }
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    //Writes the model to an OBJ file, the materials go into a library next to it with the same name and an .mtl extension
+    pub fn save_to_file(&self, file_path: &str, precision: usize) -> io::Result<()> {
+        let path = Path::new(file_path);
+        let material_library = if self.materials.is_empty() {
+            None
+        } else {
+            let library_path = path.with_extension("mtl");
+            Material::save_library_to_file(&self.materials, &library_path.to_string_lossy(), precision)?;
+            library_path.file_name().map(|name| name.to_string_lossy().into_owned())
+        };
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer, precision, material_library.as_deref())?;
+        writer.flush()
+    }
+}
+
+impl Material {
+    //Writes the materials as an MTL library, numbers get at most `precision` decimals
+    pub fn write_library_to<W: Write>(materials: &[Material], mut writer: W, precision: usize) -> io::Result<()> {
+        let float = |value: f32| format_float(value, precision);
+
+        for (i, material) in materials.iter().enumerate() {
+            if i > 0 {
+                writeln!(writer)?;
+            }
+            writeln!(writer, "newmtl {}", material.name)?;
+
+            let colors = [
+                ("Ka", material.ambient),
+                ("Kd", material.diffuse),
+                ("Ks", material.specular),
+                ("Ke", material.emissive),
+                ("Tf", material.transmission_filter),
+            ];
+            for &(keyword, color) in colors.iter() {
+                if let Some(c) = color {
+                    writeln!(writer, "{} {} {} {}", keyword, float(c.x), float(c.y), float(c.z))?;
+                }
+            }
+
+            let scalars = [
+                ("Ns", material.shininess),
+                ("Ni", material.optical_density),
+                ("d", material.dissolve),
+                ("Pr", material.roughness),
+                ("Pm", material.metallic),
+                ("Ps", material.sheen),
+                ("Pc", material.clearcoat_thickness),
+                ("Pcr", material.clearcoat_roughness),
+                ("aniso", material.anisotropy),
+                ("anisor", material.anisotropy_rotation),
+            ];
+            for &(keyword, scalar) in scalars.iter() {
+                if let Some(value) = scalar {
+                    writeln!(writer, "{} {}", keyword, float(value))?;
+                }
+            }
+            if let Some(illumination_model) = material.illumination_model {
+                writeln!(writer, "illum {}", illumination_model)?;
+            }
+
+            let maps = [
+                ("map_Ka", &material.ambient_map),
+                ("map_Kd", &material.diffuse_map),
+                ("map_Ks", &material.specular_map),
+                ("map_Ke", &material.emissive_map),
+                ("map_Ns", &material.shininess_map),
+                ("map_d", &material.dissolve_map),
+                ("map_Bump", &material.bump_map),
+                ("norm", &material.normal_map),
+                ("disp", &material.displacement_map),
+                ("decal", &material.decal_map),
+                ("refl", &material.reflection_map),
+                ("map_Pr", &material.roughness_map),
+                ("map_Pm", &material.metallic_map),
+                ("map_Ps", &material.sheen_map),
+            ];
+            for &(keyword, map) in maps.iter() {
+                if let Some(map) = map {
+                    write_texture_map(&mut writer, keyword, map, precision)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save_library_to_file(materials: &[Material], file_path: &str, precision: usize) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(file_path)?);
+        Material::write_library_to(materials, &mut writer, precision)?;
+        writer.flush()
+    }
+}
+
+//Only options that differ from their defaults are written
+fn write_texture_map<W: Write>(writer: &mut W, keyword: &str, map: &TextureMap, precision: usize) -> io::Result<()> {
+    let float = |value: f32| format_float(value, precision);
+    let switch = |value: bool| if value { "on" } else { "off" };
+    let defaults = TextureOptions::default();
+    let options = &map.options;
+
+    write!(writer, "{}", keyword)?;
+    if options.blend_u != defaults.blend_u {
+        write!(writer, " -blendu {}", switch(options.blend_u))?;
+    }
+    if options.blend_v != defaults.blend_v {
+        write!(writer, " -blendv {}", switch(options.blend_v))?;
+    }
+    if options.color_correction != defaults.color_correction {
+        write!(writer, " -cc {}", switch(options.color_correction))?;
+    }
+    if options.clamp != defaults.clamp {
+        write!(writer, " -clamp {}", switch(options.clamp))?;
+    }
+    if let Some(boost) = options.boost {
+        write!(writer, " -boost {}", float(boost))?;
+    }
+    if options.range != defaults.range {
+        write!(writer, " -mm {} {}", float(options.range.0), float(options.range.1))?;
+    }
+    let vectors = [("-o", options.offset, defaults.offset), ("-s", options.scale, defaults.scale), ("-t", options.turbulence, defaults.turbulence)];
+    for &(option, value, default) in vectors.iter() {
+        if vec3_components(value) != vec3_components(default) {
+            write!(writer, " {} {} {} {}", option, float(value.x), float(value.y), float(value.z))?;
+        }
+    }
+    if let Some(resolution) = options.resolution {
+        write!(writer, " -texres {}", resolution)?;
+    }
+    if options.bump_multiplier != defaults.bump_multiplier {
+        write!(writer, " -bm {}", float(options.bump_multiplier))?;
+    }
+    if let Some(channel) = &options.channel {
+        write!(writer, " -imfchan {}", channel)?;
+    }
+    if let Some(reflection_type) = &options.reflection_type {
+        write!(writer, " -type {}", reflection_type)?;
+    }
+    writeln!(writer, " {}", map.path)
+}
+
+//Gives every distinct key an index in order of first use.
+//Returns the first element with each key, and the index of the key of every element
+fn deduplicate<K: Hash + Eq, F: Fn(usize) -> K>(count: usize, key: F) -> (Vec<usize>, Vec<usize>) {
+    let mut mapped_keys: HashMap<K, usize> = HashMap::with_capacity(count);
+    let mut unique = Vec::new();
+    let indices = (0..count).map(|i| {
+        *mapped_keys.entry(key(i)).or_insert_with(|| {
+            unique.push(i);
+            unique.len() - 1
+        })
+    }).collect();
+    (unique, indices)
+}
+
+//Floats are not hashable, their bit patterns are
+fn bits(values: &[f32]) -> Vec<u32> {
+    values.iter().map(|v| v.to_bits()).collect()
+}
+
+fn vec3_components(v: Vec3n) -> [f32; 3] {
+    [v.x, v.y, v.z]
+}